@@ -81,7 +81,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let num_reps = env::args()
         .nth(1)
-        .ok_or_else(|| "Number of loop repetitions required")?
+        .ok_or("Number of loop repetitions required")?
         .parse::<usize>()?;
 
     for _ in 0..num_reps {
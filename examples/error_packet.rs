@@ -13,7 +13,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let error = sock.resolve_nl_mcast_group("not_a", "group");
     match error {
         Ok(_) => panic!("Should not succeed"),
-        Err(RouterError::Nlmsgerr(e)) => {
+        Err(RouterError::Nlmsgerr(e, _)) => {
             // Should be packet that caused error
             println!("{e:?}");
         }
@@ -80,7 +80,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Some(Ok(msgs)) => {
             println!("msgs: {msgs:?}");
         }
-        Some(Err(RouterError::Nlmsgerr(e))) => {
+        Some(Err(RouterError::Nlmsgerr(e, _))) => {
             println!("msg err: {e:?}");
             println!(
                 "unix error: {:?}",
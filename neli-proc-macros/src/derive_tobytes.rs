@@ -15,7 +15,7 @@ pub fn impl_tobytes_struct(is: ItemStruct) -> TokenStream2 {
     if field_names.is_empty() {
         return quote! {
             impl neli::ToBytes for #struct_name {
-                fn to_bytes(&self, _: &mut std::io::Cursor<Vec<u8>>) -> Result<(), neli::err::SerError> {
+                fn to_bytes(&self, _: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), neli::err::SerError> {
                     Ok(())
                 }
             }
@@ -32,7 +32,7 @@ pub fn impl_tobytes_struct(is: ItemStruct) -> TokenStream2 {
 
     quote! {
         impl#generics neli::ToBytes for #struct_name#generics_without_bounds {
-            fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), neli::err::SerError> {
+            fn to_bytes(&self, buffer: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), neli::err::SerError> {
                 #( <#field_types as neli::ToBytes>::to_bytes(&self.#field_names, buffer)?; )*
                 #padding
                 Ok(())
@@ -90,7 +90,7 @@ pub fn impl_tobytes_enum(ie: ItemEnum) -> TokenStream2 {
     );
     quote! {
         impl#generics neli::ToBytes for #enum_name#generics_without_bounds where #( #trait_bounds ),* {
-            fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), neli::err::SerError> {
+            fn to_bytes(&self, buffer: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), neli::err::SerError> {
                 match self {
                     #(#arms)*
                 }
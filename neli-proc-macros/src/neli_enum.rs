@@ -114,7 +114,7 @@ pub fn generate_neli_enum(mut enm: ItemEnum, meta: Meta) -> TokenStream2 {
         }
 
         impl neli::ToBytes for #enum_name {
-            fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), neli::err::SerError> {
+            fn to_bytes(&self, buffer: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), neli::err::SerError> {
                 let bin_rep: #ty = self.into();
                 bin_rep.to_bytes(buffer)
             }
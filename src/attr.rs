@@ -9,9 +9,15 @@
 //! [`Nlattr`][crate::genl::Nlattr] types in the
 //! `rtnl.rs` and `genl.rs` modules respectively.
 
-use std::{io::Cursor, slice::Iter};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io::Cursor,
+    slice::Iter,
+};
 
 use crate::{
+    consts::alignto,
     err::{DeError, SerError},
     types::Buffer,
     FromBytes, FromBytesWithInput, FromBytesWithInputBorrowed, Size, ToBytes,
@@ -109,3 +115,140 @@ where
         }
     }
 }
+
+/// Length of an attribute header.
+const NLA_HDRLEN: usize = 4;
+/// Mask removing the nested and byte order flags from an attribute type.
+const NLA_TYPE_MASK: u16 = !(3 << 14);
+
+/// Names of the attributes that can appear in a message, used to describe the
+/// attribute an extended ACK points to.
+///
+/// Attributes that are not in the schema are named by their numeric type.
+///
+/// ```
+/// use neli::{
+///     attr::AttrSchema,
+///     consts::rtnl::{Ifla, IflaInfo, IflaVlan},
+/// };
+///
+/// let schema = AttrSchema::new()
+///     .attr(Ifla::Ifname, "IFLA_IFNAME")
+///     .nested(
+///         Ifla::Linkinfo,
+///         "IFLA_LINKINFO",
+///         AttrSchema::new().attr(IflaInfo::Kind, "IFLA_INFO_KIND").nested(
+///             IflaInfo::Data,
+///             "IFLA_INFO_DATA",
+///             AttrSchema::new().attr(IflaVlan::Id, "IFLA_VLAN_ID"),
+///         ),
+///     );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttrSchema {
+    attrs: HashMap<u16, (String, AttrSchema)>,
+}
+
+impl AttrSchema {
+    /// Create an empty schema.
+    pub fn new() -> Self {
+        AttrSchema::default()
+    }
+
+    /// Name the attribute type `ty`.
+    pub fn attr<T>(self, ty: T, name: &str) -> Self
+    where
+        T: Into<u16>,
+    {
+        self.nested(ty, name, AttrSchema::new())
+    }
+
+    /// Name the attribute type `ty` containing the nested attributes described
+    /// by `schema`.
+    pub fn nested<T>(mut self, ty: T, name: &str, schema: AttrSchema) -> Self
+    where
+        T: Into<u16>,
+    {
+        self.attrs.insert(ty.into(), (name.to_string(), schema));
+        self
+    }
+
+    /// Resolve `offset` in the serialized attributes `attrs` to the path of the
+    /// innermost attribute containing it.
+    ///
+    /// Returns the path along with the schema of the attributes nested in the
+    /// last attribute of the path, or [`None`] if no attribute contains `offset`.
+    pub(crate) fn resolve(
+        &self,
+        mut attrs: &[u8],
+        mut offset: usize,
+    ) -> Option<(AttrPath, Option<&Self>)> {
+        let mut path = AttrPath::default();
+        let mut schema = Some(self);
+        'nested: loop {
+            let mut pos = 0;
+            while pos + NLA_HDRLEN <= attrs.len() {
+                let len = usize::from(u16::from_ne_bytes([attrs[pos], attrs[pos + 1]]));
+                let ty = u16::from_ne_bytes([attrs[pos + 2], attrs[pos + 3]]) & NLA_TYPE_MASK;
+                if len < NLA_HDRLEN || pos + len > attrs.len() {
+                    break;
+                }
+                if (pos..pos + len).contains(&offset) {
+                    let entry = schema.and_then(|s| s.attrs.get(&ty));
+                    path.push(attr_name(entry, ty));
+                    schema = entry.map(|(_, nested)| nested);
+                    if offset < pos + NLA_HDRLEN {
+                        return Some((path, schema));
+                    }
+                    attrs = &attrs[pos + NLA_HDRLEN..pos + len];
+                    offset -= pos + NLA_HDRLEN;
+                    continue 'nested;
+                }
+                pos += alignto(len);
+            }
+            // The offset points into the payload of the last attribute in the path.
+            return if path.0.is_empty() {
+                None
+            } else {
+                Some((path, schema))
+            };
+        }
+    }
+
+    /// Get the name of the attribute type `ty`.
+    pub(crate) fn name(&self, ty: u16) -> String {
+        attr_name(self.attrs.get(&ty), ty)
+    }
+}
+
+fn attr_name(entry: Option<&(String, AttrSchema)>, ty: u16) -> String {
+    entry
+        .map(|(name, _)| name.clone())
+        .unwrap_or_else(|| ty.to_string())
+}
+
+/// Names of the nested attributes leading to an attribute, outermost first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttrPath(Vec<String>);
+
+impl AttrPath {
+    /// Create a path from the names of the attributes, outermost first.
+    pub fn new(names: Vec<String>) -> Self {
+        AttrPath(names)
+    }
+
+    /// Get the names of the attributes in the path.
+    pub fn names(&self) -> &[String] {
+        &self.0
+    }
+
+    pub(crate) fn push(&mut self, name: String) {
+        self.0.push(name);
+    }
+}
+
+impl Display for AttrPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join("/"))
+    }
+}
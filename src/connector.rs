@@ -18,7 +18,7 @@
 //!
 //! This design allows both high-level ergonomic handling of proc events and low-level manual parsing for custom needs.
 
-use std::{io::Cursor, io::Read};
+use std::io::{Cursor, Read, Seek, Write};
 
 use derive_builder::{Builder, UninitializedFieldError};
 use getset::Getters;
@@ -213,7 +213,7 @@ impl From<&ProcEvent> for ProcEventType {
 }
 
 impl ToBytes for ProcEventHeader {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         ProcEventType::from(&self.event).to_bytes(buffer)?;
         self.cpu.to_bytes(buffer)?;
         self.timestamp_ns.to_bytes(buffer)?;
@@ -1,4 +1,7 @@
-use std::{io::Cursor, mem::size_of};
+use std::{
+    io::{Cursor, Seek, Write},
+    mem::size_of,
+};
 
 use neli_proc_macros::neli_enum;
 
@@ -6,7 +9,7 @@ use crate::{
     self as neli,
     consts::{
         netfilter::{NfLogAttr, NfLogCfg},
-        nl::NlmsgerrAttr,
+        nl::{NlPolicyTypeAttr, NlmsgerrAttr},
     },
     err::{DeError, SerError},
     FromBytes, Size, ToBytes, TypeSize,
@@ -36,6 +39,7 @@ pub enum CtrlCmd {
     NewmcastGrp = libc::CTRL_CMD_NEWMCAST_GRP as u8,
     DelmcastGrp = libc::CTRL_CMD_DELMCAST_GRP as u8,
     GetmcastGrp = libc::CTRL_CMD_GETMCAST_GRP as u8,
+    Getpolicy = 10,
 }
 
 impl_trait!(
@@ -48,10 +52,13 @@ impl_trait!(
     pub NlAttrTypeWrapper,
     CtrlAttr,
     CtrlAttrMcastGrp,
+    CtrlAttrOp,
+    CtrlAttrPolicy,
     NfLogAttr,
     NfLogCfg,
     Index,
     NlmsgerrAttr,
+    NlPolicyTypeAttr,
 );
 
 /// Values for `nla_type` in [`Nlattr`][crate::genl::Nlattr]
@@ -65,6 +72,9 @@ pub enum CtrlAttr {
     Maxattr = libc::CTRL_ATTR_MAXATTR as u16,
     Ops = libc::CTRL_ATTR_OPS as u16,
     McastGroups = libc::CTRL_ATTR_MCAST_GROUPS as u16,
+    Policy = 8,
+    OpPolicy = 9,
+    Op = 10,
 }
 
 /// Values for `nla_type` in [`Nlattr`][crate::genl::Nlattr]
@@ -75,6 +85,43 @@ pub enum CtrlAttrMcastGrp {
     Id = libc::CTRL_ATTR_MCAST_GRP_ID as u16,
 }
 
+/// Values for `nla_type` in [`Nlattr`][crate::genl::Nlattr] nested in an
+/// operation of [`CtrlAttr::Ops`]
+#[neli_enum(serialized_type = "u16")]
+pub enum CtrlAttrOp {
+    Unspec = libc::CTRL_ATTR_OP_UNSPEC as u16,
+    Id = libc::CTRL_ATTR_OP_ID as u16,
+    Flags = libc::CTRL_ATTR_OP_FLAGS as u16,
+}
+
+impl_flags!(
+    /// Flags of a generic netlink operation in [`CtrlAttrOp::Flags`]
+    pub GenlOpFlags: u32 {
+        /// The operation requires `CAP_NET_ADMIN`
+        ADMIN_PERM = libc::GENL_ADMIN_PERM as u32,
+        /// The operation handles `do` requests
+        CMD_CAP_DO = libc::GENL_CMD_CAP_DO as u32,
+        /// The operation handles `dump` requests
+        CMD_CAP_DUMP = libc::GENL_CMD_CAP_DUMP as u32,
+        /// The operation validates its attributes against a policy
+        CMD_CAP_HASPOL = libc::GENL_CMD_CAP_HASPOL as u32,
+        /// The operation requires `CAP_NET_ADMIN` in the user namespace of the
+        /// network namespace
+        UNS_ADMIN_PERM = 0x10,
+    }
+);
+
+/// Values for `nla_type` in [`Nlattr`][crate::genl::Nlattr] nested in an
+/// operation of [`CtrlAttr::OpPolicy`]
+#[neli_enum(serialized_type = "u16")]
+pub enum CtrlAttrPolicy {
+    Unspec = 0,
+    /// Index of the policy used for `do` requests (u32)
+    Do = 1,
+    /// Index of the policy used for `dump` requests (u32)
+    Dump = 2,
+}
+
 /// Type representing attribute list types as indices
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Size)]
 pub struct Index(u16);
@@ -92,7 +139,7 @@ impl TypeSize for Index {
 }
 
 impl ToBytes for Index {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         self.0.to_bytes(buffer)
     }
 }
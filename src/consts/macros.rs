@@ -122,7 +122,7 @@ macro_rules! impl_trait {
         }
 
         impl $crate::ToBytes for $wrapper_type {
-            fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), $crate::err::SerError> {
+            fn to_bytes(&self, buffer: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), $crate::err::SerError> {
                 Ok(match self {
                     $(
                         $wrapper_type::$const_enum(val) => val.to_bytes(buffer)?,
@@ -77,4 +77,63 @@ pub enum NlmsgerrAttr {
     Cookie = 3,
     /// Policy for a rejected attribute
     Policy = 4,
+    /// Type of a missing required attribute (u32)
+    MissType = 5,
+    /// Offset of the nest where an attribute was missing, counting
+    /// from the beginning of the header (u32)
+    MissNest = 6,
+}
+
+/// Attributes describing the policy of an attribute, found in
+/// [`NlmsgerrAttr::Policy`] and generic netlink policy dumps
+#[neli_enum(serialized_type = "u16")]
+pub enum NlPolicyTypeAttr {
+    Unspec = 0,
+    /// Type of the attribute (u32, [`NlAttrKind`])
+    Type = 1,
+    /// Minimum value of a signed integer attribute (s64)
+    MinValueS = 2,
+    /// Maximum value of a signed integer attribute (s64)
+    MaxValueS = 3,
+    /// Minimum value of an unsigned integer attribute (u64)
+    MinValueU = 4,
+    /// Maximum value of an unsigned integer attribute (u64)
+    MaxValueU = 5,
+    /// Minimum length of a binary attribute (u32)
+    MinLength = 6,
+    /// Maximum length of a binary attribute (u32)
+    MaxLength = 7,
+    /// Index of the policy of a nested attribute (u32)
+    PolicyIdx = 8,
+    /// Maximum attribute type of the policy of a nested attribute (u32)
+    PolicyMaxtype = 9,
+    /// Valid bits of a bitfield32 attribute (u32)
+    Bitfield32Mask = 10,
+    /// Padding for 64 bit attributes
+    Pad = 11,
+    /// Valid bits of an unsigned integer attribute (u64)
+    Mask = 12,
+}
+
+/// Types of attributes reported in [`NlPolicyTypeAttr::Type`]
+#[neli_enum(serialized_type = "u32")]
+pub enum NlAttrKind {
+    Invalid = 0,
+    Flag = 1,
+    U8 = 2,
+    U16 = 3,
+    U32 = 4,
+    U64 = 5,
+    S8 = 6,
+    S16 = 7,
+    S32 = 8,
+    S64 = 9,
+    Binary = 10,
+    String = 11,
+    NulString = 12,
+    Nested = 13,
+    NestedArray = 14,
+    Bitfield32 = 15,
+    Sint = 16,
+    Uint = 17,
 }
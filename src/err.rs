@@ -27,16 +27,24 @@ use std::{
 use derive_builder::{Builder, UninitializedFieldError};
 use getset::Getters;
 
+#[cfg(any(feature = "sync", feature = "async-core"))]
+use crate::router::RouterConfigBuilderError;
 use crate::{
     self as neli,
-    consts::nl::{NlType, NlmF, NlmsgerrAttr},
+    attr::{AttrPath, AttrSchema, Attribute},
+    consts::{
+        alignto,
+        nl::{NlType, NlmF, NlmsgerrAttr},
+    },
     genl::{AttrTypeBuilderError, GenlmsghdrBuilderError, NlattrBuilderError},
     nl::{Nlmsghdr, NlmsghdrBuilderError},
+    policy::AttrPolicy,
     rtnl::{
         IfaddrmsgBuilderError, IfinfomsgBuilderError, NdaCacheinfoBuilderError, NdmsgBuilderError,
         RtattrBuilderError, RtgenmsgBuilderError, RtmsgBuilderError, TcmsgBuilderError,
     },
     types::{Buffer, GenlBuffer},
+    utils::BufferPoolConfigBuilderError,
     FromBytes, FromBytesWithInput, Header, Size, ToBytes, TypeSize,
 };
 
@@ -183,9 +191,36 @@ pub struct Nlmsgerr<M> {
     ext_ack: GenlBuffer<NlmsgerrAttr, Buffer>,
 }
 
+impl<M> Nlmsgerr<M> {
+    /// Parse the extended ACK attributes.
+    pub fn get_ext_ack(&self) -> Result<ExtAck, DeError> {
+        ExtAck::parse(&self.ext_ack)
+    }
+
+    fn fmt_with_attr_path(&self, f: &mut fmt::Formatter, path: Option<&AttrPath>) -> fmt::Result {
+        let err = io::Error::from_raw_os_error(-self.error);
+        let ext_ack = self.get_ext_ack().unwrap_or_default();
+        let msg = ext_ack.msg().clone().unwrap_or_else(|| err.to_string());
+        match path {
+            Some(path) if ext_ack.miss_type().is_some() => {
+                write!(f, "missing attribute {path}: {msg}")?
+            }
+            Some(path) => write!(f, "invalid attribute {path}: {msg}")?,
+            None => match ext_ack.msg() {
+                Some(ext_msg) => write!(f, "{err}: {ext_msg}")?,
+                None => write!(f, "{err}")?,
+            },
+        }
+        if let Some(policy) = ext_ack.policy() {
+            write!(f, " (expected {policy})")?;
+        }
+        Ok(())
+    }
+}
+
 impl<M> Display for Nlmsgerr<M> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", io::Error::from_raw_os_error(-self.error))
+        self.fmt_with_attr_path(f, None)
     }
 }
 
@@ -201,6 +236,7 @@ impl Nlmsgerr<NlmsghdrErr<u16, Buffer>> {
         Ok(NlmsgerrBuilder::default()
             .error(self.error)
             .nlmsg(self.nlmsg.to_typed()?)
+            .ext_ack(self.ext_ack)
             .build()?)
     }
 }
@@ -214,10 +250,90 @@ impl Nlmsgerr<NlmsghdrAck<u16>> {
         Ok(NlmsgerrBuilder::default()
             .error(self.error)
             .nlmsg(self.nlmsg.to_typed()?)
+            .ext_ack(self.ext_ack)
             .build()?)
     }
 }
 
+/// Typed contents of the extended ACK attributes of an [`Nlmsgerr`].
+#[derive(Getters, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtAck {
+    /// Error message
+    #[getset(get = "pub")]
+    msg: Option<String>,
+    /// Offset of the invalid attribute in the request, counting from the
+    /// beginning of the netlink header
+    #[getset(get = "pub")]
+    offset: Option<u32>,
+    /// Subsystem specific cookie identifying a created object or operation
+    #[getset(get = "pub")]
+    cookie: Option<Buffer>,
+    /// Policy violated by the rejected attribute
+    #[getset(get = "pub")]
+    policy: Option<AttrPolicy>,
+    /// Type of a missing required attribute
+    #[getset(get = "pub")]
+    miss_type: Option<u16>,
+    /// Offset of the nest the required attribute is missing from, counting from
+    /// the beginning of the netlink header
+    #[getset(get = "pub")]
+    miss_nest: Option<u32>,
+}
+
+impl ExtAck {
+    /// Parse the extended ACK attributes of an [`Nlmsgerr`].
+    pub fn parse(attrs: &GenlBuffer<NlmsgerrAttr, Buffer>) -> Result<Self, DeError> {
+        let mut ext_ack = ExtAck::default();
+        for attr in attrs.iter() {
+            match attr.nla_type().nla_type() {
+                NlmsgerrAttr::Msg => ext_ack.msg = Some(attr.get_payload_as_with_len()?),
+                NlmsgerrAttr::Offset => ext_ack.offset = Some(attr.get_payload_as()?),
+                NlmsgerrAttr::Cookie => ext_ack.cookie = Some(attr.nla_payload().clone()),
+                NlmsgerrAttr::Policy => {
+                    ext_ack.policy = Some(AttrPolicy::parse(&attr.get_attr_handle()?)?)
+                }
+                NlmsgerrAttr::MissType => {
+                    ext_ack.miss_type = Some(attr.get_payload_as::<u32>()? as u16)
+                }
+                NlmsgerrAttr::MissNest => ext_ack.miss_nest = Some(attr.get_payload_as()?),
+                _ => (),
+            }
+        }
+        Ok(ext_ack)
+    }
+
+    /// Name the attribute this extended ACK points to in `request`, the
+    /// serialized request including the netlink header.
+    ///
+    /// `header_len` is the length of the family header following the netlink
+    /// header, for example [`Ifinfomsg::header_size`][crate::Header::header_size]
+    /// for link requests. For a missing attribute, the path names the nest the
+    /// attribute is missing from followed by the missing attribute.
+    pub fn attr_path(
+        &self,
+        request: &[u8],
+        header_len: usize,
+        schema: &AttrSchema,
+    ) -> Option<AttrPath> {
+        let start = Nlmsghdr::<u16, Buffer>::header_size() + alignto(header_len);
+        let attrs = request.get(start..)?;
+        let resolve = |offset: u32| {
+            (offset as usize)
+                .checked_sub(start)
+                .and_then(|offset| schema.resolve(attrs, offset))
+        };
+        match (self.miss_type, self.miss_nest) {
+            (Some(miss_type), Some(miss_nest)) => {
+                let (mut path, nested) = resolve(miss_nest)?;
+                path.push(nested.map_or_else(|| miss_type.to_string(), |s| s.name(miss_type)));
+                Some(path)
+            }
+            (Some(miss_type), None) => Some(AttrPath::new(vec![schema.name(miss_type)])),
+            (None, _) => self.offset.and_then(resolve).map(|(path, _)| path),
+        }
+    }
+}
+
 #[derive(Debug)]
 #[allow(missing_docs)]
 pub enum BuilderError {
@@ -251,6 +367,11 @@ pub enum BuilderError {
     Rtattr(RtattrBuilderError),
     #[allow(missing_docs)]
     NlmsghdrAck(NlmsghdrAckBuilderError),
+    #[allow(missing_docs)]
+    BufferPoolConfig(BufferPoolConfigBuilderError),
+    #[allow(missing_docs)]
+    #[cfg(any(feature = "sync", feature = "async-core"))]
+    RouterConfig(RouterConfigBuilderError),
 }
 
 impl Error for BuilderError {}
@@ -273,6 +394,9 @@ impl Display for BuilderError {
             BuilderError::Tcmsg(err) => write!(f, "{err}"),
             BuilderError::Rtattr(err) => write!(f, "{err}"),
             BuilderError::NlmsghdrAck(err) => write!(f, "{err}"),
+            BuilderError::BufferPoolConfig(err) => write!(f, "{err}"),
+            #[cfg(any(feature = "sync", feature = "async-core"))]
+            BuilderError::RouterConfig(err) => write!(f, "{err}"),
         }
     }
 }
@@ -367,6 +491,19 @@ impl From<NlmsghdrAckBuilderError> for BuilderError {
     }
 }
 
+impl From<BufferPoolConfigBuilderError> for BuilderError {
+    fn from(e: BufferPoolConfigBuilderError) -> Self {
+        BuilderError::BufferPoolConfig(e)
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+impl From<RouterConfigBuilderError> for BuilderError {
+    fn from(e: RouterConfigBuilderError) -> Self {
+        BuilderError::RouterConfig(e)
+    }
+}
+
 /// Sendable, clonable error that can be sent across channels in the router infrastructure
 /// to provide typed errors to all receivers indicating what went wrong.
 #[derive(Clone, Debug)]
@@ -379,8 +516,10 @@ pub enum RouterError<T, P> {
     De(DeError),
     /// Error from socket infrastructure.
     Socket(SocketError),
-    /// An error packet sent back by netlink.
-    Nlmsgerr(Nlmsgerr<NlmsghdrErr<T, P>>),
+    /// An error packet sent back by netlink along with the path of the
+    /// attribute its extended ACK points to, if it was resolved with an
+    /// [`AttrSchema`] registered for the request.
+    Nlmsgerr(Nlmsgerr<NlmsghdrErr<T, P>>, Option<AttrPath>),
     /// A bad sequence number or PID was received.
     BadSeqOrPid(Nlmsghdr<T, P>),
     /// No ack was received when
@@ -393,6 +532,18 @@ pub enum RouterError<T, P> {
     UnexpectedAck,
     /// A channel has closed and message processing cannot continue.
     ClosedChannel,
+    /// The deadline for receiving a response passed before a message was received.
+    Timeout,
+    /// A dump was still interrupted by concurrent changes after all attempts to
+    /// retrieve a consistent snapshot were exhausted.
+    DumpInterrupted,
+    /// The kernel dropped multicast messages because the socket receive buffer was
+    /// full. Any state derived from multicast notifications should be resynchronized,
+    /// for example with a new dump.
+    ///
+    /// This error is only delivered to the multicast receiver and does not end the
+    /// stream of messages.
+    EventsLost,
 }
 
 impl<T, P> RouterError<T, P> {
@@ -417,11 +568,14 @@ impl RouterError<u16, Buffer> {
             RouterError::Io(kind) => Ok(RouterError::Io(kind)),
             RouterError::De(err) => Ok(RouterError::De(err)),
             RouterError::Socket(err) => Ok(RouterError::Socket(err)),
-            RouterError::Nlmsgerr(err) => Ok(RouterError::Nlmsgerr(err.to_typed()?)),
+            RouterError::Nlmsgerr(err, path) => Ok(RouterError::Nlmsgerr(err.to_typed()?, path)),
             RouterError::BadSeqOrPid(msg) => Ok(RouterError::BadSeqOrPid(msg.to_typed()?)),
             RouterError::NoAck => Ok(RouterError::NoAck),
             RouterError::UnexpectedAck => Ok(RouterError::UnexpectedAck),
             RouterError::ClosedChannel => Ok(RouterError::ClosedChannel),
+            RouterError::Timeout => Ok(RouterError::Timeout),
+            RouterError::DumpInterrupted => Ok(RouterError::DumpInterrupted),
+            RouterError::EventsLost => Ok(RouterError::EventsLost),
         }
     }
 }
@@ -437,9 +591,7 @@ where
             RouterError::Io(kind) => write!(f, "IO error: {kind}"),
             RouterError::De(err) => write!(f, "Deserialization failed: {err}"),
             RouterError::Socket(err) => write!(f, "Socket error: {err}"),
-            RouterError::Nlmsgerr(msg) => {
-                write!(f, "Application error was returned by netlink: {msg:?}")
-            }
+            RouterError::Nlmsgerr(msg, path) => msg.fmt_with_attr_path(f, path.as_ref()),
             RouterError::BadSeqOrPid(msg) => {
                 write!(f, "A bad sequence number or PID was received: {msg:?}")
             }
@@ -448,6 +600,16 @@ where
             RouterError::ClosedChannel => {
                 write!(f, "A channel required for message processing closed")
             }
+            RouterError::Timeout => write!(f, "Timed out waiting for a response"),
+            RouterError::DumpInterrupted => {
+                write!(f, "Dump was interrupted and may be inconsistent")
+            }
+            RouterError::EventsLost => {
+                write!(
+                    f,
+                    "Multicast messages were lost due to a receive buffer overrun"
+                )
+            }
         }
     }
 }
@@ -497,6 +659,13 @@ pub enum SocketError {
     De(DeError),
     /// IO error.
     Io(Arc<io::Error>),
+    /// A received datagram did not fit in the receive buffer and was truncated.
+    Truncated {
+        /// Size of the datagram in bytes.
+        size: usize,
+        /// Size of the receive buffer in bytes.
+        capacity: usize,
+    },
 }
 
 impl From<SerError> for SocketError {
@@ -556,6 +725,12 @@ impl Display for SocketError {
             SocketError::Io(ref err) => {
                 write!(f, "IO error: {err}")
             }
+            SocketError::Truncated { size, capacity } => {
+                write!(
+                    f,
+                    "Datagram of {size} bytes truncated to fit a receive buffer of {capacity} bytes"
+                )
+            }
         }
     }
 }
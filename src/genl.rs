@@ -12,7 +12,7 @@
 //! nested within another, a use case that is instead handled using
 //! [`AttrHandle`].
 
-use std::io::Cursor;
+use std::io::{Cursor, Seek, Write};
 
 use derive_builder::{Builder, UninitializedFieldError};
 use getset::Getters;
@@ -20,7 +20,9 @@ use getset::Getters;
 use crate::{
     self as neli,
     attr::{AttrHandle, Attribute},
-    consts::genl::{Cmd, NlAttrType},
+    consts::genl::{
+        Cmd, CtrlAttr, CtrlAttrMcastGrp, CtrlAttrOp, CtrlCmd, GenlOpFlags, Index, NlAttrType,
+    },
     err::{DeError, SerError},
     types::{Buffer, GenlBuffer},
     FromBytes, FromBytesWithInput, FromBytesWithInputBorrowed, Header, Size, ToBytes, TypeSize,
@@ -156,7 +158,7 @@ impl<T> ToBytes for AttrType<T>
 where
     T: NlAttrType,
 {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         let int: u16 = self.into();
         int.to_bytes(buffer)
     }
@@ -396,3 +398,178 @@ where
         }
     }
 }
+
+/// Operation supported by a generic netlink family.
+#[derive(Getters, Clone, Debug, PartialEq, Eq)]
+pub struct GenlOp {
+    /// Command of the operation
+    #[getset(get = "pub")]
+    id: u32,
+    /// Capabilities and permission requirements of the operation
+    #[getset(get = "pub")]
+    flags: GenlOpFlags,
+}
+
+/// Multicast group of a generic netlink family.
+#[derive(Getters, Clone, Debug, PartialEq, Eq)]
+pub struct GenlMcastGroup {
+    /// Name of the multicast group
+    #[getset(get = "pub")]
+    name: String,
+    /// ID used to join the multicast group
+    #[getset(get = "pub")]
+    id: u32,
+}
+
+/// Description of a generic netlink family as reported by
+/// `CTRL_CMD_GETFAMILY`.
+#[derive(Getters, Clone, Debug, PartialEq, Eq)]
+pub struct GenlFamilyInfo {
+    /// Name of the family
+    #[getset(get = "pub")]
+    name: String,
+    /// Numeric ID used as the netlink message type of the family
+    #[getset(get = "pub")]
+    id: u16,
+    /// Protocol version of the family
+    #[getset(get = "pub")]
+    version: u32,
+    /// Size of the user header of the family
+    #[getset(get = "pub")]
+    hdrsize: u32,
+    /// Maximum attribute type of the family
+    #[getset(get = "pub")]
+    maxattr: u32,
+    /// Operations supported by the family
+    #[getset(get = "pub")]
+    ops: Vec<GenlOp>,
+    /// Multicast groups of the family
+    #[getset(get = "pub")]
+    mcast_groups: Vec<GenlMcastGroup>,
+}
+
+impl GenlFamilyInfo {
+    /// Decode the attributes of a `CTRL_CMD_GETFAMILY` response or a
+    /// `CTRL_CMD_NEWFAMILY` notification.
+    pub fn parse(msg: &Genlmsghdr<CtrlCmd, CtrlAttr>) -> Result<Self, DeError> {
+        let handle = msg.attrs().get_attr_handle();
+        let mut ops = Vec::new();
+        if handle.get_attribute(CtrlAttr::Ops).is_some() {
+            for op in handle.get_nested_attributes::<Index>(CtrlAttr::Ops)?.iter() {
+                let attrs = op.get_attr_handle::<CtrlAttrOp>()?;
+                ops.push(GenlOp {
+                    id: attrs.get_attr_payload_as(CtrlAttrOp::Id)?,
+                    flags: attrs
+                        .get_attr_payload_as(CtrlAttrOp::Flags)
+                        .unwrap_or_else(|_| GenlOpFlags::empty()),
+                });
+            }
+        }
+        let mut mcast_groups = Vec::new();
+        if handle.get_attribute(CtrlAttr::McastGroups).is_some() {
+            for group in handle
+                .get_nested_attributes::<Index>(CtrlAttr::McastGroups)?
+                .iter()
+            {
+                let attrs = group.get_attr_handle::<CtrlAttrMcastGrp>()?;
+                mcast_groups.push(GenlMcastGroup {
+                    name: attrs.get_attr_payload_as_with_len(CtrlAttrMcastGrp::Name)?,
+                    id: attrs.get_attr_payload_as(CtrlAttrMcastGrp::Id)?,
+                });
+            }
+        }
+        Ok(GenlFamilyInfo {
+            name: handle.get_attr_payload_as_with_len(CtrlAttr::FamilyName)?,
+            id: handle.get_attr_payload_as(CtrlAttr::FamilyId)?,
+            version: handle.get_attr_payload_as(CtrlAttr::Version).unwrap_or(0),
+            hdrsize: handle.get_attr_payload_as(CtrlAttr::Hdrsize).unwrap_or(0),
+            maxattr: handle.get_attr_payload_as(CtrlAttr::Maxattr).unwrap_or(0),
+            ops,
+            mcast_groups,
+        })
+    }
+
+    /// Get the operation with the command `cmd`.
+    pub fn op<C>(&self, cmd: C) -> Option<&GenlOp>
+    where
+        C: Into<u8>,
+    {
+        let cmd = u32::from(cmd.into());
+        self.ops.iter().find(|op| op.id == cmd)
+    }
+
+    /// Get the multicast group with the name `name`.
+    pub fn mcast_group(&self, name: &str) -> Option<&GenlMcastGroup> {
+        self.mcast_groups.iter().find(|group| group.name == name)
+    }
+}
+
+/// Change of the generic netlink families announced on the `notify` multicast
+/// group of `nlctrl`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FamilyEvent {
+    /// A family was registered
+    Added(GenlFamilyInfo),
+    /// A family was unregistered
+    Removed(GenlFamilyInfo),
+    /// A multicast group was added to a family
+    GroupAdded {
+        /// Name of the family
+        family_name: String,
+        /// ID of the family
+        family_id: u16,
+        /// Multicast group that was added
+        group: GenlMcastGroup,
+    },
+    /// A multicast group was removed from a family
+    GroupRemoved {
+        /// Name of the family
+        family_name: String,
+        /// ID of the family
+        family_id: u16,
+        /// Multicast group that was removed
+        group: GenlMcastGroup,
+    },
+}
+
+impl FamilyEvent {
+    /// Decode an `nlctrl` notification.
+    ///
+    /// Returns [`None`] for commands that do not announce a change of the
+    /// families.
+    pub fn parse(msg: &Genlmsghdr<CtrlCmd, CtrlAttr>) -> Result<Option<Self>, DeError> {
+        Ok(Some(match msg.cmd() {
+            CtrlCmd::Newfamily => FamilyEvent::Added(GenlFamilyInfo::parse(msg)?),
+            CtrlCmd::Delfamily => FamilyEvent::Removed(GenlFamilyInfo::parse(msg)?),
+            CtrlCmd::NewmcastGrp | CtrlCmd::DelmcastGrp => {
+                let info = GenlFamilyInfo::parse(msg)?;
+                let group = info.mcast_groups.into_iter().next().ok_or_else(|| {
+                    DeError::new("Multicast group notification contains no group")
+                })?;
+                if *msg.cmd() == CtrlCmd::NewmcastGrp {
+                    FamilyEvent::GroupAdded {
+                        family_name: info.name,
+                        family_id: info.id,
+                        group,
+                    }
+                } else {
+                    FamilyEvent::GroupRemoved {
+                        family_name: info.name,
+                        family_id: info.id,
+                        group,
+                    }
+                }
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Get the name of the family the event applies to.
+    pub fn family_name(&self) -> &str {
+        match self {
+            FamilyEvent::Added(info) | FamilyEvent::Removed(info) => info.name(),
+            FamilyEvent::GroupAdded { family_name, .. }
+            | FamilyEvent::GroupRemoved { family_name, .. } => family_name,
+        }
+    }
+}
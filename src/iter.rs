@@ -5,13 +5,15 @@ use std::{io::Cursor, marker::PhantomData};
 use log::trace;
 
 use crate::{
-    consts::nl::NlType, err::SocketError, nl::Nlmsghdr, FromBytes, FromBytesWithInput, Size,
+    consts::nl::NlType, err::SocketError, nl::Nlmsghdr, socket::RecvMetadata, FromBytes,
+    FromBytesWithInput, Size,
 };
 
 /// Iterator over a single buffer received from a [`recv`][crate::socket::NlSocket::recv]
 /// call.
 pub struct NlBufferIter<T, P, B> {
     buffer: Cursor<B>,
+    metadata: Option<RecvMetadata>,
     next_is_none: bool,
     data: PhantomData<(T, P)>,
 }
@@ -20,15 +22,28 @@ impl<T, P, B> NlBufferIter<T, P, B>
 where
     B: AsRef<[u8]>,
 {
-    #[cfg(any(feature = "sync", feature = "async"))]
     pub(crate) fn new(buffer: Cursor<B>) -> Self {
         NlBufferIter {
             buffer,
+            metadata: None,
             next_is_none: false,
             data: PhantomData,
         }
     }
 
+    #[cfg(any(feature = "sync", feature = "async-core"))]
+    pub(crate) fn with_metadata(buffer: Cursor<B>, metadata: RecvMetadata) -> Self {
+        NlBufferIter {
+            metadata: Some(metadata),
+            ..NlBufferIter::new(buffer)
+        }
+    }
+
+    /// Metadata of the datagram that the messages were received in.
+    pub fn metadata(&self) -> Option<&RecvMetadata> {
+        self.metadata.as_ref()
+    }
+
     /// Optional method for parsing messages of varied types in the same buffer. Models
     /// the [`Iterator`] API.
     pub fn next_typed<TT, PP>(&mut self) -> Option<Result<Nlmsghdr<TT, PP>, SocketError>>
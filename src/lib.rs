@@ -18,10 +18,16 @@
 //!   packets.
 //! * `nl` - This is the top level netlink header code that handles
 //!   the header that all netlink messages are encapsulated in.
+//! * `policy` - Attribute policies reported by the kernel in extended ACKs and
+//!   generic netlink policy dumps.
 //! * `router` - High level API handling ACK and PID validation as well as automatic
 //!   sequence number handling.
 //! * `rtnl` - Routing netlink subsystem of the netlink protocol.
+//! * `runtime` - Abstraction over the async runtime used by the asynchronous socket
+//!   and router.
 //! * `socket` - Lower level API for use in sending and receiving messages.
+//! * `testing` - In-process emulation of the kernel side of generic netlink and
+//!   replay of recorded sessions for tests.
 //! * `types` - Wrapper data types used in the library primarily to represent parts
 //!   of netlink messages.
 //! * `utils` - Data structures used for FFI and synchronization in socket operations.
@@ -141,15 +147,20 @@ pub mod err;
 pub mod genl;
 pub mod iter;
 pub mod nl;
+pub mod policy;
 pub mod router;
 pub mod rtnl;
+#[cfg(feature = "async-core")]
+pub mod runtime;
 pub mod socket;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod types;
 pub mod utils;
 
 use std::{
     fmt::Debug,
-    io::{Cursor, Read, Write},
+    io::{Cursor, Read, Seek, Write},
     marker::PhantomData,
     str,
 };
@@ -190,11 +201,17 @@ pub trait TypeSize {
 pub trait ToBytes: Debug {
     /// Takes a byte buffer and serializes the data structure into
     /// it.
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError>;
+    ///
+    /// Any sink implementing [`Write`] and [`Seek`] can be used, such as a
+    /// [`Cursor`] over a [`Vec<u8>`] or a `&mut [u8]`. Padding is computed from
+    /// the position of the sink, so serialization should start at an aligned
+    /// position.
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError>;
 
     /// Pad a netlink message to the appropriate alignment.
-    fn pad(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
-        let num_pad_bytes = alignto(buffer.position() as usize) - buffer.position() as usize;
+    fn pad(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
+        let position = buffer.stream_position()? as usize;
+        let num_pad_bytes = alignto(position) - position;
         buffer.write_all(&[0; libc::NLA_ALIGNTO as usize][..num_pad_bytes])?;
         Ok(())
     }
@@ -290,8 +307,8 @@ macro_rules! impl_nl_int {
         impl_nl_int!(impl__ $ty);
 
         impl $crate::ToBytes for $ty {
-            fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), $crate::err::SerError> {
-                <std::io::Cursor::<Vec<u8>> as byteorder::WriteBytesExt>::$write_method(buffer, *self)?;
+            fn to_bytes(&self, buffer: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), $crate::err::SerError> {
+                byteorder::WriteBytesExt::$write_method(buffer, *self)?;
                 Ok(())
             }
         }
@@ -306,8 +323,8 @@ macro_rules! impl_nl_int {
         impl_nl_int!(impl__ $ty);
 
         impl $crate::ToBytes for $ty {
-            fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), $crate::err::SerError> {
-                <std::io::Cursor::<Vec<u8>> as byteorder::WriteBytesExt>::$write_method::<$endianness>(buffer, *self)?;
+            fn to_bytes(&self, buffer: &mut (impl std::io::Write + std::io::Seek)) -> Result<(), $crate::err::SerError> {
+                byteorder::WriteBytesExt::$write_method::<$endianness>(buffer, *self)?;
                 Ok(())
             }
         }
@@ -340,7 +357,7 @@ impl Size for () {
 }
 
 impl ToBytes for () {
-    fn to_bytes(&self, _: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, _: &mut (impl Write + Seek)) -> Result<(), SerError> {
         Ok(())
     }
 }
@@ -376,7 +393,7 @@ impl<T> TypeSize for PhantomData<T> {
 }
 
 impl<T> ToBytes for PhantomData<T> {
-    fn to_bytes(&self, _: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, _: &mut (impl Write + Seek)) -> Result<(), SerError> {
         Ok(())
     }
 }
@@ -394,7 +411,7 @@ impl Size for &'_ str {
 }
 
 impl ToBytes for &'_ str {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         buffer.write_all(self.as_bytes())?;
         buffer.write_all(&[0])?;
         Ok(())
@@ -419,7 +436,7 @@ impl Size for String {
 }
 
 impl ToBytes for String {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         self.as_str().to_bytes(buffer)?;
         Ok(())
     }
@@ -463,14 +480,14 @@ impl<const N: usize> Size for [u8; N] {
 }
 
 impl ToBytes for &'_ [u8] {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         buffer.write_all(self)?;
         Ok(())
     }
 }
 
 impl<const N: usize> ToBytes for [u8; N] {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         buffer.write_all(self)?;
         Ok(())
     }
@@ -499,7 +516,7 @@ impl<T> ToBytes for Vec<T>
 where
     T: ToBytes,
 {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         for elem in self.iter() {
             elem.to_bytes(buffer)?;
         }
@@ -561,7 +578,7 @@ impl BeU64 {
 }
 
 impl ToBytes for BeU64 {
-    fn to_bytes(&self, buffer: &mut Cursor<Vec<u8>>) -> Result<(), SerError> {
+    fn to_bytes(&self, buffer: &mut (impl Write + Seek)) -> Result<(), SerError> {
         buffer.write_all(&self.0.to_be_bytes() as &[u8])?;
         Ok(())
     }
@@ -599,6 +616,24 @@ mod test {
         *LOGGER;
     }
 
+    /// Create an empty network namespace on a thread that exits afterwards.
+    ///
+    /// Returns [`None`] if creating a namespace requires privileges the tests do
+    /// not have, in which case the calling test should be skipped.
+    pub fn empty_netns() -> Option<socket::netns::NetNs> {
+        std::thread::spawn(|| {
+            if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                let err = std::io::Error::last_os_error();
+                assert_eq!(err.raw_os_error(), Some(libc::EPERM), "{err}");
+                eprintln!("Skipping test: creating a network namespace requires CAP_SYS_ADMIN");
+                return None;
+            }
+            Some(socket::netns::NetNs::open("/proc/thread-self/ns/net").unwrap())
+        })
+        .join()
+        .unwrap()
+    }
+
     #[test]
     fn test_nl_u8() {
         setup();
@@ -723,4 +758,56 @@ mod test {
         let de = String::from_bytes_with_input(&mut Cursor::new(desired_s.as_bytes()), 6).unwrap();
         assert_eq!(de_s, de)
     }
+
+    #[test]
+    fn test_slice_sink() {
+        use crate::{
+            consts::{
+                genl::{CtrlAttr, CtrlCmd},
+                nl::{GenlId, NlmF},
+            },
+            genl::{AttrTypeBuilder, GenlmsghdrBuilder, NlattrBuilder, NoUserHeader},
+            nl::{NlPayload, NlmsghdrBuilder},
+            types::GenlBuffer,
+        };
+
+        setup();
+
+        let attrs = vec![NlattrBuilder::default()
+            .nla_type(
+                AttrTypeBuilder::default()
+                    .nla_type(CtrlAttr::FamilyName)
+                    .build()
+                    .unwrap(),
+            )
+            .nla_payload("nlctrl")
+            .build()
+            .unwrap()]
+        .into_iter()
+        .collect::<GenlBuffer<_, _>>();
+        let msg = NlmsghdrBuilder::default()
+            .nl_type(GenlId::Ctrl)
+            .nl_flags(NlmF::REQUEST)
+            .nl_payload(NlPayload::Payload(
+                GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                    .cmd(CtrlCmd::Getfamily)
+                    .version(2)
+                    .attrs(attrs)
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        let ser_buffer = serialize(&msg).unwrap();
+
+        let mut slice = [0xffu8; 64];
+        let mut cursor = Cursor::new(&mut slice[..]);
+        msg.to_bytes(&mut cursor).unwrap();
+        let len = cursor.position() as usize;
+        assert_eq!(len, msg.padded_size());
+        assert_eq!(&slice[..len], ser_buffer.as_slice());
+
+        let mut slice = [0u8; 16];
+        assert!(msg.to_bytes(&mut Cursor::new(&mut slice[..])).is_err());
+    }
 }
@@ -0,0 +1,292 @@
+//! Attribute policies reported by the kernel.
+//!
+//! The kernel validates the attributes of a request against a policy that
+//! describes the type and the valid range of each attribute. When it rejects an
+//! attribute, the extended ACK can describe the violated policy in
+//! [`ExtAck::policy`][crate::err::ExtAck::policy]. Generic netlink families
+//! also expose the policies of all of their operations through
+//! `CTRL_CMD_GETPOLICY`, which
+//! [`NlRouter::resolve_genl_policy`][crate::router::synchronous::NlRouter::resolve_genl_policy]
+//! decodes into a [`FamilyPolicy`].
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+use getset::Getters;
+
+use crate::{
+    attr::Attribute,
+    consts::{
+        genl::{CtrlAttr, CtrlAttrPolicy, CtrlCmd, Index},
+        nl::{NlAttrKind, NlPolicyTypeAttr},
+    },
+    err::DeError,
+    genl::{GenlAttrHandle, Genlmsghdr},
+};
+
+/// Policy of a single attribute.
+///
+/// Fields the kernel did not report for the attribute are [`None`].
+#[derive(Getters, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttrPolicy {
+    /// Type of the attribute
+    #[getset(get = "pub")]
+    attr_type: Option<NlAttrKind>,
+    /// Minimum value of a signed integer attribute
+    #[getset(get = "pub")]
+    min_value_s: Option<i64>,
+    /// Maximum value of a signed integer attribute
+    #[getset(get = "pub")]
+    max_value_s: Option<i64>,
+    /// Minimum value of an unsigned integer attribute
+    #[getset(get = "pub")]
+    min_value_u: Option<u64>,
+    /// Maximum value of an unsigned integer attribute
+    #[getset(get = "pub")]
+    max_value_u: Option<u64>,
+    /// Minimum length of the attribute payload in bytes
+    #[getset(get = "pub")]
+    min_length: Option<u32>,
+    /// Maximum length of the attribute payload in bytes
+    #[getset(get = "pub")]
+    max_length: Option<u32>,
+    /// Index of the policy of the attributes nested in this attribute
+    #[getset(get = "pub")]
+    policy_idx: Option<u32>,
+    /// Maximum attribute type of the policy of the nested attributes
+    #[getset(get = "pub")]
+    policy_maxtype: Option<u32>,
+    /// Valid bits of a bitfield32 attribute
+    #[getset(get = "pub")]
+    bitfield32_mask: Option<u32>,
+    /// Valid bits of an unsigned integer attribute
+    #[getset(get = "pub")]
+    mask: Option<u64>,
+}
+
+impl AttrPolicy {
+    /// Parse the `NL_POLICY_TYPE_ATTR_*` attributes describing a policy.
+    pub fn parse(attrs: &GenlAttrHandle<'_, NlPolicyTypeAttr>) -> Result<Self, DeError> {
+        let mut policy = AttrPolicy::default();
+        for attr in attrs.iter() {
+            match attr.nla_type().nla_type() {
+                NlPolicyTypeAttr::Type => {
+                    policy.attr_type = Some(NlAttrKind::from(attr.get_payload_as::<u32>()?))
+                }
+                NlPolicyTypeAttr::MinValueS => policy.min_value_s = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::MaxValueS => policy.max_value_s = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::MinValueU => policy.min_value_u = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::MaxValueU => policy.max_value_u = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::MinLength => policy.min_length = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::MaxLength => policy.max_length = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::PolicyIdx => policy.policy_idx = Some(attr.get_payload_as()?),
+                NlPolicyTypeAttr::PolicyMaxtype => {
+                    policy.policy_maxtype = Some(attr.get_payload_as()?)
+                }
+                NlPolicyTypeAttr::Bitfield32Mask => {
+                    policy.bitfield32_mask = Some(attr.get_payload_as()?)
+                }
+                NlPolicyTypeAttr::Mask => policy.mask = Some(attr.get_payload_as()?),
+                _ => (),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+impl Display for AttrPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.attr_type {
+            Some(NlAttrKind::Invalid) => write!(f, "invalid attribute")?,
+            Some(NlAttrKind::Flag) => write!(f, "flag")?,
+            Some(NlAttrKind::U8) => write!(f, "u8")?,
+            Some(NlAttrKind::U16) => write!(f, "u16")?,
+            Some(NlAttrKind::U32) => write!(f, "u32")?,
+            Some(NlAttrKind::U64) => write!(f, "u64")?,
+            Some(NlAttrKind::S8) => write!(f, "s8")?,
+            Some(NlAttrKind::S16) => write!(f, "s16")?,
+            Some(NlAttrKind::S32) => write!(f, "s32")?,
+            Some(NlAttrKind::S64) => write!(f, "s64")?,
+            Some(NlAttrKind::Binary) => write!(f, "binary")?,
+            Some(NlAttrKind::String) => write!(f, "string")?,
+            Some(NlAttrKind::NulString) => write!(f, "NUL terminated string")?,
+            Some(NlAttrKind::Nested) => write!(f, "nested attributes")?,
+            Some(NlAttrKind::NestedArray) => write!(f, "nested array")?,
+            Some(NlAttrKind::Bitfield32) => write!(f, "bitfield32")?,
+            Some(NlAttrKind::Sint) => write!(f, "signed integer")?,
+            Some(NlAttrKind::Uint) => write!(f, "unsigned integer")?,
+            Some(NlAttrKind::UnrecognizedConst(ty)) => write!(f, "attribute of type {ty}")?,
+            None => write!(f, "attribute")?,
+        }
+        if let (Some(min), Some(max)) = (self.min_value_s, self.max_value_s) {
+            write!(f, " in range {min}..={max}")?;
+        }
+        if let (Some(min), Some(max)) = (self.min_value_u, self.max_value_u) {
+            write!(f, " in range {min}..={max}")?;
+        }
+        match (self.min_length, self.max_length) {
+            (Some(min), Some(max)) => write!(f, " of {min} to {max} bytes")?,
+            (Some(min), None) => write!(f, " of at least {min} bytes")?,
+            (None, Some(max)) => write!(f, " of at most {max} bytes")?,
+            (None, None) => (),
+        }
+        if let Some(mask) = self.mask {
+            write!(f, " with mask {mask:#x}")?;
+        }
+        if let Some(mask) = self.bitfield32_mask {
+            write!(f, " with valid bits {mask:#x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Policy indices used by a generic netlink operation.
+#[derive(Getters, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpPolicy {
+    /// Index of the policy used for `do` requests
+    #[getset(get = "pub")]
+    do_policy: Option<u32>,
+    /// Index of the policy used for `dump` requests
+    #[getset(get = "pub")]
+    dump_policy: Option<u32>,
+}
+
+/// Attribute policies of a generic netlink family returned by
+/// `CTRL_CMD_GETPOLICY`.
+///
+/// Policies are identified by an index. Operations refer to the policies used
+/// for their requests and nested attributes refer to the policy of their
+/// contents with [`AttrPolicy::policy_idx`].
+#[derive(Getters, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FamilyPolicy {
+    /// Attribute policies by policy index and attribute type
+    #[getset(get = "pub")]
+    policies: BTreeMap<u32, BTreeMap<u16, AttrPolicy>>,
+    /// Policy indices of the operations by command
+    #[getset(get = "pub")]
+    ops: BTreeMap<u8, OpPolicy>,
+}
+
+impl FamilyPolicy {
+    /// Decode the messages of a `CTRL_CMD_GETPOLICY` dump.
+    pub fn parse<'a, I>(msgs: I) -> Result<Self, DeError>
+    where
+        I: IntoIterator<Item = &'a Genlmsghdr<CtrlCmd, CtrlAttr>>,
+    {
+        let mut family = FamilyPolicy::default();
+        for msg in msgs {
+            let handle = msg.attrs().get_attr_handle();
+            if handle.get_attribute(CtrlAttr::Policy).is_some() {
+                for policy in handle
+                    .get_nested_attributes::<Index>(CtrlAttr::Policy)?
+                    .iter()
+                {
+                    let attrs = family
+                        .policies
+                        .entry(u32::from(u16::from(policy.nla_type().nla_type())))
+                        .or_default();
+                    for attr in policy.get_attr_handle::<Index>()?.iter() {
+                        attrs.insert(
+                            u16::from(attr.nla_type().nla_type()),
+                            AttrPolicy::parse(&attr.get_attr_handle()?)?,
+                        );
+                    }
+                }
+            }
+            if handle.get_attribute(CtrlAttr::OpPolicy).is_some() {
+                for op in handle
+                    .get_nested_attributes::<Index>(CtrlAttr::OpPolicy)?
+                    .iter()
+                {
+                    let policies = op.get_attr_handle::<CtrlAttrPolicy>()?;
+                    family.ops.insert(
+                        u16::from(op.nla_type().nla_type()) as u8,
+                        OpPolicy {
+                            do_policy: policies.get_attr_payload_as(CtrlAttrPolicy::Do).ok(),
+                            dump_policy: policies.get_attr_payload_as(CtrlAttrPolicy::Dump).ok(),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(family)
+    }
+
+    /// Get the attribute policies with the index `idx`.
+    pub fn policy(&self, idx: u32) -> Option<&BTreeMap<u16, AttrPolicy>> {
+        self.policies.get(&idx)
+    }
+
+    /// Get the policy indices of the operation with the command `cmd`.
+    pub fn op<C>(&self, cmd: C) -> Option<&OpPolicy>
+    where
+        C: Into<u8>,
+    {
+        self.ops.get(&cmd.into())
+    }
+
+    /// Get the policy of the attribute `attr` in `do` requests with the command
+    /// `cmd`.
+    pub fn do_attr_policy<C, T>(&self, cmd: C, attr: T) -> Option<&AttrPolicy>
+    where
+        C: Into<u8>,
+        T: Into<u16>,
+    {
+        self.op(cmd)?
+            .do_policy
+            .and_then(|idx| self.policy(idx))?
+            .get(&attr.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        attr::AttrHandle,
+        genl::{AttrTypeBuilder, Nlattr, NlattrBuilder},
+        types::{Buffer, GenlBuffer},
+        Size, ToBytes,
+    };
+
+    fn attr<P>(ty: NlPolicyTypeAttr, payload: P) -> Nlattr<NlPolicyTypeAttr, Buffer>
+    where
+        P: Size + ToBytes,
+    {
+        NlattrBuilder::default()
+            .nla_type(AttrTypeBuilder::default().nla_type(ty).build().unwrap())
+            .nla_payload(payload)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_attr_policy() {
+        let attrs = [
+            attr(NlPolicyTypeAttr::Type, u32::from(NlAttrKind::U16)),
+            attr(NlPolicyTypeAttr::MinValueU, 0u64),
+            attr(NlPolicyTypeAttr::MaxValueU, 4094u64),
+        ]
+        .into_iter()
+        .collect::<GenlBuffer<_, _>>();
+        let policy = AttrPolicy::parse(&AttrHandle::new(attrs)).unwrap();
+        assert_eq!(policy.attr_type(), &Some(NlAttrKind::U16));
+        assert_eq!(policy.max_value_u(), &Some(4094));
+        assert_eq!(policy.to_string(), "u16 in range 0..=4094");
+
+        let attrs = [
+            attr(NlPolicyTypeAttr::Type, u32::from(NlAttrKind::NulString)),
+            attr(NlPolicyTypeAttr::MaxLength, 15u32),
+        ]
+        .into_iter()
+        .collect::<GenlBuffer<_, _>>();
+        let policy = AttrPolicy::parse(&AttrHandle::new(attrs)).unwrap();
+        assert_eq!(
+            policy.to_string(),
+            "NUL terminated string of at most 15 bytes"
+        );
+    }
+}
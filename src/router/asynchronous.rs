@@ -1,182 +1,297 @@
 use std::{
-    collections::{HashMap, HashSet},
+    future::{poll_fn, Future},
     iter::once,
     marker::PhantomData,
+    pin::{pin, Pin},
     sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
-use log::{error, trace, warn};
-use tokio::{
-    select, spawn,
-    sync::{
-        mpsc::{channel, Receiver, Sender},
-        Mutex,
-    },
+use futures_channel::{
+    mpsc::{channel, Receiver, SendError, Sender},
+    oneshot,
+};
+use futures_util::{
+    future::{select, Either},
+    Stream, StreamExt,
 };
+use log::{debug, error, trace, warn};
+use parking_lot::Mutex;
 
 use crate::{
+    attr::AttrSchema,
     consts::{
-        genl::{CtrlAttr, CtrlAttrMcastGrp, CtrlCmd, Index},
-        nl::{GenlId, NlType, NlmF, Nlmsg},
+        genl::{CtrlAttr, CtrlCmd},
+        nl::{GenlId, NlType, NlmF},
         socket::NlFamily,
     },
     err::{RouterError, SocketError},
-    genl::{AttrTypeBuilder, Genlmsghdr, GenlmsghdrBuilder, NlattrBuilder, NoUserHeader},
+    genl::{
+        AttrTypeBuilder, FamilyEvent, GenlFamilyInfo, Genlmsghdr, GenlmsghdrBuilder, NlattrBuilder,
+        NoUserHeader,
+    },
     nl::{NlPayload, Nlmsghdr, NlmsghdrBuilder},
-    socket::asynchronous::NlSocketHandle,
+    policy::FamilyPolicy,
+    router::{
+        protocol::{Delivery, Dispatch, GenlCache, ResponseValidator, RouterCore},
+        RouterConfig,
+    },
+    runtime::{DefaultRuntime, Runtime},
+    socket::{
+        asynchronous::NlSocketHandle, capture::Capture, filter::SocketFilter, NlSocket,
+        RecvMetadata,
+    },
     types::{Buffer, GenlBuffer, NlBuffer},
     utils::{Groups, NetlinkBitArray},
     FromBytesWithInput, Size, ToBytes,
 };
 
+const DEFAULT_DUMP_ATTEMPTS: usize = 3;
+const CHANNEL_CAPACITY: usize = 1024;
+
 type GenlFamily = Result<
     NlBuffer<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>,
     RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>,
 >;
-type MCastSender = Sender<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>;
-type Senders =
-    Arc<Mutex<HashMap<u32, Sender<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>>>>;
-type ProcThreadReturn = (
-    Sender<()>,
-    Receiver<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>,
-);
+type Core = Arc<Mutex<RouterCore<Sender<Delivery>>>>;
+type Sleep = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+type Sleeper = Arc<dyn Fn(Instant) -> Sleep + Send + Sync>;
+type PollTyped<T, P> = Poll<Option<Result<Nlmsghdr<T, P>, RouterError<T, P>>>>;
+type ProcThreadReturn = (oneshot::Sender<()>, Receiver<Delivery>);
+type ConnectReturn<T> = Result<
+    (
+        T,
+        NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+    ),
+    RouterError<u16, Buffer>,
+>;
 
 /// A high-level handle for sending messages and generating a handle that validates
 /// all of the received messages.
-pub struct NlRouter {
-    socket: Arc<NlSocketHandle>,
-    seq: Mutex<u32>,
-    senders: Senders,
+///
+/// The router is driven by the [`Runtime`] `R`. See [`crate::runtime`] for the
+/// available backends.
+pub struct NlRouter<R: Runtime = DefaultRuntime> {
+    socket: Arc<NlSocketHandle<R>>,
+    core: Core,
+    sleeper: Sleeper,
+    default_timeout: Mutex<Option<Duration>>,
+    dump_attempts: Mutex<usize>,
+
+    exit_sender: Option<oneshot::Sender<()>>,
+}
+
+/// Sender for the multicast receiver handle.
+///
+/// Messages are dropped instead of blocking the processing task while the
+/// channel is full. The next message that fits is preceded by
+/// [`RouterError::EventsLost`] so that the receiver can resynchronize.
+struct MCastSender {
+    sender: Sender<Delivery>,
+    overrun: bool,
+}
+
+impl MCastSender {
+    fn new(sender: Sender<Delivery>) -> Self {
+        MCastSender {
+            sender,
+            overrun: false,
+        }
+    }
+
+    fn send(&mut self, delivery: Delivery) {
+        if self.overrun {
+            match self.sender.try_send((Err(RouterError::EventsLost), None)) {
+                Ok(()) => self.overrun = false,
+                Err(e) if e.is_full() => return,
+                Err(_) => {
+                    warn!("{}", RouterError::<u16, Buffer>::ClosedChannel);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.sender.try_send(delivery) {
+            if e.is_full() {
+                warn!("Multicast channel is full; dropping messages");
+                self.overrun = true;
+            } else {
+                warn!("{}", RouterError::<u16, Buffer>::ClosedChannel);
+            }
+        }
+    }
+}
 
-    exit_sender: Sender<()>,
+/// Wait for capacity in the channel of a receiver handle and send the delivery.
+async fn send(sender: &mut Sender<Delivery>, delivery: Delivery) -> Result<(), SendError> {
+    poll_fn(|cx| sender.poll_ready(cx)).await?;
+    sender.start_send(delivery)
 }
 
-async fn processing_loop(
-    socket: &Arc<NlSocketHandle>,
-    senders: &Senders,
-    multicast_sender: &MCastSender,
-    iter: impl Iterator<Item = Result<Nlmsghdr<u16, Buffer>, SocketError>>,
-    group: Groups,
+async fn deliver(
+    core: &Core,
+    multicast_sender: &mut MCastSender,
+    dispatches: impl IntoIterator<Item = Dispatch>,
+    metadata: Option<&RecvMetadata>,
 ) {
-    for msg in iter {
-        trace!("Message received: {msg:?}");
-        let mut seqs_to_remove = HashSet::new();
-        match msg {
-            Ok(m) => {
-                let seq = *m.nl_seq();
-                let lock = senders.lock().await;
-                if !group.is_empty() {
-                    if multicast_sender.send(Ok(m)).await.is_err() {
-                        warn!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                    }
-                } else if let Some(sender) = lock.get(m.nl_seq()) {
-                    if &socket.pid() == m.nl_pid() {
-                        if sender.send(Ok(m)).await.is_err() {
-                            error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                            seqs_to_remove.insert(seq);
-                        }
-                    } else {
-                        for (seq, sender) in lock.iter() {
-                            if sender
-                                .send(Err(RouterError::BadSeqOrPid(m.clone())))
-                                .await
-                                .is_err()
-                            {
-                                error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                                seqs_to_remove.insert(*seq);
-                            }
-                        }
+    for dispatch in dispatches {
+        match dispatch {
+            Dispatch::Response(seq, m) => {
+                let sender = core.lock().sender(seq).cloned();
+                if let Some(mut sender) = sender {
+                    if send(&mut sender, (Ok(m), metadata.cloned())).await.is_err() {
+                        error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
+                        core.lock().unregister(seq);
                     }
                 }
             }
-            Err(e) => {
-                let lock = senders.lock().await;
-                for (seq, sender) in lock.iter() {
-                    if sender
-                        .send(Err(RouterError::from(e.clone())))
-                        .await
-                        .is_err()
-                    {
+            Dispatch::Multicast(received) => {
+                if let Err(RouterError::EventsLost) = received {
+                    warn!("Socket receive buffer overrun; multicast messages were lost");
+                }
+                multicast_sender.send((received, metadata.cloned()));
+            }
+            Dispatch::Late(seq, m) => {
+                trace!("Dropping response to abandoned request {seq}: {m:?}");
+            }
+            Dispatch::Broadcast(e) => {
+                let senders = core
+                    .lock()
+                    .senders()
+                    .map(|(seq, sender)| (seq, sender.clone()))
+                    .collect::<Vec<_>>();
+                for (seq, mut sender) in senders {
+                    if send(&mut sender, (Err(e.clone()), None)).await.is_err() {
                         error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                        seqs_to_remove.insert(*seq);
+                        core.lock().unregister(seq);
                     }
                 }
             }
         }
-        for seq in seqs_to_remove {
-            senders.lock().await.remove(&seq);
-        }
     }
 }
 
-fn spawn_processing_thread(socket: Arc<NlSocketHandle>, senders: Senders) -> ProcThreadReturn {
-    let (exit_sender, mut exit_receiver) = channel(1);
-    let (multicast_sender, multicast_receiver) = channel(1024);
-    spawn(async move {
+fn spawn_processing_thread<R>(
+    runtime: &R,
+    socket: Arc<NlSocketHandle<R>>,
+    core: Core,
+) -> ProcThreadReturn
+where
+    R: Runtime,
+{
+    let (exit_sender, mut exit_receiver) = oneshot::channel();
+    let (multicast_sender, multicast_receiver) = channel(CHANNEL_CAPACITY);
+    let mut multicast_sender = MCastSender::new(multicast_sender);
+    runtime.spawn(async move {
         loop {
-            select! {
-                res = exit_receiver.recv() => {
-                    if res.is_none() {
-                        warn!("Failed to read from exit channel");
-                    }
+            let res = match select(&mut exit_receiver, pin!(socket.recv::<u16, Buffer>())).await {
+                Either::Left(_) => {
+                    debug!("Processing task signaled to exit; exiting");
                     return;
                 }
-                res = socket.recv::<u16, Buffer>() => {
-                    match res {
-                        Ok((iter, group)) => {
-                            processing_loop(&socket, &senders, &multicast_sender, iter, group).await
-                        }
-                        Err(e) => {
-                            let mut seqs_to_remove = HashSet::new();
-                            let mut lock = senders.lock().await;
-                            for (seq, sender) in lock.iter() {
-                                if sender
-                                    .send(Err(RouterError::from(e.clone())))
-                                    .await
-                                    .is_err()
-                                {
-                                    seqs_to_remove.insert(*seq);
-                                    error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                                    break;
-                                }
-                            }
-                            for seq in seqs_to_remove {
-                                lock.remove(&seq);
-                            }
-                        }
-                    }
+                Either::Right((res, _)) => res,
+            };
+            let (dispatches, metadata) = match res {
+                Ok((iter, group)) => {
+                    let metadata = iter.metadata().cloned();
+                    (core.lock().dispatch_iter(iter, &group), metadata)
                 }
+                Err(e) => (core.lock().dispatch_error(e).into_iter().collect(), None),
+            };
+            // Waiting for a receiver handle to make room must not prevent the
+            // router from shutting down.
+            let delivered = pin!(deliver(
+                &core,
+                &mut multicast_sender,
+                dispatches,
+                metadata.as_ref()
+            ));
+            let res = select(&mut exit_receiver, delivered).await;
+            if let Either::Left(_) = res {
+                debug!("Processing task signaled to exit; exiting");
+                return;
             }
         }
     });
     (exit_sender, multicast_receiver)
 }
 
+#[cfg(any(feature = "tokio", feature = "async-io"))]
 impl NlRouter {
-    /// Equivalent of `socket` and `bind` calls.
-    pub async fn connect(
+    /// Equivalent of `socket` and `bind` calls using the [`DefaultRuntime`].
+    pub async fn connect(proto: NlFamily, pid: Option<u32>, groups: Groups) -> ConnectReturn<Self> {
+        Self::connect_with_runtime(DefaultRuntime::default(), proto, pid, groups).await
+    }
+}
+
+impl<R: Runtime> NlRouter<R> {
+    /// Equivalent of `socket` and `bind` calls using the provided runtime to drive
+    /// the socket and spawn the background processing task.
+    ///
+    /// Multicast messages are dropped if the returned multicast handle falls
+    /// behind by more than 1024 messages. The handle then receives
+    /// [`RouterError::EventsLost`] like for a socket receive buffer overrun.
+    pub async fn connect_with_runtime(
+        runtime: R,
         proto: NlFamily,
         pid: Option<u32>,
         groups: Groups,
-    ) -> Result<
-        (
-            Self,
-            NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
-        ),
-        RouterError<u16, Buffer>,
-    > {
-        let socket = Arc::new(NlSocketHandle::connect(proto, pid, groups)?);
-        let senders = Arc::new(Mutex::new(HashMap::default()));
+    ) -> ConnectReturn<Self> {
+        Self::connect_with_config(runtime, proto, pid, groups, RouterConfig::default()).await
+    }
+
+    /// Equivalent of [`NlRouter::connect_with_runtime`] with the receive buffers and
+    /// network namespace described by `config`.
+    ///
+    /// A socket in another network namespace is created on a helper thread
+    /// without blocking the executor.
+    pub async fn connect_with_config(
+        runtime: R,
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+        config: RouterConfig,
+    ) -> ConnectReturn<Self> {
+        let socket = match config.netns {
+            Some(ref netns) => {
+                let socket = netns
+                    .run_async(move || NlSocket::connect(proto, pid, groups))
+                    .await
+                    .map_err(SocketError::from)?;
+                NlSocketHandle::from_socket(&runtime, socket)?
+            }
+            None => NlSocketHandle::connect_with_runtime(&runtime, proto, pid, groups)?,
+        };
+        Self::from_socket(runtime, socket.with_buffer_pool(config.buffer_pool))
+    }
+
+    fn from_socket(runtime: R, socket: NlSocketHandle<R>) -> ConnectReturn<Self> {
+        let socket = Arc::new(socket);
+        // Report the group number of multicast messages for groups above 32.
+        socket.enable_pktinfo(true)?;
+        let core = Arc::new(Mutex::new(RouterCore::new(socket.pid())));
         let (exit_sender, multicast_receiver) =
-            spawn_processing_thread(Arc::clone(&socket), Arc::clone(&senders));
-        let multicast_receiver =
-            NlRouterReceiverHandle::new(multicast_receiver, Arc::clone(&senders), false, None);
+            spawn_processing_thread(&runtime, Arc::clone(&socket), Arc::clone(&core));
+        let sleeper: Sleeper = Arc::new(move |deadline| {
+            let sleep = runtime.sleep_until(deadline);
+            Box::pin(async move {
+                sleep.await;
+            })
+        });
+        let multicast_receiver = NlRouterReceiverHandle::new(
+            multicast_receiver,
+            Arc::clone(&core),
+            ResponseValidator::multicast(),
+            Arc::clone(&sleeper),
+        );
         Ok((
             NlRouter {
                 socket,
-                senders,
-                seq: Mutex::new(0),
-                exit_sender,
+                core,
+                sleeper,
+                default_timeout: Mutex::new(None),
+                dump_attempts: Mutex::new(DEFAULT_DUMP_ATTEMPTS),
+                exit_sender: Some(exit_sender),
             },
             multicast_receiver,
         ))
@@ -184,13 +299,32 @@ impl NlRouter {
 
     /// Join multicast groups for a socket.
     pub fn add_mcast_membership(&self, groups: Groups) -> Result<(), RouterError<u16, Buffer>> {
+        if let Some(cache) = self.core.lock().genl_cache_mut() {
+            if groups.as_groups().contains(&cache.notify_group()) {
+                cache.set_deliver_notifications(true);
+            }
+        }
         self.socket
             .add_mcast_membership(groups)
             .map_err(RouterError::from)
     }
 
     /// Leave multicast groups for a socket.
-    pub fn drop_mcast_membership(&self, groups: Groups) -> Result<(), RouterError<u16, Buffer>> {
+    ///
+    /// While the generic netlink cache is enabled, the socket stays in the
+    /// `notify` group of `nlctrl` to keep the cache up to date, but its
+    /// notifications are no longer delivered to the multicast receiver.
+    pub fn drop_mcast_membership(
+        &self,
+        mut groups: Groups,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        if let Some(cache) = self.core.lock().genl_cache_mut() {
+            let group = cache.notify_group();
+            if groups.as_groups().contains(&group) {
+                cache.set_deliver_notifications(false);
+                groups.remove_groups(&[group]);
+            }
+        }
         self.socket
             .drop_mcast_membership(groups)
             .map_err(RouterError::from)
@@ -216,6 +350,37 @@ impl NlRouter {
         self.socket.get_ext_ack_enabled().map_err(RouterError::from)
     }
 
+    /// Name the attributes of requests with the message type `nl_type` so that
+    /// [`RouterError::Nlmsgerr`] errors for these requests report the path of
+    /// the attribute the extended ACK points to.
+    ///
+    /// `header_len` is the length of the family header following the netlink
+    /// header, for example [`Ifinfomsg::header_size`][crate::Header::header_size]
+    /// for link requests.
+    pub fn register_attr_schema<T>(&self, nl_type: T, header_len: usize, schema: AttrSchema)
+    where
+        T: NlType,
+    {
+        self.core
+            .lock()
+            .register_attr_schema(nl_type.into(), header_len, schema);
+    }
+
+    /// If [`true`] is passed in, suppress `ENOBUFS` errors when the socket receive
+    /// buffer overruns. If [`false`] is passed in, report overruns as errors.
+    pub fn enable_no_enobufs(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_no_enobufs(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if `ENOBUFS` errors are suppressed for this socket.
+    pub fn get_no_enobufs_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket
+            .get_no_enobufs_enabled()
+            .map_err(RouterError::from)
+    }
+
     /// If [`true`] is passed in, enable strict checking for this socket. If [`false`]
     /// is passed in, disable strict checking for for this socket.
     /// Only supported by `NlFamily::Route` sockets.
@@ -235,16 +400,225 @@ impl NlRouter {
             .map_err(RouterError::from)
     }
 
+    /// Set the size of the receive buffer for the socket.
+    ///
+    /// See [`NlSocket::set_recv_buffer_size`][crate::socket::NlSocket::set_recv_buffer_size]
+    /// for more information.
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_recv_buffer_size(size)
+            .map_err(RouterError::from)
+    }
+
+    /// Set the size of the send buffer for the socket.
+    ///
+    /// The kernel will double the value provided to account for bookkeeping overhead.
+    /// The doubled value is capped by the value in `/proc/sys/net/core/wmem_max`.
+    ///
+    /// See `socket(7)` documentation for `SO_SNDBUF` for more information.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_send_buffer_size(size)
+            .map_err(RouterError::from)
+    }
+
+    /// Return the size of the send buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_send_buffer_size(&self) -> Result<usize, RouterError<u16, Buffer>> {
+        self.socket
+            .get_send_buffer_size()
+            .map_err(RouterError::from)
+    }
+
+    /// Return the size of the receive buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_recv_buffer_size(&self) -> Result<usize, RouterError<u16, Buffer>> {
+        self.socket
+            .get_recv_buffer_size()
+            .map_err(RouterError::from)
+    }
+
+    /// Set the size of the receive buffer for the socket, ignoring the limit in
+    /// `/proc/sys/net/core/rmem_max`.
+    ///
+    /// Requires `CAP_NET_ADMIN`. See `socket(7)` documentation for `SO_RCVBUFFORCE`
+    /// for more information.
+    pub fn force_recv_buffer_size(&self, size: usize) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .force_recv_buffer_size(size)
+            .map_err(RouterError::from)
+    }
+
+    /// Set the timeout for blocking receive operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A receive operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_recv_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_recv_timeout(timeout)
+            .map_err(RouterError::from)
+    }
+
+    /// Return the timeout for blocking receive operations.
+    pub fn get_recv_timeout(&self) -> Result<Option<Duration>, RouterError<u16, Buffer>> {
+        self.socket.get_recv_timeout().map_err(RouterError::from)
+    }
+
+    /// Set the timeout for blocking send operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A send operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_send_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_send_timeout(timeout)
+            .map_err(RouterError::from)
+    }
+
+    /// Return the timeout for blocking send operations.
+    pub fn get_send_timeout(&self) -> Result<Option<Duration>, RouterError<u16, Buffer>> {
+        self.socket.get_send_timeout().map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, report errors delivering multicast messages sent
+    /// from this socket. If [`false`] is passed in, only report errors if no
+    /// listener received the message.
+    pub fn enable_broadcast_error(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_broadcast_error(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if multicast delivery errors are reported for this socket.
+    pub fn get_broadcast_error_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket
+            .get_broadcast_error_enabled()
+            .map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, omit the payload of the original request from
+    /// error messages. If [`false`] is passed in, include the original request.
+    pub fn enable_cap_ack(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_cap_ack(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if the original request is omitted from error messages.
+    pub fn get_cap_ack_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket.get_cap_ack_enabled().map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, ask the kernel to attach the destination multicast
+    /// group to each received message as `NETLINK_PKTINFO` control data. If
+    /// [`false`] is passed in, disable this.
+    pub fn enable_pktinfo(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_pktinfo(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if `NETLINK_PKTINFO` control data is enabled for this
+    /// socket.
+    pub fn get_pktinfo_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket.get_pktinfo_enabled().map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, receive multicast messages from all network
+    /// namespaces that have an ID assigned in the namespace of the socket. If
+    /// [`false`] is passed in, only receive messages from the namespace of the
+    /// socket.
+    ///
+    /// Requires `CAP_NET_BROADCAST`.
+    pub fn enable_listen_all_nsid(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_listen_all_nsid(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if multicast messages from all network namespaces are
+    /// received by this socket.
+    pub fn get_listen_all_nsid_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket
+            .get_listen_all_nsid_enabled()
+            .map_err(RouterError::from)
+    }
+
+    /// Attach the classic BPF program compiled from `filter` to the socket,
+    /// replacing any filter that is already attached.
+    ///
+    /// Messages addressed to the port ID of the router are always accepted so
+    /// that responses to requests are not dropped.
+    pub fn attach_filter(&self, filter: &SocketFilter) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .attach_filter(&filter.clone().accept_pid(self.socket.pid()))
+            .map_err(RouterError::from)
+    }
+
+    /// Remove the filter attached with [`NlRouter::attach_filter`].
+    pub fn detach_filter(&self) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket.detach_filter().map_err(RouterError::from)
+    }
+
     /// Get the PID for the current socket.
     pub fn pid(&self) -> u32 {
         self.socket.pid()
     }
 
-    async fn next_seq(&self) -> u32 {
-        let mut lock = self.seq.lock().await;
-        let next = *lock;
-        *lock = lock.wrapping_add(1);
-        next
+    /// Pass every datagram sent or received by the router to `capture`.
+    ///
+    /// Passing [`None`] stops an active capture.
+    pub fn set_capture(
+        &self,
+        capture: Option<Arc<dyn Capture>>,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket.set_capture(capture).map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, grow the receive buffer for datagrams that do not
+    /// fit instead of reporting [`SocketError::Truncated`]
+    /// to all receivers.
+    pub fn enable_adaptive_buffers(&self, enable: bool) {
+        self.socket.enable_adaptive_buffers(enable)
+    }
+
+    /// Set the timeout applied to every request sent after this call.
+    ///
+    /// Each [`NlRouterReceiverHandle`] returned from [`NlRouter::send`] will have a
+    /// deadline of the time of sending plus this timeout. [`None`] disables the
+    /// timeout and is the default.
+    pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+        *self.default_timeout.lock() = timeout;
+    }
+
+    /// Get the timeout applied to every request sent by this router.
+    pub fn default_timeout(&self) -> Option<Duration> {
+        *self.default_timeout.lock()
+    }
+
+    /// Set the maximum number of times [`NlRouter::send_consistent_dump`] will
+    /// issue a dump request before giving up on getting a consistent snapshot.
+    ///
+    /// At least one attempt is always made.
+    pub fn set_consistent_dump_attempts(&self, attempts: usize) {
+        *self.dump_attempts.lock() = attempts;
+    }
+
+    /// Get the maximum number of attempts made by
+    /// [`NlRouter::send_consistent_dump`].
+    pub fn consistent_dump_attempts(&self) -> usize {
+        *self.dump_attempts.lock()
+    }
+
+    fn next_seq(&self) -> u32 {
+        self.core.lock().next_seq()
     }
 
     /// Send a message and return a handle for receiving responses from this message.
@@ -265,22 +639,131 @@ impl NlRouter {
                 nl_flags | NlmF::REQUEST,
             )
             .nl_pid(self.socket.pid())
-            .nl_seq(self.next_seq().await)
+            .nl_seq(self.next_seq())
             .nl_payload(nl_payload)
             .build()?;
         let seq = *msg.nl_seq();
-        let (sender, receiver) = channel(1024);
-        self.senders.lock().await.insert(seq, sender);
+        let (sender, receiver) = channel(CHANNEL_CAPACITY);
+        self.core.lock().register(seq, sender);
         let flags = *msg.nl_flags();
 
-        self.socket.send(&msg).await?;
+        if let Err(e) = self.socket.send(&msg).await {
+            self.core.lock().unregister(seq);
+            return Err(RouterError::from(e));
+        }
 
-        Ok(NlRouterReceiverHandle::new(
+        let mut handle = NlRouterReceiverHandle::new(
             receiver,
-            Arc::clone(&self.senders),
-            flags.contains(NlmF::ACK) && !flags.contains(NlmF::DUMP),
-            Some(seq),
-        ))
+            Arc::clone(&self.core),
+            ResponseValidator::request(flags, seq),
+            Arc::clone(&self.sleeper),
+        );
+        handle.set_deadline(self.default_timeout().map(|t| Instant::now() + t));
+        Ok(handle)
+    }
+
+    /// Send a batch of messages in a single system call and return a handle for
+    /// receiving responses for each message.
+    ///
+    /// Each message is assigned its own sequence number and the returned handles are
+    /// in the same order as the messages provided. This allows ACKs and errors to be
+    /// correlated with the message that caused them.
+    /// An empty batch sends nothing and returns no handles.
+    pub async fn send_batch<ST, SP, RT, RP, I>(
+        &self,
+        msgs: I,
+    ) -> Result<Vec<NlRouterReceiverHandle<RT, RP>>, RouterError<ST, SP>>
+    where
+        ST: NlType,
+        SP: Size + ToBytes,
+        I: IntoIterator<Item = (ST, NlmF, NlPayload<ST, SP>)>,
+    {
+        let mut buffer = NlBuffer::new();
+        for (nl_type, nl_flags, nl_payload) in msgs {
+            buffer.push(
+                NlmsghdrBuilder::default()
+                    .nl_type(nl_type)
+                    .nl_flags(
+                        // Required for messages
+                        nl_flags | NlmF::REQUEST,
+                    )
+                    .nl_pid(self.socket.pid())
+                    .nl_seq(self.next_seq())
+                    .nl_payload(nl_payload)
+                    .build()?,
+            );
+        }
+
+        if buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut receivers = Vec::with_capacity(buffer.len());
+        {
+            let mut lock = self.core.lock();
+            for msg in buffer.iter() {
+                let (sender, receiver) = channel(CHANNEL_CAPACITY);
+                lock.register(*msg.nl_seq(), sender);
+                receivers.push(receiver);
+            }
+        }
+
+        if let Err(e) = self.socket.send_batch(&buffer).await {
+            let mut lock = self.core.lock();
+            for msg in buffer.iter() {
+                lock.unregister(*msg.nl_seq());
+            }
+            return Err(RouterError::from(e));
+        }
+
+        let deadline = self.default_timeout().map(|t| Instant::now() + t);
+        Ok(buffer
+            .iter()
+            .zip(receivers)
+            .map(|(msg, receiver)| {
+                let mut handle = NlRouterReceiverHandle::new(
+                    receiver,
+                    Arc::clone(&self.core),
+                    ResponseValidator::request(*msg.nl_flags(), *msg.nl_seq()),
+                    Arc::clone(&self.sleeper),
+                );
+                handle.set_deadline(deadline);
+                handle
+            })
+            .collect())
+    }
+
+    /// Send a dump request and collect all of the responses, reissuing the request
+    /// if the kernel reports that the dump was interrupted by a concurrent change.
+    ///
+    /// [`NlmF::DUMP`] is added to the flags automatically. The request is sent at most
+    /// [`NlRouter::consistent_dump_attempts`] times before
+    /// [`RouterError::DumpInterrupted`] is returned.
+    pub async fn send_consistent_dump<T, P>(
+        &self,
+        nl_type: T,
+        nl_flags: NlmF,
+        nl_payload: NlPayload<T, P>,
+    ) -> Result<NlBuffer<T, P>, RouterError<T, P>>
+    where
+        T: NlType,
+        P: Clone + Size + ToBytes + FromBytesWithInput<Input = usize>,
+    {
+        let attempts = self.consistent_dump_attempts().max(1);
+        for attempt in 1..=attempts {
+            let mut recv = self
+                .send::<_, _, T, P>(nl_type, nl_flags | NlmF::DUMP, nl_payload.clone())
+                .await?;
+            let mut buffer = NlBuffer::new();
+            while let Some(msg) = recv.next().await {
+                buffer.push(msg?);
+            }
+            if !recv.dump_interrupted() {
+                return Ok(buffer);
+            }
+            debug!("Dump was interrupted on attempt {attempt} of {attempts}");
+        }
+        Err(RouterError::DumpInterrupted)
     }
 
     async fn get_genl_family(&self, family_name: &str) -> GenlFamily {
@@ -323,21 +806,7 @@ impl NlRouter {
         &self,
         family_name: &str,
     ) -> Result<u16, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
-        let mut res = Err(RouterError::new(format!(
-            "Generic netlink family {family_name} was not found"
-        )));
-
-        let nlhdrs = self.get_genl_family(family_name).await?;
-        for nlhdr in nlhdrs.into_iter() {
-            if let NlPayload::Payload(p) = nlhdr.nl_payload() {
-                let handle = p.attrs().get_attr_handle();
-                if let Ok(u) = handle.get_attr_payload_as::<u16>(CtrlAttr::FamilyId) {
-                    res = Ok(u);
-                }
-            }
-        }
-
-        res
+        Ok(*self.get_family_info(family_name).await?.id())
     }
 
     /// Convenience function for resolving a [`str`] containing the
@@ -347,47 +816,118 @@ impl NlRouter {
         family_name: &str,
         mcast_name: &str,
     ) -> Result<u32, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
-        let mut res = Err(RouterError::new(format!(
-            "Failed to resolve multicast group ID for family name {family_name}, multicast group name {mcast_name}"
-        )));
+        self.get_family_info(family_name)
+            .await?
+            .mcast_group(mcast_name)
+            .map(|group| *group.id())
+            .ok_or_else(|| {
+                RouterError::new(format!(
+                    "Failed to resolve multicast group ID for family name {family_name}, multicast group name {mcast_name}"
+                ))
+            })
+    }
+
+    /// Cache the generic netlink families resolved by this router if [`true`]
+    /// is passed in.
+    ///
+    /// While the cache is enabled, [`NlRouter::get_family_info`],
+    /// [`NlRouter::resolve_genl_family`], [`NlRouter::resolve_nl_mcast_group`]
+    /// and [`NlRouter::lookup_id`] only query the kernel for families that are
+    /// not cached. The router joins the `notify` group of `nlctrl` and drops a
+    /// family from the cache when the kernel announces that it was registered,
+    /// unregistered or changed its multicast groups. These notifications are
+    /// only delivered to the multicast receiver if the socket joined the group
+    /// itself. Leaving the group with [`NlRouter::drop_mcast_membership`] only
+    /// stops delivering them while the cache is enabled. The group is left
+    /// when the cache is disabled.
+    pub async fn enable_genl_cache(
+        &self,
+        enable: bool,
+    ) -> Result<(), RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        if !enable {
+            let cache = self.core.lock().set_genl_cache(None);
+            if let Some(cache) = cache.filter(|cache| !cache.deliver_notifications()) {
+                self.socket
+                    .drop_mcast_membership(Groups::new_groups(&[cache.notify_group()]))?;
+            }
+            return Ok(());
+        }
+        if self.core.lock().genl_cache().is_some() {
+            return Ok(());
+        }
+
+        let ctrl = self.get_family_info("nlctrl").await?;
+        let group = ctrl
+            .mcast_group("notify")
+            .map(|group| *group.id())
+            .ok_or_else(|| RouterError::new("nlctrl has no notify multicast group"))?;
+        let joined = self
+            .socket
+            .list_mcast_membership()?
+            .to_vec()
+            .contains(&group);
+        if !joined {
+            self.socket
+                .add_mcast_membership(Groups::new_groups(&[group]))?;
+        }
+        let mut cache = GenlCache::new(group, joined);
+        cache.insert(cache.generation(), ctrl);
+        self.core.lock().set_genl_cache(Some(cache));
+        Ok(())
+    }
+
+    /// Cache family descriptions requested at `generation` if the cache is
+    /// enabled.
+    fn cache_families<I>(&self, generation: Option<u64>, families: I)
+    where
+        I: IntoIterator<Item = GenlFamilyInfo>,
+    {
+        if let (Some(generation), Some(cache)) = (generation, self.core.lock().genl_cache_mut()) {
+            for family in families {
+                cache.insert(generation, family);
+            }
+        }
+    }
+
+    /// Get the description of the generic netlink family `family_name`,
+    /// including its operations and multicast groups.
+    pub async fn get_family_info(
+        &self,
+        family_name: &str,
+    ) -> Result<GenlFamilyInfo, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let generation = {
+            let core = self.core.lock();
+            match core.genl_cache() {
+                Some(cache) => match cache.family(family_name) {
+                    Some(info) => return Ok(info.clone()),
+                    None => Some(cache.generation()),
+                },
+                None => None,
+            }
+        };
 
         let nlhdrs = self.get_genl_family(family_name).await?;
         for nlhdr in nlhdrs {
             if let NlPayload::Payload(p) = nlhdr.nl_payload() {
-                let handle = p.attrs().get_attr_handle();
-                let mcast_groups = handle.get_nested_attributes::<Index>(CtrlAttr::McastGroups)?;
-                if let Some(id) = mcast_groups.iter().find_map(|item| {
-                    let nested_attrs = item.get_attr_handle::<CtrlAttrMcastGrp>().ok()?;
-                    let string = nested_attrs
-                        .get_attr_payload_as_with_len::<String>(CtrlAttrMcastGrp::Name)
-                        .ok()?;
-                    if string.as_str() == mcast_name {
-                        nested_attrs
-                            .get_attr_payload_as::<u32>(CtrlAttrMcastGrp::Id)
-                            .ok()
-                    } else {
-                        None
-                    }
-                }) {
-                    res = Ok(id);
-                }
+                let info = GenlFamilyInfo::parse(p)?;
+                self.cache_families(generation, once(info.clone()));
+                return Ok(info);
             }
         }
 
-        res
+        Err(RouterError::new(format!(
+            "Generic netlink family {family_name} was not found"
+        )))
     }
 
-    /// Look up netlink family and multicast group name by ID.
-    pub async fn lookup_id(
+    /// Get the descriptions of all generic netlink families registered with
+    /// the kernel.
+    pub async fn list_families(
         &self,
-        id: u32,
-    ) -> Result<(String, String), RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
-        let mut res = Err(RouterError::new(
-            "ID does not correspond to a multicast group",
-        ));
-
+    ) -> Result<Vec<GenlFamilyInfo>, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let generation = self.core.lock().genl_cache().map(GenlCache::generation);
         let mut recv = self
-            .send::<_, _, u16, Genlmsghdr<u8, u16>>(
+            .send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
                 GenlId::Ctrl,
                 NlmF::DUMP,
                 NlPayload::Payload(
@@ -399,71 +939,171 @@ impl NlRouter {
                 ),
             )
             .await?;
-        while let Some(res_msg) = recv.next().await {
-            let msg = res_msg?;
-
-            if let NlPayload::Payload(p) = msg.nl_payload() {
-                let attributes = p.attrs().get_attr_handle();
-                let name =
-                    attributes.get_attr_payload_as_with_len::<String>(CtrlAttr::FamilyName)?;
-                let groups = match attributes.get_nested_attributes::<Index>(CtrlAttr::McastGroups)
-                {
-                    Ok(grps) => grps,
-                    Err(_) => continue,
-                };
-                for group_by_index in groups.iter() {
-                    let attributes = group_by_index.get_attr_handle::<CtrlAttrMcastGrp>()?;
-                    if let Ok(mcid) = attributes.get_attr_payload_as::<u32>(CtrlAttrMcastGrp::Id) {
-                        if mcid == id {
-                            let mcast_name = attributes
-                                .get_attr_payload_as_with_len::<String>(CtrlAttrMcastGrp::Name)?;
-                            res = Ok((name.clone(), mcast_name));
-                        }
-                    }
-                }
+        let mut families = Vec::new();
+        while let Some(msg) = recv.next().await {
+            if let NlPayload::Payload(p) = msg?.nl_payload() {
+                families.push(GenlFamilyInfo::parse(p)?);
             }
         }
+        self.cache_families(generation, families.iter().cloned());
 
-        res
+        Ok(families)
+    }
+
+    /// Dump the attribute policies of the generic netlink family `family_name`.
+    ///
+    /// If `cmd` is set, only the policies used by that command are returned.
+    /// Policy dumps are supported by Linux 5.8 and newer.
+    pub async fn resolve_genl_policy(
+        &self,
+        family_name: &str,
+        cmd: Option<u8>,
+    ) -> Result<FamilyPolicy, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let mut attrs = once(
+            NlattrBuilder::default()
+                .nla_type(
+                    AttrTypeBuilder::default()
+                        .nla_type(CtrlAttr::FamilyName)
+                        .build()?,
+                )
+                .nla_payload(family_name)
+                .build()?,
+        )
+        .collect::<GenlBuffer<_, _>>();
+        if let Some(cmd) = cmd {
+            attrs.push(
+                NlattrBuilder::default()
+                    .nla_type(AttrTypeBuilder::default().nla_type(CtrlAttr::Op).build()?)
+                    .nla_payload(u32::from(cmd))
+                    .build()?,
+            );
+        }
+
+        let mut recv = self
+            .send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+                GenlId::Ctrl,
+                NlmF::DUMP,
+                NlPayload::Payload(
+                    GenlmsghdrBuilder::default()
+                        .cmd(CtrlCmd::Getpolicy)
+                        .version(2)
+                        .attrs(attrs)
+                        .build()?,
+                ),
+            )
+            .await?;
+        let mut msgs = Vec::new();
+        while let Some(msg) = recv.next().await {
+            msgs.push(msg?);
+        }
+        Ok(FamilyPolicy::parse(
+            msgs.iter().filter_map(|msg| msg.get_payload()),
+        )?)
+    }
+
+    /// Look up netlink family and multicast group name by ID.
+    pub async fn lookup_id(
+        &self,
+        id: u32,
+    ) -> Result<(String, String), RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        if let Some(names) = self
+            .core
+            .lock()
+            .genl_cache()
+            .and_then(|cache| cache.mcast_group(id))
+            .map(|(family, group)| (family.name().clone(), group.name().clone()))
+        {
+            return Ok(names);
+        }
+        self.list_families()
+            .await?
+            .iter()
+            .find_map(|family| {
+                family
+                    .mcast_groups()
+                    .iter()
+                    .find(|group| *group.id() == id)
+                    .map(|group| (family.name().clone(), group.name().clone()))
+            })
+            .ok_or_else(|| RouterError::new("ID does not correspond to a multicast group"))
     }
 }
 
-impl Drop for NlRouter {
+impl<R: Runtime> Drop for NlRouter<R> {
     fn drop(&mut self) {
-        if self.exit_sender.try_send(()).is_err() {
-            warn!("Failed to send shutdown message; processing thread should exit anyway");
+        if let Some(exit_sender) = self.exit_sender.take() {
+            if exit_sender.send(()).is_err() {
+                warn!("Failed to send shutdown message; processing task should exit anyway");
+            }
         }
     }
 }
 
 /// A handle for receiving and validating all messages that correspond to a request.
+///
+/// The handle implements [`Stream`] so that combinators from crates like
+/// `futures` can be used with it. Because [`NlRouterReceiverHandle::next`] is an
+/// inherent method, `StreamExt::next` must be called with fully qualified syntax.
 pub struct NlRouterReceiverHandle<T, P> {
-    receiver: Receiver<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>,
-    senders: Senders,
-    needs_ack: bool,
-    seq: Option<u32>,
-    next_is_none: bool,
-    next_is_ack: bool,
+    receiver: Receiver<Delivery>,
+    core: Core,
+    validator: ResponseValidator,
+    sleeper: Sleeper,
+    sleep: Option<Sleep>,
+    deadline: Option<Instant>,
+    metadata: Option<RecvMetadata>,
     data: PhantomData<(T, P)>,
 }
 
 impl<T, P> NlRouterReceiverHandle<T, P> {
     fn new(
-        receiver: Receiver<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>,
-        senders: Senders,
-        needs_ack: bool,
-        seq: Option<u32>,
+        receiver: Receiver<Delivery>,
+        core: Core,
+        validator: ResponseValidator,
+        sleeper: Sleeper,
     ) -> Self {
         NlRouterReceiverHandle {
             receiver,
-            senders,
-            needs_ack,
-            seq,
-            next_is_none: false,
-            next_is_ack: false,
+            core,
+            validator,
+            sleeper,
+            sleep: None,
+            deadline: None,
+            metadata: None,
             data: PhantomData,
         }
     }
+
+    /// Get the metadata of the datagram containing the last message returned by
+    /// this handle, such as the multicast group of a notification.
+    pub fn metadata(&self) -> Option<&RecvMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Returns [`true`] if any message received so far had
+    /// [`NlmF::DUMP_INTR`] set, indicating that the dump was modified while
+    /// it was in progress and the results may be inconsistent.
+    pub fn dump_interrupted(&self) -> bool {
+        self.validator.dump_interrupted()
+    }
+
+    /// Set the point in time after which waiting for the next message will fail
+    /// with [`RouterError::Timeout`]. [`None`] waits indefinitely.
+    ///
+    /// For a handle corresponding to a request, a timeout ends the stream of
+    /// responses and the sequence number is no longer tracked by the router.
+    /// Responses that arrive later are dropped.
+    /// For the multicast handle, a timeout is reported but the handle can
+    /// continue to be used after a new deadline is set.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        self.sleep = None;
+    }
+
+    /// Get the deadline for receiving messages on this handle.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
 }
 
 impl<T, P> NlRouterReceiverHandle<T, P>
@@ -478,73 +1118,304 @@ where
         TT: NlType,
         PP: Size + FromBytesWithInput<Input = usize>,
     {
-        if self.next_is_none {
-            return None;
+        poll_fn(|cx| self.poll_next_typed(cx)).await
+    }
+
+    /// Imitates the [`Stream`] API but allows parsing differently typed messages in a
+    /// sequence of messages meant for this receiver.
+    pub fn poll_next_typed<TT, PP>(&mut self, cx: &mut Context<'_>) -> PollTyped<TT, PP>
+    where
+        TT: NlType,
+        PP: Size + FromBytesWithInput<Input = usize>,
+    {
+        if self.validator.is_done() {
+            return Poll::Ready(None);
         }
 
-        let mut msg = match self.receiver.recv().await {
-            Some(untyped) => match untyped {
-                Ok(u) => match u.to_typed::<TT, PP>() {
-                    Ok(t) => t,
-                    Err(e) => {
-                        self.next_is_none = true;
-                        return Some(Err(e));
+        let received = match self.receiver.poll_next_unpin(cx) {
+            Poll::Ready(Some((received, metadata))) => {
+                self.metadata = metadata;
+                received
+            }
+            Poll::Ready(None) => return Poll::Ready(Some(Err(self.validator.closed()))),
+            Poll::Pending => {
+                if let Some(deadline) = self.deadline {
+                    let sleeper = &self.sleeper;
+                    let sleep = self.sleep.get_or_insert_with(|| sleeper(deadline));
+                    if sleep.as_mut().poll(cx).is_ready() {
+                        self.sleep = None;
+                        if let Some(seq) = self.validator.seq() {
+                            self.core.lock().abandon(seq);
+                        }
+                        return Poll::Ready(Some(Err(self.validator.timeout())));
                     }
-                },
-                Err(e) => {
-                    self.next_is_none = true;
-                    return Some(Err(match e.to_typed() {
-                        Ok(e) => e,
-                        Err(e) => e,
-                    }));
                 }
-            },
-            None => {
-                self.next_is_none = true;
-                return Some(Err(RouterError::ClosedChannel));
+                return Poll::Pending;
             }
         };
 
-        let nl_type = Nlmsg::from((*msg.nl_type()).into());
-        if let NlPayload::Ack(_) = msg.nl_payload() {
-            self.next_is_none = true;
-            if !self.needs_ack {
-                return Some(Err(RouterError::UnexpectedAck));
-            }
-        } else if let Some(e) = msg.get_err() {
-            self.next_is_none = true;
-            if self.next_is_ack {
-                return Some(Err(RouterError::NoAck));
-            } else {
-                return Some(Err(RouterError::<TT, PP>::Nlmsgerr(e)));
-            }
-        } else if (!msg.nl_flags().contains(NlmF::MULTI) || nl_type == Nlmsg::Done)
-            && self.seq.is_some()
-        {
-            assert!(!self.next_is_ack);
+        let res = self
+            .validator
+            .validate_with_attr_path(received, |e| self.core.lock().attr_path(e));
+        if let Ok(ref msg) = res {
+            trace!("Router received message: {msg:?}");
+        }
+
+        Poll::Ready(Some(res))
+    }
+}
+
+impl<T, P> Unpin for NlRouterReceiverHandle<T, P> {}
+
+impl<T, P> Stream for NlRouterReceiverHandle<T, P>
+where
+    T: NlType,
+    P: Size + FromBytesWithInput<Input = usize>,
+{
+    type Item = Result<Nlmsghdr<T, P>, RouterError<T, P>>;
 
-            if self.needs_ack {
-                self.next_is_ack = true;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_typed::<T, P>(cx)
+    }
+}
+
+impl<T, P> Drop for NlRouterReceiverHandle<T, P> {
+    fn drop(&mut self) {
+        if let Some(seq) = self.validator.seq() {
+            if self.validator.is_done() {
+                self.core.lock().unregister(seq);
             } else {
-                self.next_is_none = true;
+                self.core.lock().abandon(seq);
             }
-        } else if self.next_is_ack {
-            self.next_is_none = true;
-            return Some(Err(RouterError::NoAck));
         }
+    }
+}
+
+/// Stream of the generic netlink families being registered and unregistered,
+/// for example when the kernel module providing a family is loaded or unloaded.
+///
+/// Multicast messages other than `nlctrl` notifications are skipped.
+pub struct FamilyEvents<R: Runtime = DefaultRuntime> {
+    router: NlRouter<R>,
+    multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+}
+
+impl<R: Runtime> FamilyEvents<R> {
+    /// Join the `notify` group of `nlctrl` with `router` and receive the
+    /// family events delivered to its multicast receiver `multicast`.
+    pub async fn new(
+        router: NlRouter<R>,
+        multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+    ) -> Result<Self, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let group = router.resolve_nl_mcast_group("nlctrl", "notify").await?;
+        router
+            .add_mcast_membership(Groups::new_groups(&[group]))
+            .map_err(|e| e.to_typed().unwrap_or_else(|e| e))?;
+        Ok(FamilyEvents { router, multicast })
+    }
 
-        trace!("Router received message: {msg:?}");
+    /// Get the router used to receive the events.
+    pub fn router(&self) -> &NlRouter<R> {
+        &self.router
+    }
 
-        Some(Ok(msg))
+    /// Set the deadline for receiving the next event. See
+    /// [`NlRouterReceiverHandle::set_deadline`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.multicast.set_deadline(deadline);
     }
 }
 
-impl<T, P> Drop for NlRouterReceiverHandle<T, P> {
-    fn drop(&mut self) {
-        if let Some(seq) = self.seq {
-            if let Ok(mut lock) = self.senders.try_lock() {
-                lock.remove(&seq);
+impl<R: Runtime> Unpin for FamilyEvents<R> {}
+
+impl<R: Runtime> Stream for FamilyEvents<R> {
+    type Item = Result<FamilyEvent, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let events = self.get_mut();
+        loop {
+            let msg = match events
+                .multicast
+                .poll_next_typed::<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(cx)
+            {
+                Poll::Ready(Some(Ok(msg))) => msg,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if *msg.nl_type() != GenlId::Ctrl {
+                continue;
+            }
+            if let Some(payload) = msg.get_payload() {
+                match FamilyEvent::parse(payload) {
+                    Ok(Some(event)) => return Poll::Ready(Some(Ok(event))),
+                    Ok(None) => (),
+                    Err(e) => return Poll::Ready(Some(Err(RouterError::from(e)))),
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test::setup;
+
+    #[test]
+    fn test_multicast_overrun() {
+        setup();
+
+        let (sender, mut receiver) = channel(1);
+        let mut multicast = MCastSender::new(sender);
+        let delivery = |seq| {
+            let msg = NlmsghdrBuilder::default()
+                .nl_type(0u16)
+                .nl_flags(NlmF::empty())
+                .nl_seq(seq)
+                .nl_payload(NlPayload::<u16, Buffer>::Empty)
+                .build()
+                .unwrap();
+            (Ok(msg), None)
+        };
+        let mut next = || match receiver.try_recv() {
+            Ok((Ok(msg), _)) => Ok(*msg.nl_seq()),
+            Ok((Err(e), _)) => Err(e),
+            r => panic!("Unexpected result {r:?}"),
+        };
+
+        // The channel has room for one message per sender plus its capacity.
+        for seq in 0..3 {
+            multicast.send(delivery(seq));
+        }
+        assert_eq!(next().unwrap(), 0);
+        assert_eq!(next().unwrap(), 1);
+
+        multicast.send(delivery(3));
+        assert!(matches!(next(), Err(RouterError::EventsLost)));
+        assert_eq!(next().unwrap(), 3);
+    }
+
+    #[cfg(any(feature = "tokio", feature = "async-io"))]
+    async fn resolve_and_time_out<R>(runtime: R)
+    where
+        R: Runtime,
+    {
+        let (sock, mut multicast) =
+            NlRouter::connect_with_runtime(runtime, NlFamily::Generic, None, Groups::empty())
+                .await
+                .unwrap();
+        let id = sock.resolve_genl_family("nlctrl").await.unwrap();
+        assert_eq!(id, u16::from(GenlId::Ctrl));
+        assert!(sock.core.lock().is_empty());
+
+        multicast.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(
+            multicast.next::<u16, Buffer>().await,
+            Some(Err(RouterError::Timeout))
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn real_test_tokio_runtime() {
+        setup();
+
+        resolve_and_time_out(crate::runtime::TokioRuntime).await;
+    }
+
+    #[cfg(feature = "async-io")]
+    #[test]
+    fn real_test_async_io_runtime() {
+        setup();
+
+        async_io::block_on(resolve_and_time_out(
+            crate::runtime::AsyncIoRuntime::default(),
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn real_test_stream() {
+        setup();
+
+        let (sock, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty())
+            .await
+            .unwrap();
+        let recv = sock
+            .send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+                GenlId::Ctrl,
+                NlmF::DUMP,
+                NlPayload::Payload(
+                    GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                        .cmd(CtrlCmd::Getfamily)
+                        .version(2)
+                        .build()
+                        .unwrap(),
+                ),
+            )
+            .await
+            .unwrap();
+        let msgs = recv.collect::<Vec<_>>().await;
+        assert!(msgs.len() > 1);
+        assert!(msgs.iter().all(|msg| msg.is_ok()));
+        assert!(sock.core.lock().is_empty());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn real_test_netns() {
+        use crate::test::empty_netns;
+
+        setup();
+
+        let Some(netns) = empty_netns() else {
+            return;
+        };
+        let config = crate::router::RouterConfigBuilder::default()
+            .netns(netns)
+            .build()
+            .unwrap();
+        let (sock, _) = NlRouter::connect_with_config(
+            DefaultRuntime::default(),
+            NlFamily::Generic,
+            None,
+            Groups::empty(),
+            config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            sock.resolve_genl_family("nlctrl").await.unwrap(),
+            u16::from(GenlId::Ctrl)
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn real_test_family_events() {
+        setup();
+
+        let (sock, multicast) = NlRouter::connect(NlFamily::Generic, None, Groups::empty())
+            .await
+            .unwrap();
+        let group = sock
+            .resolve_nl_mcast_group("nlctrl", "notify")
+            .await
+            .unwrap();
+        let mut events = FamilyEvents::new(sock, multicast).await.unwrap();
+        assert!(events
+            .router()
+            .list_mcast_membership()
+            .unwrap()
+            .to_vec()
+            .contains(&group));
+
+        events.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(
+            events.next().await,
+            Some(Err(RouterError::Timeout))
+        ));
+    }
+}
@@ -9,6 +9,9 @@
 //!   [`NlRouterReceiverHandle`][crate::router::synchronous::NlRouterReceiverHandle] that corresponds
 //!   to the request.
 //! * Errors in packet reception and parsing are broadcast to all receivers.
+//! * If the kernel drops messages because the socket receive buffer is full,
+//!   [`RouterError::EventsLost`][crate::err::RouterError::EventsLost] is sent only
+//!   to the multicast receiver so that in-flight requests are unaffected.
 //! * An [`NlRouterReceiverHandle`][crate::router::synchronous::NlRouterReceiverHandle]
 //!   can be used as an iterator and will return [`None`] either when all
 //!   messages corresponding to the request have been received or there is a fatal error.
@@ -33,13 +36,82 @@
 //! group so that messages can be interleaved and still processed in the correct
 //! order by the handle associated with the request that generated it.
 //!
+//! The sequence number tracking, message dispatch and response validation shared by
+//! both implementations lives in [`protocol`], which performs no IO and can be
+//! used to drive a netlink socket from any event loop.
+//!
 //! ## Features
 //! The `async` feature exposed by `cargo` allows the socket to use
-//! Rust's [tokio](https://tokio.rs) for async IO.
+//! Rust's [tokio](https://tokio.rs) for async IO. The asynchronous API itself is
+//! runtime-agnostic and is available with the `async-core` feature. The `tokio` and
+//! `async-io` features provide backends for it. See the `runtime` module for details.
+//!
+//! The `mio` feature implements `mio::event::Source` for the synchronous
+//! `NlRouter` and the `calloop` feature provides `synchronous::NlRouterSource`.
+//! Both are intended for routers created with
+//! `synchronous::NlRouter::connect_caller_driven`.
+//!
+//! ## Configuration
+//! [`RouterConfig`] describes the receive buffers, the network namespace and
+//! the mode of a router and is passed to `NlRouter::connect_with_config`.
 
 /// Asynchronous packet routing functionality.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub mod asynchronous;
 /// Synchronous packet routing functionality.
 #[cfg(feature = "sync")]
 pub mod synchronous;
+
+pub mod protocol;
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+use derive_builder::Builder;
+#[cfg(any(feature = "sync", feature = "async-core"))]
+use getset::Getters;
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+use crate::{socket::netns::NetNs, utils::BufferPoolConfig};
+
+/// Configuration of a router created with `NlRouter::connect_with_config`.
+///
+/// ```
+/// use neli::{
+///     router::RouterConfigBuilder,
+///     utils::{BufferGrowth, BufferPoolConfigBuilder},
+/// };
+///
+/// let config = RouterConfigBuilder::default()
+///     .buffer_pool(
+///         BufferPoolConfigBuilder::default()
+///             .growth(BufferGrowth::OnDemand)
+///             .build()
+///             .unwrap(),
+///     )
+///     .caller_driven(true)
+///     .build()
+///     .unwrap();
+/// assert!(config.netns().is_none());
+/// ```
+#[cfg(any(feature = "sync", feature = "async-core"))]
+#[derive(Builder, Getters, Debug, Default)]
+#[builder(pattern = "owned")]
+pub struct RouterConfig {
+    /// Buffers used for receive operations.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    buffer_pool: BufferPoolConfig,
+    /// Network namespace in which the socket is created. The socket is created on
+    /// a helper thread so the calling thread stays in its namespace.
+    #[getset(get = "pub")]
+    #[builder(setter(strip_option), default)]
+    netns: Option<NetNs>,
+    /// If [`true`], the synchronous router does not spawn a background thread and
+    /// messages are only read when the router or one of its receiver handles is
+    /// polled. See `synchronous::NlRouter::connect_caller_driven`.
+    ///
+    /// The asynchronous router always routes messages on a background task and
+    /// ignores this option.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    caller_driven: bool,
+}
@@ -0,0 +1,869 @@
+//! Runtime-independent routing state shared by all router implementations.
+//!
+//! [`RouterCore`] performs sequence number allocation and tracks outstanding
+//! requests. Received bytes are passed in and a [`Dispatch`] decision is returned
+//! for each message without performing any IO. [`ResponseValidator`] implements the
+//! ACK and multipart message validation for the stream of messages belonging to a
+//! single request.
+//!
+//! [`GenlCache`] stores generic netlink family descriptions and drops them when
+//! `nlctrl` announces that a family or one of its multicast groups changed.
+//!
+//! Both [`NlRouter`][crate::router::synchronous::NlRouter] implementations are
+//! built on top of these types, and they can also be used to drive a netlink socket
+//! from a custom event loop.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Cursor,
+};
+
+use crate::{
+    attr::{AttrPath, AttrSchema},
+    consts::{
+        genl::{CtrlAttr, CtrlCmd},
+        nl::{GenlId, NlType, NlmF, Nlmsg},
+    },
+    err::{Nlmsgerr, NlmsghdrErr, RouterError, SocketError},
+    genl::{GenlFamilyInfo, GenlMcastGroup, Genlmsghdr},
+    iter::NlBufferIter,
+    nl::{NlPayload, Nlmsghdr},
+    socket::RecvMetadata,
+    types::Buffer,
+    utils::Groups,
+    FromBytesWithInput, Size, ToBytes,
+};
+
+/// A message or error received on a socket before it has been parsed into a
+/// specific type.
+pub type Received = Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>;
+
+/// A [`Received`] message or error along with the metadata of the datagram it
+/// was received in. Errors that did not come from a datagram have no metadata.
+pub type Delivery = (Received, Option<RecvMetadata>);
+
+/// Describes where a received message or error should be delivered.
+#[derive(Debug)]
+pub enum Dispatch {
+    /// Deliver the message to the receiver registered for the given sequence number.
+    Response(u32, Nlmsghdr<u16, Buffer>),
+    /// Deliver the message or error to the multicast receiver.
+    Multicast(Received),
+    /// Deliver the error to the receivers of all outstanding requests.
+    Broadcast(RouterError<u16, Buffer>),
+    /// Drop a response to a request whose receiver stopped waiting for it with
+    /// [`RouterCore::abandon`].
+    Late(u32, Nlmsghdr<u16, Buffer>),
+}
+
+/// Number of abandoned sequence numbers remembered by a [`RouterCore`].
+const MAX_ABANDONED: usize = 256;
+
+/// Sequence number allocation and request tracking for a single socket.
+///
+/// `S` is the type used to deliver messages to the receiver of a request,
+/// typically the sending half of a channel.
+pub struct RouterCore<S> {
+    pid: u32,
+    seq: u32,
+    senders: HashMap<u32, S>,
+    abandoned: VecDeque<u32>,
+    attr_schemas: HashMap<u16, (usize, AttrSchema)>,
+    genl_cache: Option<GenlCache>,
+}
+
+impl<S> RouterCore<S> {
+    /// Create a new router state for a socket bound to `pid`.
+    pub fn new(pid: u32) -> Self {
+        RouterCore {
+            pid,
+            seq: 0,
+            senders: HashMap::new(),
+            abandoned: VecDeque::new(),
+            attr_schemas: HashMap::new(),
+            genl_cache: None,
+        }
+    }
+
+    /// Get the PID of the socket associated with this state.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Allocate the next sequence number.
+    pub fn next_seq(&mut self) -> u32 {
+        let next = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        next
+    }
+
+    /// Register a request so that responses with the given sequence number are
+    /// dispatched to `sender`.
+    pub fn register(&mut self, seq: u32, sender: S) {
+        self.abandoned.retain(|abandoned| *abandoned != seq);
+        self.senders.insert(seq, sender);
+    }
+
+    /// Stop tracking the request with the given sequence number.
+    pub fn unregister(&mut self, seq: u32) -> Option<S> {
+        self.senders.remove(&seq)
+    }
+
+    /// Stop tracking the request with the given sequence number before all of
+    /// its responses were received, for example after a timeout.
+    ///
+    /// Responses that arrive later are dispatched as [`Dispatch::Late`] instead
+    /// of an error for all outstanding requests. Only the most recently
+    /// abandoned sequence numbers are remembered.
+    pub fn abandon(&mut self, seq: u32) -> Option<S> {
+        if self.abandoned.len() == MAX_ABANDONED {
+            self.abandoned.pop_front();
+        }
+        self.abandoned.push_back(seq);
+        self.senders.remove(&seq)
+    }
+
+    /// Get the sender registered for the given sequence number.
+    pub fn sender(&self, seq: u32) -> Option<&S> {
+        self.senders.get(&seq)
+    }
+
+    /// Iterate over all outstanding requests.
+    pub fn senders(&self) -> impl Iterator<Item = (u32, &S)> {
+        self.senders.iter().map(|(seq, sender)| (*seq, sender))
+    }
+
+    /// Returns [`true`] if there are no outstanding requests.
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    /// Name the attributes of requests with the message type `nl_type` so that
+    /// errors with an extended ACK pointing to an attribute of the request
+    /// report its path.
+    ///
+    /// `header_len` is the length of the family header following the netlink
+    /// header in these requests.
+    pub fn register_attr_schema(&mut self, nl_type: u16, header_len: usize, schema: AttrSchema) {
+        self.attr_schemas.insert(nl_type, (header_len, schema));
+    }
+
+    /// Resolve the attribute the extended ACK of `err` points to with the
+    /// schema registered for the type of the failed request.
+    ///
+    /// The path is only resolved if the kernel included the request payload in
+    /// the error, which it does unless `NETLINK_CAP_ACK` is enabled on the
+    /// socket.
+    pub fn attr_path(&self, err: &Nlmsgerr<NlmsghdrErr<u16, Buffer>>) -> Option<AttrPath> {
+        let (header_len, schema) = self.attr_schemas.get(err.nlmsg().nl_type())?;
+        let mut request = Cursor::new(Vec::new());
+        err.nlmsg().to_bytes(&mut request).ok()?;
+        err.get_ext_ack()
+            .ok()?
+            .attr_path(request.get_ref(), *header_len, schema)
+    }
+
+    /// Set or remove the cache of generic netlink families kept up to date by
+    /// [`RouterCore::dispatch_iter`].
+    pub fn set_genl_cache(&mut self, cache: Option<GenlCache>) -> Option<GenlCache> {
+        std::mem::replace(&mut self.genl_cache, cache)
+    }
+
+    /// Get the cache of generic netlink families if it is enabled.
+    pub fn genl_cache(&self) -> Option<&GenlCache> {
+        self.genl_cache.as_ref()
+    }
+
+    /// Get the cache of generic netlink families mutably if it is enabled.
+    pub fn genl_cache_mut(&mut self) -> Option<&mut GenlCache> {
+        self.genl_cache.as_mut()
+    }
+
+    /// Determine where a single parsed message should be delivered.
+    ///
+    /// `groups` is the set of multicast groups the containing packet was
+    /// received on. The generic netlink cache is not updated by this method.
+    pub fn dispatch(
+        &self,
+        msg: Result<Nlmsghdr<u16, Buffer>, SocketError>,
+        groups: &Groups,
+    ) -> Dispatch {
+        match msg {
+            Ok(m) => {
+                if !groups.is_empty() {
+                    Dispatch::Multicast(Ok(m))
+                } else if self.senders.contains_key(m.nl_seq()) && *m.nl_pid() == self.pid {
+                    Dispatch::Response(*m.nl_seq(), m)
+                } else if *m.nl_pid() == self.pid && self.abandoned.contains(m.nl_seq()) {
+                    Dispatch::Late(*m.nl_seq(), m)
+                } else {
+                    Dispatch::Broadcast(RouterError::BadSeqOrPid(m))
+                }
+            }
+            Err(e) => Dispatch::Broadcast(RouterError::from(e)),
+        }
+    }
+
+    /// Parse all messages in a buffer received from the socket and determine
+    /// where each one should be delivered.
+    pub fn dispatch_buffer<B>(&mut self, buffer: B, groups: &Groups) -> Vec<Dispatch>
+    where
+        B: AsRef<[u8]>,
+    {
+        self.dispatch_iter(NlBufferIter::new(Cursor::new(buffer)), groups)
+    }
+
+    /// Determine where each message produced by an iterator over a received
+    /// buffer should be delivered.
+    ///
+    /// `nlctrl` notifications update the generic netlink cache and are only
+    /// delivered if the cache does not consume them.
+    pub fn dispatch_iter<I>(&mut self, iter: I, groups: &Groups) -> Vec<Dispatch>
+    where
+        I: IntoIterator<Item = Result<Nlmsghdr<u16, Buffer>, SocketError>>,
+    {
+        let mut dispatches = Vec::new();
+        for msg in iter {
+            if let (Ok(m), Some(cache)) = (&msg, self.genl_cache.as_mut()) {
+                if !groups.is_empty() && cache.update(m) {
+                    continue;
+                }
+            }
+            dispatches.push(self.dispatch(msg, groups));
+        }
+        dispatches
+    }
+
+    /// Determine where an error returned when receiving from the socket should be
+    /// delivered.
+    ///
+    /// Returns [`None`] if the error does not need to be reported, for example
+    /// when a nonblocking socket has no data available.
+    pub fn dispatch_error(&self, e: SocketError) -> Option<Dispatch> {
+        match e {
+            SocketError::Io(ref io_e) if io_e.kind() == std::io::ErrorKind::WouldBlock => None,
+            SocketError::Io(ref io_e) if io_e.raw_os_error() == Some(libc::ENOBUFS) => {
+                Some(Dispatch::Multicast(Err(RouterError::EventsLost)))
+            }
+            e => Some(Dispatch::Broadcast(RouterError::from(e))),
+        }
+    }
+}
+
+/// Generic netlink families resolved through `nlctrl`.
+///
+/// Entries are dropped when a `CtrlCmd::Newfamily`, `CtrlCmd::Delfamily`,
+/// `CtrlCmd::NewmcastGrp` or `CtrlCmd::DelmcastGrp` notification for the family
+/// is received on the `notify` group of `nlctrl`, so they are resolved again
+/// after a kernel module is loaded or unloaded.
+#[derive(Debug)]
+pub struct GenlCache {
+    families: HashMap<String, GenlFamilyInfo>,
+    notify_group: u32,
+    deliver_notifications: bool,
+    generation: u64,
+}
+
+impl GenlCache {
+    /// Create an empty cache updated by notifications on the multicast group
+    /// `notify_group`.
+    ///
+    /// If `deliver_notifications` is [`false`], notifications are consumed by
+    /// the cache instead of being delivered to the multicast receiver.
+    pub fn new(notify_group: u32, deliver_notifications: bool) -> Self {
+        GenlCache {
+            families: HashMap::new(),
+            notify_group,
+            deliver_notifications,
+            generation: 0,
+        }
+    }
+
+    /// Get the ID of the `notify` multicast group of `nlctrl`.
+    pub fn notify_group(&self) -> u32 {
+        self.notify_group
+    }
+
+    /// Returns [`true`] if notifications are also delivered to the multicast
+    /// receiver.
+    pub fn deliver_notifications(&self) -> bool {
+        self.deliver_notifications
+    }
+
+    /// Set whether notifications are also delivered to the multicast receiver.
+    pub fn set_deliver_notifications(&mut self, deliver: bool) {
+        self.deliver_notifications = deliver;
+    }
+
+    /// Get the number of times entries were invalidated.
+    ///
+    /// Read this before requesting a family description and pass it to
+    /// [`GenlCache::insert`] so that a description superseded by a
+    /// notification is not cached.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Get the cached description of the family `name`.
+    pub fn family(&self, name: &str) -> Option<&GenlFamilyInfo> {
+        self.families.get(name)
+    }
+
+    /// Get the cached family and multicast group with the multicast group ID
+    /// `id`.
+    pub fn mcast_group(&self, id: u32) -> Option<(&GenlFamilyInfo, &GenlMcastGroup)> {
+        self.families.values().find_map(|family| {
+            family
+                .mcast_groups()
+                .iter()
+                .find(|group| *group.id() == id)
+                .map(|group| (family, group))
+        })
+    }
+
+    /// Cache a family description requested when the cache was at
+    /// `generation`. The description is discarded if entries were invalidated
+    /// since then.
+    pub fn insert(&mut self, generation: u64, info: GenlFamilyInfo) {
+        if generation == self.generation {
+            self.families.insert(info.name().clone(), info);
+        }
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self) {
+        self.families.clear();
+        self.generation += 1;
+    }
+
+    /// Invalidate the entries affected by a message received on a multicast
+    /// group.
+    ///
+    /// Returns [`true`] if the message is an `nlctrl` notification that should
+    /// not be delivered to the multicast receiver.
+    pub fn update(&mut self, msg: &Nlmsghdr<u16, Buffer>) -> bool {
+        if *msg.nl_type() != u16::from(GenlId::Ctrl) {
+            return false;
+        }
+        let Some(payload) = msg.get_payload() else {
+            return false;
+        };
+        let name = Genlmsghdr::<CtrlCmd, CtrlAttr>::from_bytes_with_input(
+            &mut Cursor::new(payload.as_ref()),
+            payload.as_ref().len(),
+        )
+        .ok()
+        .filter(|genl| {
+            matches!(
+                genl.cmd(),
+                CtrlCmd::Newfamily
+                    | CtrlCmd::Delfamily
+                    | CtrlCmd::NewmcastGrp
+                    | CtrlCmd::DelmcastGrp
+            )
+        })
+        .map(|genl| {
+            genl.attrs()
+                .get_attr_handle()
+                .get_attr_payload_as_with_len::<String>(CtrlAttr::FamilyName)
+        });
+        match name {
+            Some(Ok(name)) => {
+                self.families.remove(&name);
+                self.generation += 1;
+            }
+            Some(Err(_)) => self.clear(),
+            None => return false,
+        }
+        !self.deliver_notifications
+    }
+}
+
+/// Validation state for the stream of messages received by a single receiver.
+///
+/// This tracks ACK handling, the end of multipart messages and dump
+/// interruption for a request, or passes messages through for a multicast
+/// receiver.
+#[derive(Debug)]
+pub struct ResponseValidator {
+    needs_ack: bool,
+    seq: Option<u32>,
+    next_is_none: bool,
+    next_is_ack: bool,
+    dump_interrupted: bool,
+}
+
+impl ResponseValidator {
+    /// Create validation state for a request sent with the given flags and
+    /// sequence number.
+    pub fn request(flags: NlmF, seq: u32) -> Self {
+        ResponseValidator {
+            needs_ack: flags.contains(NlmF::ACK) && !flags.contains(NlmF::DUMP),
+            seq: Some(seq),
+            next_is_none: false,
+            next_is_ack: false,
+            dump_interrupted: false,
+        }
+    }
+
+    /// Create validation state for a receiver of multicast messages.
+    pub fn multicast() -> Self {
+        ResponseValidator {
+            needs_ack: false,
+            seq: None,
+            next_is_none: false,
+            next_is_ack: false,
+            dump_interrupted: false,
+        }
+    }
+
+    /// Get the sequence number of the request, or [`None`] for a multicast
+    /// receiver.
+    pub fn seq(&self) -> Option<u32> {
+        self.seq
+    }
+
+    /// Returns [`true`] if no more messages are expected.
+    pub fn is_done(&self) -> bool {
+        self.next_is_none
+    }
+
+    /// Returns [`true`] if any message validated so far had
+    /// [`NlmF::DUMP_INTR`] set.
+    pub fn dump_interrupted(&self) -> bool {
+        self.dump_interrupted
+    }
+
+    /// Record that the deadline for the next message passed.
+    ///
+    /// A timeout ends the stream of messages for a request but not for a
+    /// multicast receiver.
+    pub fn timeout<T, P>(&mut self) -> RouterError<T, P> {
+        if self.seq.is_some() {
+            self.next_is_none = true;
+        }
+        RouterError::Timeout
+    }
+
+    /// Record that the channel delivering messages was closed.
+    pub fn closed<T, P>(&mut self) -> RouterError<T, P> {
+        self.next_is_none = true;
+        RouterError::ClosedChannel
+    }
+
+    /// Parse and validate the next message or error delivered to this receiver.
+    pub fn validate<T, P>(
+        &mut self,
+        received: Received,
+    ) -> Result<Nlmsghdr<T, P>, RouterError<T, P>>
+    where
+        T: NlType,
+        P: Size + FromBytesWithInput<Input = usize>,
+    {
+        let mut msg = match received {
+            Ok(u) => match u.to_typed::<T, P>() {
+                Ok(t) => t,
+                Err(e) => {
+                    self.next_is_none = true;
+                    return Err(e);
+                }
+            },
+            Err(e) => {
+                if !matches!(e, RouterError::EventsLost) {
+                    self.next_is_none = true;
+                }
+                return Err(match e.to_typed() {
+                    Ok(e) => e,
+                    Err(e) => e,
+                });
+            }
+        };
+
+        if msg.nl_flags().contains(NlmF::DUMP_INTR) {
+            self.dump_interrupted = true;
+        }
+
+        let nl_type = Nlmsg::from((*msg.nl_type()).into());
+        if let NlPayload::Ack(_) = msg.nl_payload() {
+            self.next_is_none = true;
+            if !self.needs_ack {
+                return Err(RouterError::UnexpectedAck);
+            }
+        } else if let Some(e) = msg.get_err() {
+            // An error in place of the expected ACK is reported with its errno.
+            self.next_is_none = true;
+            return Err(RouterError::Nlmsgerr(e, None));
+        } else if (!msg.nl_flags().contains(NlmF::MULTI) || nl_type == Nlmsg::Done)
+            && self.seq.is_some()
+        {
+            if self.next_is_ack {
+                // A second final response was received before the ACK.
+                self.next_is_none = true;
+                return Err(RouterError::NoAck);
+            }
+
+            if self.needs_ack {
+                self.next_is_ack = true;
+            } else {
+                self.next_is_none = true;
+            }
+        } else if self.next_is_ack {
+            self.next_is_none = true;
+            return Err(RouterError::NoAck);
+        }
+
+        Ok(msg)
+    }
+
+    /// Validate the next message like [`ResponseValidator::validate`] and name
+    /// the attribute an error response points to with `attr_path`, for example
+    /// [`RouterCore::attr_path`].
+    ///
+    /// `attr_path` is only called for error responses.
+    pub fn validate_with_attr_path<T, P, F>(
+        &mut self,
+        received: Received,
+        attr_path: F,
+    ) -> Result<Nlmsghdr<T, P>, RouterError<T, P>>
+    where
+        T: NlType,
+        P: Size + FromBytesWithInput<Input = usize>,
+        F: FnOnce(&Nlmsgerr<NlmsghdrErr<u16, Buffer>>) -> Option<AttrPath>,
+    {
+        let path = match received {
+            Ok(ref m) => match m.nl_payload() {
+                NlPayload::Err(e) => attr_path(e),
+                _ => None,
+            },
+            Err(_) => None,
+        };
+        match self.validate(received) {
+            Err(RouterError::Nlmsgerr(e, None)) => Err(RouterError::Nlmsgerr(e, path)),
+            res => res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{io, sync::Arc};
+
+    use crate::consts::nl::NlmsgerrAttr;
+
+    fn msg_bytes(nl_type: u16, nl_flags: u16, seq: u32, pid: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(16 + payload.len() as u32).to_ne_bytes());
+        bytes.extend_from_slice(&nl_type.to_ne_bytes());
+        bytes.extend_from_slice(&nl_flags.to_ne_bytes());
+        bytes.extend_from_slice(&seq.to_ne_bytes());
+        bytes.extend_from_slice(&pid.to_ne_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    fn ack_bytes(seq: u32, pid: u32) -> Vec<u8> {
+        err_bytes(0, seq, pid)
+    }
+
+    fn err_bytes(error: i32, seq: u32, pid: u32) -> Vec<u8> {
+        let mut payload = error.to_ne_bytes().to_vec();
+        payload.extend(msg_bytes(16, NlmF::REQUEST.bits(), seq, pid, &[]));
+        msg_bytes(Nlmsg::Error.into(), NlmF::CAPPED.bits(), seq, pid, &payload)
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let mut core = RouterCore::<()>::new(5);
+        let seq = core.next_seq();
+        core.register(seq, ());
+        assert_eq!(core.next_seq(), seq + 1);
+
+        let mut buffer = msg_bytes(16, 0, seq, 5, &[0; 4]);
+        buffer.extend(msg_bytes(16, 0, seq + 1, 5, &[0; 4]));
+        buffer.extend(msg_bytes(16, 0, seq, 6, &[0; 4]));
+
+        let dispatches = core.dispatch_buffer(&buffer, &Groups::empty());
+        assert!(matches!(dispatches[0], Dispatch::Response(s, _) if s == seq));
+        assert!(matches!(
+            dispatches[1],
+            Dispatch::Broadcast(RouterError::BadSeqOrPid(_))
+        ));
+        assert!(matches!(
+            dispatches[2],
+            Dispatch::Broadcast(RouterError::BadSeqOrPid(_))
+        ));
+
+        let dispatches = core.dispatch_buffer(&buffer, &Groups::new_groups(&[1]));
+        assert_eq!(dispatches.len(), 3);
+        assert!(dispatches
+            .iter()
+            .all(|d| matches!(d, Dispatch::Multicast(Ok(_)))));
+
+        assert!(core.unregister(seq).is_some());
+        assert!(core.is_empty());
+    }
+
+    #[test]
+    fn test_genl_cache() {
+        let family = |cmd: CtrlCmd, name: &str| {
+            let mut payload = vec![u8::from(cmd), 2, 0, 0];
+            payload.extend(attr_bytes(CtrlAttr::FamilyId.into(), &[0x20, 0, 0, 0]));
+            let mut name = name.as_bytes().to_vec();
+            name.push(0);
+            payload.extend(attr_bytes(CtrlAttr::FamilyName.into(), &name));
+            payload.resize(payload.len().next_multiple_of(4), 0);
+            msg_bytes(GenlId::Ctrl.into(), 0, 0, 0, &payload)
+        };
+        let info = |buffer: &[u8]| {
+            let msg = NlBufferIter::<u16, Buffer, _>::new(Cursor::new(buffer))
+                .next()
+                .unwrap()
+                .unwrap();
+            let genl = Genlmsghdr::<CtrlCmd, CtrlAttr>::from_bytes_with_input(
+                &mut Cursor::new(msg.get_payload().unwrap().as_ref()),
+                msg.get_payload().unwrap().as_ref().len(),
+            )
+            .unwrap();
+            GenlFamilyInfo::parse(&genl).unwrap()
+        };
+
+        let mut core = RouterCore::<()>::new(5);
+        core.set_genl_cache(Some(GenlCache::new(0x10, false)));
+        let generation = core.genl_cache().unwrap().generation();
+        let cache = core.genl_cache_mut().unwrap();
+        cache.insert(generation, info(&family(CtrlCmd::Newfamily, "first")));
+        cache.insert(generation, info(&family(CtrlCmd::Newfamily, "second")));
+        assert_eq!(*cache.family("first").unwrap().id(), 0x20);
+
+        let groups = Groups::new_groups(&[0x10]);
+        assert!(core
+            .dispatch_buffer(family(CtrlCmd::Delfamily, "first"), &groups)
+            .is_empty());
+        let cache = core.genl_cache_mut().unwrap();
+        assert!(cache.family("first").is_none());
+        assert!(cache.family("second").is_some());
+        cache.insert(generation, info(&family(CtrlCmd::Newfamily, "first")));
+        assert!(cache.family("first").is_none());
+
+        cache.set_deliver_notifications(true);
+        assert!(matches!(
+            core.dispatch_buffer(family(CtrlCmd::Newfamily, "second"), &groups)[..],
+            [Dispatch::Multicast(Ok(_))]
+        ));
+        assert!(core.genl_cache().unwrap().family("second").is_none());
+    }
+
+    #[test]
+    fn test_abandon() {
+        let mut core = RouterCore::<()>::new(5);
+        let seq = core.next_seq();
+        core.register(seq, ());
+        assert!(core.abandon(seq).is_some());
+        assert!(core.is_empty());
+
+        let mut buffer = msg_bytes(16, 0, seq, 5, &[0; 4]);
+        buffer.extend(msg_bytes(16, 0, seq + 1, 5, &[0; 4]));
+        let dispatches = core.dispatch_buffer(&buffer, &Groups::empty());
+        assert!(matches!(dispatches[0], Dispatch::Late(s, _) if s == seq));
+        assert!(matches!(
+            dispatches[1],
+            Dispatch::Broadcast(RouterError::BadSeqOrPid(_))
+        ));
+
+        for seq in 1..=MAX_ABANDONED as u32 {
+            core.abandon(seq);
+        }
+        assert!(matches!(
+            core.dispatch_buffer(msg_bytes(16, 0, seq, 5, &[0; 4]), &Groups::empty())[..],
+            [Dispatch::Broadcast(RouterError::BadSeqOrPid(_))]
+        ));
+    }
+
+    #[test]
+    fn test_dispatch_error() {
+        let core = RouterCore::<()>::new(5);
+        assert!(core
+            .dispatch_error(SocketError::Io(Arc::new(io::Error::from(
+                io::ErrorKind::WouldBlock
+            ))))
+            .is_none());
+        assert!(matches!(
+            core.dispatch_error(SocketError::Io(Arc::new(io::Error::from_raw_os_error(
+                libc::ENOBUFS
+            )))),
+            Some(Dispatch::Multicast(Err(RouterError::EventsLost)))
+        ));
+        assert!(matches!(
+            core.dispatch_error(SocketError::Io(Arc::new(io::Error::from_raw_os_error(
+                libc::EBADF
+            )))),
+            Some(Dispatch::Broadcast(RouterError::Socket(_)))
+        ));
+    }
+
+    #[test]
+    fn test_validate_ack() {
+        let mut core = RouterCore::<()>::new(5);
+        let seq = core.next_seq();
+        core.register(seq, ());
+
+        let mut buffer = msg_bytes(16, 0, seq, 5, &[0; 4]);
+        buffer.extend(ack_bytes(seq, 5));
+        let mut msgs = core
+            .dispatch_buffer(&buffer, &Groups::empty())
+            .into_iter()
+            .map(|d| match d {
+                Dispatch::Response(_, m) => m,
+                d => panic!("Unexpected dispatch {d:?}"),
+            });
+
+        let mut validator = ResponseValidator::request(NlmF::ACK, seq);
+        assert!(validator
+            .validate::<u16, Buffer>(Ok(msgs.next().unwrap()))
+            .is_ok());
+        assert!(!validator.is_done());
+        assert!(validator
+            .validate::<u16, Buffer>(Ok(msgs.next().unwrap()))
+            .is_ok());
+        assert!(validator.is_done());
+
+        let mut buffer = msg_bytes(16, 0, seq, 5, &[0; 4]);
+        buffer.extend(err_bytes(-libc::EPERM, seq, 5));
+        let mut msgs = core
+            .dispatch_buffer(&buffer, &Groups::empty())
+            .into_iter()
+            .map(|d| match d {
+                Dispatch::Response(_, m) => m,
+                d => panic!("Unexpected dispatch {d:?}"),
+            });
+        let mut validator = ResponseValidator::request(NlmF::ACK, seq);
+        assert!(validator
+            .validate::<u16, Buffer>(Ok(msgs.next().unwrap()))
+            .is_ok());
+        assert!(matches!(
+            validator.validate::<u16, Buffer>(Ok(msgs.next().unwrap())),
+            Err(RouterError::Nlmsgerr(e, _)) if *e.error() == -libc::EPERM
+        ));
+        assert!(validator.is_done());
+
+        let mut validator = ResponseValidator::request(NlmF::empty(), seq);
+        let mut msgs = core
+            .dispatch_buffer(ack_bytes(seq, 5), &Groups::empty())
+            .into_iter();
+        match msgs.next() {
+            Some(Dispatch::Response(_, m)) => assert!(matches!(
+                validator.validate::<u16, Buffer>(Ok(m)),
+                Err(RouterError::UnexpectedAck)
+            )),
+            d => panic!("Unexpected dispatch {d:?}"),
+        }
+        assert!(validator.is_done());
+    }
+
+    #[test]
+    fn test_validate_repeated_response() {
+        let mut core = RouterCore::<()>::new(5);
+        let seq = core.next_seq();
+        core.register(seq, ());
+
+        let mut buffer = msg_bytes(16, 0, seq, 5, &[0; 4]);
+        buffer.extend(msg_bytes(16, 0, seq, 5, &[0; 4]));
+        let mut msgs = core
+            .dispatch_buffer(&buffer, &Groups::empty())
+            .into_iter()
+            .map(|d| match d {
+                Dispatch::Response(_, m) => m,
+                d => panic!("Unexpected dispatch {d:?}"),
+            });
+
+        let mut validator = ResponseValidator::request(NlmF::ACK, seq);
+        assert!(validator
+            .validate::<u16, Buffer>(Ok(msgs.next().unwrap()))
+            .is_ok());
+        assert!(matches!(
+            validator.validate::<u16, Buffer>(Ok(msgs.next().unwrap())),
+            Err(RouterError::NoAck)
+        ));
+        assert!(validator.is_done());
+    }
+
+    #[test]
+    fn test_validate_multicast() {
+        let mut validator = ResponseValidator::multicast();
+        assert!(matches!(
+            validator.validate::<u16, Buffer>(Err(RouterError::EventsLost)),
+            Err(RouterError::EventsLost)
+        ));
+        assert!(!validator.is_done());
+        assert!(matches!(
+            validator.timeout::<u16, Buffer>(),
+            RouterError::Timeout
+        ));
+        assert!(!validator.is_done());
+        assert!(matches!(
+            validator.closed::<u16, Buffer>(),
+            RouterError::ClosedChannel
+        ));
+        assert!(validator.is_done());
+    }
+
+    fn attr_bytes(ty: u16, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(4 + payload.len() as u16).to_ne_bytes());
+        bytes.extend_from_slice(&ty.to_ne_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_attr_schema() {
+        let mut core = RouterCore::<()>::new(5);
+        core.register_attr_schema(
+            20,
+            4,
+            AttrSchema::new().nested(
+                1u16,
+                "OUTER",
+                AttrSchema::new().attr(2u16, "INNER").attr(3u16, "MISSING"),
+            ),
+        );
+        let seq = core.next_seq();
+        core.register(seq, ());
+
+        let mut request_payload = vec![0; 4];
+        request_payload.extend(attr_bytes(1, &attr_bytes(2, &[0; 4])));
+        let mut payload = (-libc::EINVAL).to_ne_bytes().to_vec();
+        payload.extend(msg_bytes(20, 0, seq, 5, &request_payload));
+        payload.extend(attr_bytes(NlmsgerrAttr::MissType.into(), &[3, 0, 0, 0]));
+        payload.extend(attr_bytes(
+            NlmsgerrAttr::MissNest.into(),
+            &20u32.to_ne_bytes(),
+        ));
+        let buffer = msg_bytes(Nlmsg::Error.into(), NlmF::ACK_TLVS.bits(), seq, 5, &payload);
+
+        let msg = match core.dispatch_buffer(&buffer, &Groups::empty()).pop() {
+            Some(Dispatch::Response(_, m)) => m,
+            d => panic!("Unexpected dispatch {d:?}"),
+        };
+        let mut validator = ResponseValidator::request(NlmF::ACK, seq);
+        let err = validator
+            .validate_with_attr_path::<u16, Buffer, _>(Ok(msg), |e| core.attr_path(e))
+            .unwrap_err();
+        match err {
+            RouterError::Nlmsgerr(ref e, Some(ref path)) => {
+                assert_eq!(e.get_ext_ack().unwrap().miss_type(), &Some(3));
+                assert_eq!(path.to_string(), "OUTER/MISSING");
+            }
+            ref r => panic!("Unexpected result {r:?}"),
+        }
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "missing attribute OUTER/MISSING: {}",
+                io::Error::from_raw_os_error(libc::EINVAL)
+            ),
+        );
+    }
+}
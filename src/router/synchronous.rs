@@ -1,42 +1,61 @@
 use std::{
-    collections::{HashMap, HashSet},
     io,
     iter::once,
     marker::PhantomData,
     mem::MaybeUninit,
-    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
     sync::{
-        mpsc::{channel, Receiver, Sender},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
         Arc,
     },
     thread::spawn,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "calloop")]
+use calloop::{
+    generic::{Generic, NoIoDrop},
+    EventSource, Interest, Mode, Poll, PostAction, Readiness, Token, TokenFactory,
+};
 use log::{debug, error, trace, warn};
 use parking_lot::Mutex;
 
 use crate::{
+    attr::AttrSchema,
     consts::{
-        genl::{CtrlAttr, CtrlAttrMcastGrp, CtrlCmd, Index},
-        nl::{GenlId, NlType, NlmF, Nlmsg},
+        genl::{CtrlAttr, CtrlCmd},
+        nl::{GenlId, NlType, NlmF},
         socket::NlFamily,
     },
-    err::{RouterError, SocketError},
-    genl::{AttrTypeBuilder, Genlmsghdr, GenlmsghdrBuilder, NlattrBuilder, NoUserHeader},
+    err::RouterError,
+    genl::{
+        AttrTypeBuilder, FamilyEvent, GenlFamilyInfo, Genlmsghdr, GenlmsghdrBuilder, NlattrBuilder,
+        NoUserHeader,
+    },
     nl::{NlPayload, Nlmsghdr, NlmsghdrBuilder},
-    socket::synchronous::NlSocketHandle,
+    policy::FamilyPolicy,
+    router::{
+        protocol::{Delivery, Dispatch, GenlCache, ResponseValidator, RouterCore},
+        RouterConfig,
+    },
+    socket::{
+        capture::Capture, filter::SocketFilter, synchronous::NlSocketHandle, NlSocket,
+        RecvMetadata, Transport,
+    },
     types::{Buffer, GenlBuffer, NlBuffer},
     utils::{Groups, NetlinkBitArray},
     FromBytesWithInput, Size, ToBytes,
 };
 
+const DEFAULT_DUMP_ATTEMPTS: usize = 3;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 type GenlFamily = Result<
     NlBuffer<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>,
     RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>,
 >;
-type MCastSender = Sender<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>;
-type Senders =
-    Arc<Mutex<HashMap<u32, Sender<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>>>>;
+type MCastSender = Sender<Delivery>;
+type Core = Arc<Mutex<RouterCore<Sender<Delivery>>>>;
 type ConnectReturn<T> = Result<
     (
         T,
@@ -44,102 +63,167 @@ type ConnectReturn<T> = Result<
     ),
     RouterError<u16, Buffer>,
 >;
-type ProcThreadReturn = Result<
-    (
-        Receiver<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>,
-        OwnedFd,
-    ),
-    RouterError<u16, Buffer>,
->;
 
 /// A high-level handle for sending messages and generating a handle that validates
 /// all of the received messages.
-pub struct NlRouter {
-    socket: Arc<NlSocketHandle>,
-    seq: Mutex<u32>,
-    senders: Senders,
-    fd: OwnedFd,
+///
+/// Received messages are routed to receiver handles either by a background
+/// thread ([`NlRouter::connect`]) or by the caller
+/// ([`NlRouter::connect_caller_driven`]).
+///
+/// The router is generic over the [`Transport`] used to exchange messages, which
+/// defaults to a netlink socket. Routers using other transports are created
+/// with [`NlRouter::with_transport`].
+pub struct NlRouter<S = NlSocket> {
+    socket: Arc<NlSocketHandle<S>>,
+    core: Core,
+    driver: Arc<Driver<S>>,
+    default_timeout: Mutex<Option<Duration>>,
+    dump_attempts: Mutex<usize>,
+    fd: Option<OwnedFd>,
 }
 
-fn processing_loop(
-    socket: &Arc<NlSocketHandle>,
-    senders: &Senders,
-    multicast_sender: &MCastSender,
-    iter: impl Iterator<Item = Result<Nlmsghdr<u16, Buffer>, SocketError>>,
-    group: Groups,
-) {
-    for msg in iter {
-        trace!("Message received: {msg:?}");
-        let mut seqs_to_remove = HashSet::new();
-        match msg {
-            Ok(m) => {
-                let seq = *m.nl_seq();
-                let lock = senders.lock();
-                if !group.is_empty() {
-                    if multicast_sender.send(Ok(m)).is_err() {
-                        warn!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                    }
-                } else if let Some(sender) = lock.get(m.nl_seq()) {
-                    if &socket.pid() == m.nl_pid() {
-                        if sender.send(Ok(m)).is_err() {
-                            error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                            seqs_to_remove.insert(seq);
-                        }
-                    } else {
-                        for (seq, sender) in lock.iter() {
-                            if sender
-                                .send(Err(RouterError::BadSeqOrPid(m.clone())))
-                                .is_err()
-                            {
-                                error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                                seqs_to_remove.insert(*seq);
-                            }
+/// Reads messages from the socket and routes them to the receivers.
+struct Driver<S> {
+    socket: Arc<NlSocketHandle<S>>,
+    core: Core,
+    multicast_sender: MCastSender,
+    // Serializes reading and routing so that messages for a receiver are
+    // delivered in the order in which they were received.
+    reading: Mutex<()>,
+}
+
+impl<S> Driver<S>
+where
+    S: Transport,
+{
+    fn new(
+        socket: NlSocketHandle<S>,
+    ) -> Result<(Arc<Self>, Receiver<Delivery>), RouterError<u16, Buffer>> {
+        socket.set_nonblock()?;
+        let core = Arc::new(Mutex::new(RouterCore::new(socket.pid())));
+        let (multicast_sender, multicast_receiver) = channel();
+        Ok((
+            Arc::new(Driver {
+                socket: Arc::new(socket),
+                core,
+                multicast_sender,
+                reading: Mutex::new(()),
+            }),
+            multicast_receiver,
+        ))
+    }
+
+    fn process_readable(&self) -> Result<(), RouterError<u16, Buffer>> {
+        let _guard = self.reading.lock();
+        loop {
+            match self.socket.recv::<u16, Buffer>() {
+                Ok((iter, group)) => {
+                    let metadata = iter.metadata().cloned();
+                    let dispatches = self.core.lock().dispatch_iter(iter, &group);
+                    deliver(
+                        &self.core,
+                        &self.multicast_sender,
+                        dispatches,
+                        metadata.as_ref(),
+                    );
+                }
+                Err(e) => {
+                    let dispatch = self.core.lock().dispatch_error(e);
+                    match dispatch {
+                        None => return Ok(()),
+                        Some(Dispatch::Broadcast(e)) => {
+                            deliver(
+                                &self.core,
+                                &self.multicast_sender,
+                                once(Dispatch::Broadcast(e.clone())),
+                                None,
+                            );
+                            return Err(e);
                         }
-                    }
-                } else {
-                    for (seq, sender) in lock.iter() {
-                        if sender
-                            .send(Err(RouterError::BadSeqOrPid(m.clone())))
-                            .is_err()
-                        {
-                            error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                            seqs_to_remove.insert(*seq);
+                        Some(dispatch) => {
+                            deliver(&self.core, &self.multicast_sender, once(dispatch), None)
                         }
                     }
                 }
             }
-            Err(e) => {
-                let lock = senders.lock();
-                for (seq, sender) in lock.iter() {
-                    if sender.send(Err(RouterError::from(e.clone()))).is_err() {
-                        error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-                        seqs_to_remove.insert(*seq);
-                    }
-                }
-            }
-        }
-        for seq in seqs_to_remove {
-            senders.lock().remove(&seq);
         }
     }
 }
 
-fn error_handling(senders: &Senders, e: SocketError) {
-    let mut seqs_to_remove = HashSet::new();
-    let mut lock = senders.lock();
-    for (seq, sender) in lock.iter() {
-        if sender.send(Err(RouterError::from(e.clone()))).is_err() {
-            seqs_to_remove.insert(*seq);
-            error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
-            break;
+/// Type-erased [`Driver`] used by receiver handles of caller-driven routers.
+trait Drive: Send + Sync {
+    /// Wait up to `timeout` for the socket to become readable and process any
+    /// pending messages.
+    fn drive(&self, timeout: Duration);
+}
+
+impl<S> Drive for Driver<S>
+where
+    S: Transport,
+{
+    fn drive(&self, timeout: Duration) {
+        match self.socket.transport().wait_readable(timeout) {
+            Ok(true) => {
+                // Errors have already been delivered to the receivers.
+                let _ = self.process_readable();
+            }
+            Ok(false) => (),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => warn!("Failed to wait for socket to become readable: {e}"),
         }
     }
-    for seq in seqs_to_remove {
-        lock.remove(&seq);
+}
+
+fn deliver(
+    core: &Core,
+    multicast_sender: &MCastSender,
+    dispatches: impl IntoIterator<Item = Dispatch>,
+    metadata: Option<&RecvMetadata>,
+) {
+    let mut lock = core.lock();
+    for dispatch in dispatches {
+        match dispatch {
+            Dispatch::Response(seq, m) => {
+                if let Some(sender) = lock.sender(seq) {
+                    if sender.send((Ok(m), metadata.cloned())).is_err() {
+                        error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
+                        lock.unregister(seq);
+                    }
+                }
+            }
+            Dispatch::Multicast(received) => {
+                if let Err(RouterError::EventsLost) = received {
+                    warn!("Socket receive buffer overrun; multicast messages were lost");
+                }
+                if multicast_sender
+                    .send((received, metadata.cloned()))
+                    .is_err()
+                {
+                    warn!("{}", RouterError::<u16, Buffer>::ClosedChannel);
+                }
+            }
+            Dispatch::Late(seq, m) => {
+                trace!("Dropping response to abandoned request {seq}: {m:?}");
+            }
+            Dispatch::Broadcast(e) => {
+                let closed = lock
+                    .senders()
+                    .filter(|(_, sender)| sender.send((Err(e.clone()), None)).is_err())
+                    .map(|(seq, _)| seq)
+                    .collect::<Vec<_>>();
+                for seq in closed {
+                    error!("{}", RouterError::<u16, Buffer>::ClosedChannel);
+                    lock.unregister(seq);
+                }
+            }
+        }
     }
 }
 
-fn spawn_processing_thread(socket: Arc<NlSocketHandle>, senders: Senders) -> ProcThreadReturn {
+fn spawn_processing_thread(
+    driver: Arc<Driver<NlSocket>>,
+) -> Result<OwnedFd, RouterError<u16, Buffer>> {
     let owned_event_fd = {
         let event_fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
         if event_fd < 0 {
@@ -160,8 +244,6 @@ fn spawn_processing_thread(socket: Arc<NlSocketHandle>, senders: Senders) -> Pro
         unsafe { OwnedFd::from_raw_fd(duped_event_fd) }
     };
 
-    socket.set_nonblock()?;
-
     let epoll_fd = unsafe { libc::epoll_create(1) };
     if epoll_fd < 0 {
         return Err(RouterError::Io(
@@ -170,7 +252,6 @@ fn spawn_processing_thread(socket: Arc<NlSocketHandle>, senders: Senders) -> Pro
     }
     let epoll = unsafe { OwnedFd::from_raw_fd(epoll_fd) };
 
-    let (multicast_sender, multicast_receiver) = channel();
     spawn(move || {
         const EVENT_FD_TOKEN: u64 = 0;
         const SOCKET_TOKEN: u64 = 1;
@@ -195,7 +276,7 @@ fn spawn_processing_thread(socket: Arc<NlSocketHandle>, senders: Senders) -> Pro
             libc::epoll_ctl(
                 epoll_fd.as_raw_fd(),
                 libc::EPOLL_CTL_ADD,
-                socket.as_raw_fd(),
+                driver.socket.as_raw_fd(),
                 &mut socket_epoll_event as *mut _,
             )
         };
@@ -237,66 +318,70 @@ fn spawn_processing_thread(socket: Arc<NlSocketHandle>, senders: Senders) -> Pro
                         }
                     }
                 } else if unsafe { event.assume_init_ref() }.u64 == SOCKET_TOKEN {
-                    match socket.recv::<u16, Buffer>() {
-                        Ok((iter, group)) => {
-                            processing_loop(&socket, &senders, &multicast_sender, iter, group)
-                        }
-                        Err(e) => {
-                            if let SocketError::Io(ref io_e) = e {
-                                if io_e.kind() != io::ErrorKind::WouldBlock {
-                                    error_handling(&senders, e);
-                                }
-                            } else {
-                                error_handling(&senders, e);
-                            }
-                        }
-                    }
+                    // Errors have already been delivered to the receivers.
+                    let _ = driver.process_readable();
                 }
             }
         }
     });
-    Ok((multicast_receiver, owned_duped_event_fd))
+    Ok(owned_duped_event_fd)
 }
 
 impl NlRouter {
     /// Equivalent of `socket` and `bind` calls.
+    ///
+    /// Received messages are routed to receiver handles by a background thread.
     pub fn connect(proto: NlFamily, pid: Option<u32>, groups: Groups) -> ConnectReturn<Self> {
-        let socket = Arc::new(NlSocketHandle::connect(proto, pid, groups)?);
-        let senders = Arc::new(Mutex::new(HashMap::default()));
-        let (multicast_receiver, fd) =
-            spawn_processing_thread(Arc::clone(&socket), Arc::clone(&senders))?;
-        let multicast_receiver =
-            NlRouterReceiverHandle::new(multicast_receiver, Arc::clone(&senders), false, None);
-        Ok((
-            NlRouter {
-                socket,
-                senders,
-                seq: Mutex::new(0),
-                fd,
-            },
-            multicast_receiver,
-        ))
+        Self::connect_with_config(proto, pid, groups, RouterConfig::default())
     }
 
-    /// Join multicast groups for a socket.
-    pub fn add_mcast_membership(&self, groups: Groups) -> Result<(), RouterError<u16, Buffer>> {
-        self.socket
-            .add_mcast_membership(groups)
-            .map_err(RouterError::from)
+    /// Equivalent of `socket` and `bind` calls without spawning a background thread.
+    ///
+    /// Messages are only read from the socket when [`NlRouter::process_readable`]
+    /// is called, typically after the file descriptor returned by
+    /// [`AsRawFd::as_raw_fd`] is reported as readable by an event loop. Receiver
+    /// handles created by this router also read from the socket while waiting for
+    /// a message, so they can be used without an event loop as well.
+    pub fn connect_caller_driven(
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> ConnectReturn<Self> {
+        let config = RouterConfig {
+            caller_driven: true,
+            ..Default::default()
+        };
+        Self::connect_with_config(proto, pid, groups, config)
     }
 
-    /// Leave multicast groups for a socket.
-    pub fn drop_mcast_membership(&self, groups: Groups) -> Result<(), RouterError<u16, Buffer>> {
-        self.socket
-            .drop_mcast_membership(groups)
-            .map_err(RouterError::from)
+    /// Equivalent of [`NlRouter::connect`] with the receive buffers, network
+    /// namespace and mode described by `config`.
+    pub fn connect_with_config(
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+        config: RouterConfig,
+    ) -> ConnectReturn<Self> {
+        let socket = match config.netns {
+            Some(ref netns) => NlSocketHandle::connect_in_netns(netns, proto, pid, groups)?,
+            None => NlSocketHandle::connect(proto, pid, groups)?,
+        };
+        Self::connect_with_mode(
+            socket.with_buffer_pool(config.buffer_pool),
+            !config.caller_driven,
+        )
     }
 
-    /// List joined groups for a socket.
-    pub fn list_mcast_membership(&self) -> Result<NetlinkBitArray, RouterError<u16, Buffer>> {
-        self.socket
-            .list_mcast_membership()
-            .map_err(RouterError::from)
+    fn connect_with_mode(socket: NlSocketHandle, spawn_thread: bool) -> ConnectReturn<Self> {
+        // Report the group number of multicast messages for groups above 32.
+        socket.enable_pktinfo(true)?;
+        let (driver, multicast_receiver) = Driver::new(socket)?;
+        let fd = if spawn_thread {
+            Some(spawn_processing_thread(Arc::clone(&driver))?)
+        } else {
+            None
+        };
+        Ok(Self::from_driver(driver, multicast_receiver, fd))
     }
 
     /// If [`true`] is passed in, enable extended ACKs for this socket. If [`false`]
@@ -312,6 +397,37 @@ impl NlRouter {
         self.socket.get_ext_ack_enabled().map_err(RouterError::from)
     }
 
+    /// Name the attributes of requests with the message type `nl_type` so that
+    /// [`RouterError::Nlmsgerr`] errors for these requests report the path of
+    /// the attribute the extended ACK points to.
+    ///
+    /// `header_len` is the length of the family header following the netlink
+    /// header, for example [`Ifinfomsg::header_size`][crate::Header::header_size]
+    /// for link requests.
+    pub fn register_attr_schema<T>(&self, nl_type: T, header_len: usize, schema: AttrSchema)
+    where
+        T: NlType,
+    {
+        self.core
+            .lock()
+            .register_attr_schema(nl_type.into(), header_len, schema);
+    }
+
+    /// If [`true`] is passed in, suppress `ENOBUFS` errors when the socket receive
+    /// buffer overruns. If [`false`] is passed in, report overruns as errors.
+    pub fn enable_no_enobufs(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_no_enobufs(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if `ENOBUFS` errors are suppressed for this socket.
+    pub fn get_no_enobufs_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket
+            .get_no_enobufs_enabled()
+            .map_err(RouterError::from)
+    }
+
     /// If [`true`] is passed in, enable strict checking for this socket. If [`false`]
     /// is passed in, disable strict checking for for this socket.
     /// Only supported by `NlFamily::Route` sockets.
@@ -331,16 +447,325 @@ impl NlRouter {
             .map_err(RouterError::from)
     }
 
+    /// Set the size of the receive buffer for the socket.
+    ///
+    /// See [`NlSocket::set_recv_buffer_size`][crate::socket::NlSocket::set_recv_buffer_size]
+    /// for more information.
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_recv_buffer_size(size)
+            .map_err(RouterError::from)
+    }
+
+    /// Set the size of the send buffer for the socket.
+    ///
+    /// The kernel will double the value provided to account for bookkeeping overhead.
+    /// The doubled value is capped by the value in `/proc/sys/net/core/wmem_max`.
+    ///
+    /// See `socket(7)` documentation for `SO_SNDBUF` for more information.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_send_buffer_size(size)
+            .map_err(RouterError::from)
+    }
+
+    /// Return the size of the send buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_send_buffer_size(&self) -> Result<usize, RouterError<u16, Buffer>> {
+        self.socket
+            .get_send_buffer_size()
+            .map_err(RouterError::from)
+    }
+
+    /// Return the size of the receive buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_recv_buffer_size(&self) -> Result<usize, RouterError<u16, Buffer>> {
+        self.socket
+            .get_recv_buffer_size()
+            .map_err(RouterError::from)
+    }
+
+    /// Set the size of the receive buffer for the socket, ignoring the limit in
+    /// `/proc/sys/net/core/rmem_max`.
+    ///
+    /// Requires `CAP_NET_ADMIN`. See `socket(7)` documentation for `SO_RCVBUFFORCE`
+    /// for more information.
+    pub fn force_recv_buffer_size(&self, size: usize) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .force_recv_buffer_size(size)
+            .map_err(RouterError::from)
+    }
+
+    /// Set the timeout for blocking receive operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A receive operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_recv_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_recv_timeout(timeout)
+            .map_err(RouterError::from)
+    }
+
+    /// Return the timeout for blocking receive operations.
+    pub fn get_recv_timeout(&self) -> Result<Option<Duration>, RouterError<u16, Buffer>> {
+        self.socket.get_recv_timeout().map_err(RouterError::from)
+    }
+
+    /// Set the timeout for blocking send operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A send operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_send_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .set_send_timeout(timeout)
+            .map_err(RouterError::from)
+    }
+
+    /// Return the timeout for blocking send operations.
+    pub fn get_send_timeout(&self) -> Result<Option<Duration>, RouterError<u16, Buffer>> {
+        self.socket.get_send_timeout().map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, report errors delivering multicast messages sent
+    /// from this socket. If [`false`] is passed in, only report errors if no
+    /// listener received the message.
+    pub fn enable_broadcast_error(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_broadcast_error(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if multicast delivery errors are reported for this socket.
+    pub fn get_broadcast_error_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket
+            .get_broadcast_error_enabled()
+            .map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, omit the payload of the original request from
+    /// error messages. If [`false`] is passed in, include the original request.
+    pub fn enable_cap_ack(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_cap_ack(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if the original request is omitted from error messages.
+    pub fn get_cap_ack_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket.get_cap_ack_enabled().map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, ask the kernel to attach the destination multicast
+    /// group to each received message as `NETLINK_PKTINFO` control data. If
+    /// [`false`] is passed in, disable this.
+    pub fn enable_pktinfo(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_pktinfo(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if `NETLINK_PKTINFO` control data is enabled for this
+    /// socket.
+    pub fn get_pktinfo_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket.get_pktinfo_enabled().map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, receive multicast messages from all network
+    /// namespaces that have an ID assigned in the namespace of the socket. If
+    /// [`false`] is passed in, only receive messages from the namespace of the
+    /// socket.
+    ///
+    /// Requires `CAP_NET_BROADCAST`.
+    pub fn enable_listen_all_nsid(&self, enable: bool) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .enable_listen_all_nsid(enable)
+            .map_err(RouterError::from)
+    }
+
+    /// Return [`true`] if multicast messages from all network namespaces are
+    /// received by this socket.
+    pub fn get_listen_all_nsid_enabled(&self) -> Result<bool, RouterError<u16, Buffer>> {
+        self.socket
+            .get_listen_all_nsid_enabled()
+            .map_err(RouterError::from)
+    }
+
+    /// Attach the classic BPF program compiled from `filter` to the socket,
+    /// replacing any filter that is already attached.
+    ///
+    /// Messages addressed to the port ID of the router are always accepted so
+    /// that responses to requests are not dropped.
+    pub fn attach_filter(&self, filter: &SocketFilter) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket
+            .attach_filter(&filter.clone().accept_pid(self.socket.pid()))
+            .map_err(RouterError::from)
+    }
+
+    /// Remove the filter attached with [`NlRouter::attach_filter`].
+    pub fn detach_filter(&self) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket.detach_filter().map_err(RouterError::from)
+    }
+}
+
+impl<S> NlRouter<S>
+where
+    S: Transport + 'static,
+{
+    /// Create a caller-driven router that exchanges messages using `transport`.
+    ///
+    /// See [`NlRouter::connect_caller_driven`] for how messages are routed.
+    pub fn with_transport(transport: S) -> ConnectReturn<Self> {
+        let (driver, multicast_receiver) = Driver::new(NlSocketHandle::with_transport(transport)?)?;
+        Ok(Self::from_driver(driver, multicast_receiver, None))
+    }
+
+    fn from_driver(
+        driver: Arc<Driver<S>>,
+        multicast_receiver: Receiver<Delivery>,
+        fd: Option<OwnedFd>,
+    ) -> (
+        Self,
+        NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+    ) {
+        let router = NlRouter {
+            socket: Arc::clone(&driver.socket),
+            core: Arc::clone(&driver.core),
+            driver,
+            default_timeout: Mutex::new(None),
+            dump_attempts: Mutex::new(DEFAULT_DUMP_ATTEMPTS),
+            fd,
+        };
+        let multicast_receiver =
+            router.new_handle(multicast_receiver, ResponseValidator::multicast());
+        (router, multicast_receiver)
+    }
+
+    /// Read all messages pending on the socket and route them to the
+    /// corresponding receiver handles.
+    ///
+    /// This is intended for routers created with
+    /// [`NlRouter::connect_caller_driven`] and returns once reading from the socket
+    /// would block. Socket errors are delivered to all receivers and also
+    /// returned.
+    pub fn process_readable(&self) -> Result<(), RouterError<u16, Buffer>> {
+        self.driver.process_readable()
+    }
+
+    fn new_handle<T, P>(
+        &self,
+        receiver: Receiver<Delivery>,
+        validator: ResponseValidator,
+    ) -> NlRouterReceiverHandle<T, P> {
+        NlRouterReceiverHandle::new(
+            receiver,
+            Arc::clone(&self.core),
+            self.fd
+                .is_none()
+                .then(|| Arc::clone(&self.driver) as Arc<dyn Drive>),
+            validator,
+        )
+    }
+
+    /// Join multicast groups for a socket.
+    pub fn add_mcast_membership(&self, groups: Groups) -> Result<(), RouterError<u16, Buffer>> {
+        if let Some(cache) = self.core.lock().genl_cache_mut() {
+            if groups.as_groups().contains(&cache.notify_group()) {
+                cache.set_deliver_notifications(true);
+            }
+        }
+        self.socket
+            .add_mcast_membership(groups)
+            .map_err(RouterError::from)
+    }
+
+    /// Leave multicast groups for a socket.
+    ///
+    /// While the generic netlink cache is enabled, the socket stays in the
+    /// `notify` group of `nlctrl` to keep the cache up to date, but its
+    /// notifications are no longer delivered to the multicast receiver.
+    pub fn drop_mcast_membership(
+        &self,
+        mut groups: Groups,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        if let Some(cache) = self.core.lock().genl_cache_mut() {
+            let group = cache.notify_group();
+            if groups.as_groups().contains(&group) {
+                cache.set_deliver_notifications(false);
+                groups.remove_groups(&[group]);
+            }
+        }
+        self.socket
+            .drop_mcast_membership(groups)
+            .map_err(RouterError::from)
+    }
+
+    /// List joined groups for a socket.
+    pub fn list_mcast_membership(&self) -> Result<NetlinkBitArray, RouterError<u16, Buffer>> {
+        self.socket
+            .list_mcast_membership()
+            .map_err(RouterError::from)
+    }
+
     /// Get the PID for the current socket.
     pub fn pid(&self) -> u32 {
         self.socket.pid()
     }
 
+    /// Pass every datagram sent or received by the router to `capture`.
+    ///
+    /// Passing [`None`] stops an active capture.
+    pub fn set_capture(
+        &self,
+        capture: Option<Arc<dyn Capture>>,
+    ) -> Result<(), RouterError<u16, Buffer>> {
+        self.socket.set_capture(capture).map_err(RouterError::from)
+    }
+
+    /// If [`true`] is passed in, grow the receive buffer for datagrams that do not
+    /// fit instead of reporting [`SocketError::Truncated`][crate::err::SocketError::Truncated]
+    /// to all receivers.
+    pub fn enable_adaptive_buffers(&self, enable: bool) {
+        self.socket.enable_adaptive_buffers(enable)
+    }
+
+    /// Set the timeout applied to every request sent after this call.
+    ///
+    /// Each [`NlRouterReceiverHandle`] returned from [`NlRouter::send`] will have a
+    /// deadline of the time of sending plus this timeout. [`None`] disables the
+    /// timeout and is the default.
+    pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+        *self.default_timeout.lock() = timeout;
+    }
+
+    /// Get the timeout applied to every request sent by this router.
+    pub fn default_timeout(&self) -> Option<Duration> {
+        *self.default_timeout.lock()
+    }
+
+    /// Set the maximum number of times [`NlRouter::send_consistent_dump`] will
+    /// issue a dump request before giving up on getting a consistent snapshot.
+    ///
+    /// At least one attempt is always made.
+    pub fn set_consistent_dump_attempts(&self, attempts: usize) {
+        *self.dump_attempts.lock() = attempts;
+    }
+
+    /// Get the maximum number of attempts made by
+    /// [`NlRouter::send_consistent_dump`].
+    pub fn consistent_dump_attempts(&self) -> usize {
+        *self.dump_attempts.lock()
+    }
+
     fn next_seq(&self) -> u32 {
-        let mut lock = self.seq.lock();
-        let next = *lock;
-        *lock = lock.wrapping_add(1);
-        next
+        self.core.lock().next_seq()
     }
 
     /// Send a message and return a handle for receiving responses from this message.
@@ -367,17 +792,118 @@ impl NlRouter {
 
         let (sender, receiver) = channel();
         let seq = *msg.nl_seq();
-        self.senders.lock().insert(seq, sender);
+        self.core.lock().register(seq, sender);
         let flags = *msg.nl_flags();
 
-        self.socket.send(&msg)?;
+        if let Err(e) = self.socket.send(&msg) {
+            self.core.lock().unregister(seq);
+            return Err(RouterError::from(e));
+        }
 
-        Ok(NlRouterReceiverHandle::new(
-            receiver,
-            Arc::clone(&self.senders),
-            flags.contains(NlmF::ACK) && !flags.contains(NlmF::DUMP),
-            Some(seq),
-        ))
+        let mut handle = self.new_handle(receiver, ResponseValidator::request(flags, seq));
+        handle.set_deadline(self.default_timeout().map(|t| Instant::now() + t));
+        Ok(handle)
+    }
+
+    /// Send a batch of messages in a single system call and return a handle for
+    /// receiving responses for each message.
+    ///
+    /// Each message is assigned its own sequence number and the returned handles are
+    /// in the same order as the messages provided. This allows ACKs and errors to be
+    /// correlated with the message that caused them.
+    /// An empty batch sends nothing and returns no handles.
+    pub fn send_batch<ST, SP, RT, RP, I>(
+        &self,
+        msgs: I,
+    ) -> Result<Vec<NlRouterReceiverHandle<RT, RP>>, RouterError<ST, SP>>
+    where
+        ST: NlType,
+        SP: Size + ToBytes,
+        I: IntoIterator<Item = (ST, NlmF, NlPayload<ST, SP>)>,
+    {
+        let mut buffer = NlBuffer::new();
+        for (nl_type, nl_flags, nl_payload) in msgs {
+            buffer.push(
+                NlmsghdrBuilder::default()
+                    .nl_type(nl_type)
+                    .nl_flags(
+                        // Required for messages
+                        nl_flags | NlmF::REQUEST,
+                    )
+                    .nl_pid(self.socket.pid())
+                    .nl_seq(self.next_seq())
+                    .nl_payload(nl_payload)
+                    .build()?,
+            );
+        }
+
+        if buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut receivers = Vec::with_capacity(buffer.len());
+        {
+            let mut lock = self.core.lock();
+            for msg in buffer.iter() {
+                let (sender, receiver) = channel();
+                lock.register(*msg.nl_seq(), sender);
+                receivers.push(receiver);
+            }
+        }
+
+        if let Err(e) = self.socket.send_batch(&buffer) {
+            let mut lock = self.core.lock();
+            for msg in buffer.iter() {
+                lock.unregister(*msg.nl_seq());
+            }
+            return Err(RouterError::from(e));
+        }
+
+        let deadline = self.default_timeout().map(|t| Instant::now() + t);
+        Ok(buffer
+            .iter()
+            .zip(receivers)
+            .map(|(msg, receiver)| {
+                let mut handle = self.new_handle(
+                    receiver,
+                    ResponseValidator::request(*msg.nl_flags(), *msg.nl_seq()),
+                );
+                handle.set_deadline(deadline);
+                handle
+            })
+            .collect())
+    }
+
+    /// Send a dump request and collect all of the responses, reissuing the request
+    /// if the kernel reports that the dump was interrupted by a concurrent change.
+    ///
+    /// [`NlmF::DUMP`] is added to the flags automatically. The request is sent at most
+    /// [`NlRouter::consistent_dump_attempts`] times before
+    /// [`RouterError::DumpInterrupted`] is returned.
+    pub fn send_consistent_dump<T, P>(
+        &self,
+        nl_type: T,
+        nl_flags: NlmF,
+        nl_payload: NlPayload<T, P>,
+    ) -> Result<NlBuffer<T, P>, RouterError<T, P>>
+    where
+        T: NlType,
+        P: Clone + Size + ToBytes + FromBytesWithInput<Input = usize>,
+    {
+        let attempts = self.consistent_dump_attempts().max(1);
+        for attempt in 1..=attempts {
+            let mut recv =
+                self.send::<_, _, T, P>(nl_type, nl_flags | NlmF::DUMP, nl_payload.clone())?;
+            let mut buffer = NlBuffer::new();
+            for msg in recv.by_ref() {
+                buffer.push(msg?);
+            }
+            if !recv.dump_interrupted() {
+                return Ok(buffer);
+            }
+            debug!("Dump was interrupted on attempt {attempt} of {attempts}");
+        }
+        Err(RouterError::DumpInterrupted)
     }
 
     fn get_genl_family(&self, family_name: &str) -> GenlFamily {
@@ -418,21 +944,7 @@ impl NlRouter {
         &self,
         family_name: &str,
     ) -> Result<u16, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
-        let mut res = Err(RouterError::new(format!(
-            "Generic netlink family {family_name} was not found"
-        )));
-
-        let nlhdrs = self.get_genl_family(family_name)?;
-        for nlhdr in nlhdrs.into_iter() {
-            if let NlPayload::Payload(p) = nlhdr.nl_payload() {
-                let handle = p.attrs().get_attr_handle();
-                if let Ok(u) = handle.get_attr_payload_as::<u16>(CtrlAttr::FamilyId) {
-                    res = Ok(u);
-                }
-            }
-        }
-
-        res
+        Ok(*self.get_family_info(family_name)?.id())
     }
 
     /// Convenience function for resolving a [`str`] containing the
@@ -442,46 +954,126 @@ impl NlRouter {
         family_name: &str,
         mcast_name: &str,
     ) -> Result<u32, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
-        let mut res = Err(RouterError::new(format!(
-            "Failed to resolve multicast group ID for family name {family_name}, multicast group name {mcast_name}"
-        )));
+        self.get_family_info(family_name)?
+            .mcast_group(mcast_name)
+            .map(|group| *group.id())
+            .ok_or_else(|| {
+                RouterError::new(format!(
+                    "Failed to resolve multicast group ID for family name {family_name}, multicast group name {mcast_name}"
+                ))
+            })
+    }
+
+    /// Cache the generic netlink families resolved by this router if [`true`]
+    /// is passed in.
+    ///
+    /// While the cache is enabled, [`NlRouter::get_family_info`],
+    /// [`NlRouter::resolve_genl_family`], [`NlRouter::resolve_nl_mcast_group`]
+    /// and [`NlRouter::lookup_id`] only query the kernel for families that are
+    /// not cached. The router joins the `notify` group of `nlctrl` and drops a
+    /// family from the cache when the kernel announces that it was registered,
+    /// unregistered or changed its multicast groups. These notifications are
+    /// only delivered to the multicast receiver if the socket joined the group
+    /// itself. Leaving the group with [`NlRouter::drop_mcast_membership`] only
+    /// stops delivering them while the cache is enabled. The group is left
+    /// when the cache is disabled.
+    pub fn enable_genl_cache(
+        &self,
+        enable: bool,
+    ) -> Result<(), RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        if !enable {
+            let cache = self.core.lock().set_genl_cache(None);
+            if let Some(cache) = cache.filter(|cache| !cache.deliver_notifications()) {
+                self.socket
+                    .drop_mcast_membership(Groups::new_groups(&[cache.notify_group()]))?;
+            }
+            return Ok(());
+        }
+        if self.core.lock().genl_cache().is_some() {
+            return Ok(());
+        }
+
+        let ctrl = self.get_family_info("nlctrl")?;
+        let group = ctrl
+            .mcast_group("notify")
+            .map(|group| *group.id())
+            .ok_or_else(|| RouterError::new("nlctrl has no notify multicast group"))?;
+        let joined = self
+            .socket
+            .list_mcast_membership()?
+            .to_vec()
+            .contains(&group);
+        if !joined {
+            self.socket
+                .add_mcast_membership(Groups::new_groups(&[group]))?;
+        }
+        let mut cache = GenlCache::new(group, joined);
+        cache.insert(cache.generation(), ctrl);
+        self.core.lock().set_genl_cache(Some(cache));
+        Ok(())
+    }
+
+    /// Get the generation of the generic netlink cache after applying pending
+    /// notifications, or [`None`] if the cache is disabled.
+    fn genl_cache_generation(&self) -> Option<u64> {
+        self.core.lock().genl_cache()?;
+        if self.fd.is_none() {
+            // Errors have already been delivered to the receivers.
+            let _ = self.driver.process_readable();
+        }
+        self.core.lock().genl_cache().map(GenlCache::generation)
+    }
+
+    /// Cache family descriptions requested at `generation` if the cache is
+    /// enabled.
+    fn cache_families<I>(&self, generation: Option<u64>, families: I)
+    where
+        I: IntoIterator<Item = GenlFamilyInfo>,
+    {
+        if let (Some(generation), Some(cache)) = (generation, self.core.lock().genl_cache_mut()) {
+            for family in families {
+                cache.insert(generation, family);
+            }
+        }
+    }
+
+    /// Get the description of the generic netlink family `family_name`,
+    /// including its operations and multicast groups.
+    pub fn get_family_info(
+        &self,
+        family_name: &str,
+    ) -> Result<GenlFamilyInfo, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let generation = self.genl_cache_generation();
+        if let Some(info) = self
+            .core
+            .lock()
+            .genl_cache()
+            .and_then(|cache| cache.family(family_name))
+        {
+            return Ok(info.clone());
+        }
 
         let nlhdrs = self.get_genl_family(family_name)?;
         for nlhdr in nlhdrs {
             if let NlPayload::Payload(p) = nlhdr.nl_payload() {
-                let handle = p.attrs().get_attr_handle();
-                let mcast_groups = handle.get_nested_attributes::<Index>(CtrlAttr::McastGroups)?;
-                if let Some(id) = mcast_groups.iter().find_map(|item| {
-                    let nested_attrs = item.get_attr_handle::<CtrlAttrMcastGrp>().ok()?;
-                    let string = nested_attrs
-                        .get_attr_payload_as_with_len::<String>(CtrlAttrMcastGrp::Name)
-                        .ok()?;
-                    if string.as_str() == mcast_name {
-                        nested_attrs
-                            .get_attr_payload_as::<u32>(CtrlAttrMcastGrp::Id)
-                            .ok()
-                    } else {
-                        None
-                    }
-                }) {
-                    res = Ok(id);
-                }
+                let info = GenlFamilyInfo::parse(p)?;
+                self.cache_families(generation, once(info.clone()));
+                return Ok(info);
             }
         }
 
-        res
+        Err(RouterError::new(format!(
+            "Generic netlink family {family_name} was not found"
+        )))
     }
 
-    /// Look up netlink family and multicast group name by ID.
-    pub fn lookup_id(
+    /// Get the descriptions of all generic netlink families registered with
+    /// the kernel.
+    pub fn list_families(
         &self,
-        id: u32,
-    ) -> Result<(String, String), RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
-        let mut res = Err(RouterError::new(
-            "ID does not correspond to a multicast group",
-        ));
-
-        let recv = self.send(
+    ) -> Result<Vec<GenlFamilyInfo>, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let generation = self.genl_cache_generation();
+        let recv = self.send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
             GenlId::Ctrl,
             NlmF::DUMP,
             NlPayload::Payload(
@@ -492,45 +1084,144 @@ impl NlRouter {
                     .build()?,
             ),
         )?;
-        for res_msg in recv {
-            let msg = res_msg?;
-
-            if let NlPayload::Payload(p) = msg.nl_payload() {
-                let attributes = p.attrs().get_attr_handle();
-                let name =
-                    attributes.get_attr_payload_as_with_len::<String>(CtrlAttr::FamilyName)?;
-                let groups = match attributes.get_nested_attributes::<Index>(CtrlAttr::McastGroups)
-                {
-                    Ok(grps) => grps,
-                    Err(_) => continue,
-                };
-                for group_by_index in groups.iter() {
-                    let attributes = group_by_index.get_attr_handle::<CtrlAttrMcastGrp>()?;
-                    if let Ok(mcid) = attributes.get_attr_payload_as::<u32>(CtrlAttrMcastGrp::Id) {
-                        if mcid == id {
-                            let mcast_name = attributes
-                                .get_attr_payload_as_with_len::<String>(CtrlAttrMcastGrp::Name)?;
-                            res = Ok((name.clone(), mcast_name));
-                        }
-                    }
-                }
+        let mut families = Vec::new();
+        for msg in recv {
+            if let NlPayload::Payload(p) = msg?.nl_payload() {
+                families.push(GenlFamilyInfo::parse(p)?);
             }
         }
+        self.cache_families(generation, families.iter().cloned());
+
+        Ok(families)
+    }
+
+    /// Dump the attribute policies of the generic netlink family `family_name`.
+    ///
+    /// If `cmd` is set, only the policies used by that command are returned.
+    /// Policy dumps are supported by Linux 5.8 and newer.
+    pub fn resolve_genl_policy(
+        &self,
+        family_name: &str,
+        cmd: Option<u8>,
+    ) -> Result<FamilyPolicy, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let mut attrs = once(
+            NlattrBuilder::default()
+                .nla_type(
+                    AttrTypeBuilder::default()
+                        .nla_type(CtrlAttr::FamilyName)
+                        .build()?,
+                )
+                .nla_payload(family_name)
+                .build()?,
+        )
+        .collect::<GenlBuffer<_, _>>();
+        if let Some(cmd) = cmd {
+            attrs.push(
+                NlattrBuilder::default()
+                    .nla_type(AttrTypeBuilder::default().nla_type(CtrlAttr::Op).build()?)
+                    .nla_payload(u32::from(cmd))
+                    .build()?,
+            );
+        }
+
+        let recv = self.send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+            GenlId::Ctrl,
+            NlmF::DUMP,
+            NlPayload::Payload(
+                GenlmsghdrBuilder::default()
+                    .cmd(CtrlCmd::Getpolicy)
+                    .version(2)
+                    .attrs(attrs)
+                    .build()?,
+            ),
+        )?;
+        let msgs = recv.collect::<Result<Vec<_>, _>>()?;
+        Ok(FamilyPolicy::parse(
+            msgs.iter().filter_map(|msg| msg.get_payload()),
+        )?)
+    }
+
+    /// Look up netlink family and multicast group name by ID.
+    pub fn lookup_id(
+        &self,
+        id: u32,
+    ) -> Result<(String, String), RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        if self.genl_cache_generation().is_some() {
+            if let Some(names) = self
+                .core
+                .lock()
+                .genl_cache()
+                .and_then(|cache| cache.mcast_group(id))
+                .map(|(family, group)| (family.name().clone(), group.name().clone()))
+            {
+                return Ok(names);
+            }
+        }
+        self.list_families()?
+            .iter()
+            .find_map(|family| {
+                family
+                    .mcast_groups()
+                    .iter()
+                    .find(|group| *group.id() == id)
+                    .map(|group| (family.name().clone(), group.name().clone()))
+            })
+            .ok_or_else(|| RouterError::new("ID does not correspond to a multicast group"))
+    }
+}
+
+impl<S> AsRawFd for NlRouter<S>
+where
+    S: AsRawFd,
+{
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl<S> AsFd for NlRouter<S>
+where
+    S: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
 
-        res
+/// The router should be created with [`NlRouter::connect_caller_driven`] and
+/// [`NlRouter::process_readable`] called when the socket is readable.
+#[cfg(feature = "mio")]
+impl mio::event::Source for NlRouter {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).deregister(registry)
     }
 }
 
-impl Drop for NlRouter {
+impl<S> Drop for NlRouter<S> {
     fn drop(&mut self) {
-        let buffer: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
-        let ret = unsafe {
-            libc::write(
-                self.fd.as_raw_fd(),
-                buffer.as_ptr() as *const _,
-                buffer.len(),
-            )
+        let Some(ref fd) = self.fd else {
+            return;
         };
+        let buffer: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+        let ret = unsafe { libc::write(fd.as_raw_fd(), buffer.as_ptr() as *const _, buffer.len()) };
         if ret < 0 {
             warn!("Failed to signal processing thread to exit: errno {ret}");
         }
@@ -539,32 +1230,97 @@ impl Drop for NlRouter {
 
 /// A handle for receiving and validating all messages that correspond to a request.
 pub struct NlRouterReceiverHandle<T, P> {
-    receiver: Receiver<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>,
-    senders: Senders,
-    needs_ack: bool,
-    seq: Option<u32>,
-    next_is_none: bool,
-    next_is_ack: bool,
+    receiver: Receiver<Delivery>,
+    core: Core,
+    driver: Option<Arc<dyn Drive>>,
+    validator: ResponseValidator,
+    deadline: Option<Instant>,
+    metadata: Option<RecvMetadata>,
     data: PhantomData<(T, P)>,
 }
 
 impl<T, P> NlRouterReceiverHandle<T, P> {
     fn new(
-        receiver: Receiver<Result<Nlmsghdr<u16, Buffer>, RouterError<u16, Buffer>>>,
-        senders: Senders,
-        needs_ack: bool,
-        seq: Option<u32>,
+        receiver: Receiver<Delivery>,
+        core: Core,
+        driver: Option<Arc<dyn Drive>>,
+        validator: ResponseValidator,
     ) -> Self {
         NlRouterReceiverHandle {
             receiver,
-            senders,
-            needs_ack,
-            seq,
-            next_is_none: false,
-            next_is_ack: false,
+            core,
+            driver,
+            validator,
+            deadline: None,
+            metadata: None,
             data: PhantomData,
         }
     }
+
+    /// Get the metadata of the datagram containing the last message returned by
+    /// this handle, such as the multicast group of a notification.
+    pub fn metadata(&self) -> Option<&RecvMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Returns [`true`] if any message received so far had
+    /// [`NlmF::DUMP_INTR`] set, indicating that the dump was modified while
+    /// it was in progress and the results may be inconsistent.
+    pub fn dump_interrupted(&self) -> bool {
+        self.validator.dump_interrupted()
+    }
+
+    /// Set the point in time after which waiting for the next message will fail
+    /// with [`RouterError::Timeout`]. [`None`] waits indefinitely.
+    ///
+    /// For a handle corresponding to a request, a timeout ends the stream of
+    /// responses and the sequence number is no longer tracked by the router.
+    /// Responses that arrive later are dropped.
+    /// For the multicast handle, a timeout is reported but the handle can
+    /// continue to be used after a new deadline is set.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Get the deadline for receiving messages on this handle.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn recv(&mut self) -> Result<Delivery, RecvTimeoutError> {
+        if let Some(ref driver) = self.driver {
+            return loop {
+                match self.receiver.try_recv() {
+                    Ok(received) => break Ok(received),
+                    Err(TryRecvError::Disconnected) => break Err(RecvTimeoutError::Disconnected),
+                    Err(TryRecvError::Empty) => (),
+                }
+                let timeout = match self.deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            break Err(RecvTimeoutError::Timeout);
+                        }
+                        remaining.min(POLL_INTERVAL)
+                    }
+                    // Messages may also be routed by another caller of
+                    // NlRouter::process_readable so the channel is checked periodically.
+                    None => POLL_INTERVAL,
+                };
+                driver.drive(timeout);
+            };
+        }
+
+        match self.deadline {
+            Some(deadline) => self
+                .receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
 }
 
 impl<T, P> NlRouterReceiverHandle<T, P>
@@ -572,6 +1328,30 @@ where
     T: NlType,
     P: Size + FromBytesWithInput<Input = usize>,
 {
+    /// Return the next message if one has already been routed to this receiver
+    /// without waiting or reading from the socket.
+    ///
+    /// This is intended for draining receivers after
+    /// [`NlRouter::process_readable`]. [`None`] is returned if no message is
+    /// pending or all messages have been received.
+    pub fn try_next_typed<TT, PP>(
+        &mut self,
+    ) -> Option<Result<Nlmsghdr<TT, PP>, RouterError<TT, PP>>>
+    where
+        TT: NlType,
+        PP: Size + FromBytesWithInput<Input = usize>,
+    {
+        if self.validator.is_done() {
+            return None;
+        }
+        let (received, metadata) = self.receiver.try_recv().ok()?;
+        self.metadata = metadata;
+        Some(
+            self.validator
+                .validate_with_attr_path(received, |e| self.core.lock().attr_path(e)),
+        )
+    }
+
     /// Imitates the [`Iterator`] API but allows parsing differently typed
     /// messages in a sequence of messages meant for this receiver.
     pub fn next_typed<TT, PP>(&mut self) -> Option<Result<Nlmsghdr<TT, PP>, RouterError<TT, PP>>>
@@ -579,64 +1359,34 @@ where
         TT: NlType,
         PP: Size + FromBytesWithInput<Input = usize>,
     {
-        if self.next_is_none {
+        if self.validator.is_done() {
             return None;
         }
 
-        let mut msg = match self.receiver.recv() {
-            Ok(untyped) => match untyped {
-                Ok(u) => match u.to_typed::<TT, PP>() {
-                    Ok(t) => t,
-                    Err(e) => {
-                        self.next_is_none = true;
-                        return Some(Err(e));
-                    }
-                },
-                Err(e) => {
-                    self.next_is_none = true;
-                    return Some(Err(match e.to_typed() {
-                        Ok(e) => e,
-                        Err(e) => e,
-                    }));
-                }
-            },
-            Err(_) => {
-                self.next_is_none = true;
-                return Some(Err(RouterError::ClosedChannel));
+        let received = match self.recv() {
+            Ok((received, metadata)) => {
+                self.metadata = metadata;
+                received
             }
-        };
-
-        let nl_type = Nlmsg::from((*msg.nl_type()).into());
-        if let NlPayload::Ack(_) = msg.nl_payload() {
-            self.next_is_none = true;
-            if !self.needs_ack {
-                return Some(Err(RouterError::UnexpectedAck));
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(seq) = self.validator.seq() {
+                    self.core.lock().abandon(seq);
+                }
+                return Some(Err(self.validator.timeout()));
             }
-        } else if let Some(e) = msg.get_err() {
-            self.next_is_none = true;
-            if self.next_is_ack {
-                return Some(Err(RouterError::NoAck));
-            } else {
-                return Some(Err(RouterError::<TT, PP>::Nlmsgerr(e)));
+            Err(RecvTimeoutError::Disconnected) => {
+                return Some(Err(self.validator.closed()));
             }
-        } else if (!msg.nl_flags().contains(NlmF::MULTI) || nl_type == Nlmsg::Done)
-            && self.seq.is_some()
-        {
-            assert!(!self.next_is_ack);
+        };
 
-            if self.needs_ack {
-                self.next_is_ack = true;
-            } else {
-                self.next_is_none = true;
-            }
-        } else if self.next_is_ack {
-            self.next_is_none = true;
-            return Some(Err(RouterError::NoAck));
+        let res = self
+            .validator
+            .validate_with_attr_path(received, |e| self.core.lock().attr_path(e));
+        if let Ok(ref msg) = res {
+            trace!("Router received message: {msg:?}");
         }
 
-        trace!("Router received message: {msg:?}");
-
-        Some(Ok(msg))
+        Some(res)
     }
 }
 
@@ -654,17 +1404,289 @@ where
 
 impl<T, P> Drop for NlRouterReceiverHandle<T, P> {
     fn drop(&mut self) {
-        if let Some(seq) = self.seq {
-            self.senders.lock().remove(&seq);
+        if let Some(seq) = self.validator.seq() {
+            if self.validator.is_done() {
+                self.core.lock().unregister(seq);
+            } else {
+                self.core.lock().abandon(seq);
+            }
+        }
+    }
+}
+
+/// Iterator over the generic netlink families being registered and
+/// unregistered, for example when the kernel module providing a family is
+/// loaded or unloaded.
+///
+/// Multicast messages other than `nlctrl` notifications are skipped.
+pub struct FamilyEvents<S = NlSocket> {
+    router: NlRouter<S>,
+    multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+}
+
+impl<S> FamilyEvents<S>
+where
+    S: Transport + 'static,
+{
+    /// Join the `notify` group of `nlctrl` with `router` and receive the
+    /// family events delivered to its multicast receiver `multicast`.
+    pub fn new(
+        router: NlRouter<S>,
+        multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+    ) -> Result<Self, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>> {
+        let group = router.resolve_nl_mcast_group("nlctrl", "notify")?;
+        router
+            .add_mcast_membership(Groups::new_groups(&[group]))
+            .map_err(|e| e.to_typed().unwrap_or_else(|e| e))?;
+        Ok(FamilyEvents { router, multicast })
+    }
+}
+
+impl<S> FamilyEvents<S> {
+    /// Get the router used to receive the events.
+    pub fn router(&self) -> &NlRouter<S> {
+        &self.router
+    }
+
+    /// Set the deadline for receiving the next event. See
+    /// [`NlRouterReceiverHandle::set_deadline`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.multicast.set_deadline(deadline);
+    }
+}
+
+impl<S> Iterator for FamilyEvents<S> {
+    type Item = Result<FamilyEvent, RouterError<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let msg = match self
+                .multicast
+                .next_typed::<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>()?
+            {
+                Ok(msg) => msg,
+                Err(e) => return Some(Err(e)),
+            };
+            if *msg.nl_type() != GenlId::Ctrl {
+                continue;
+            }
+            if let Some(payload) = msg.get_payload() {
+                match FamilyEvent::parse(payload) {
+                    Ok(Some(event)) => return Some(Ok(event)),
+                    Ok(None) => (),
+                    Err(e) => return Some(Err(RouterError::from(e))),
+                }
+            }
         }
     }
 }
 
+/// A [`calloop`] event source for a caller-driven [`NlRouter`].
+///
+/// When the socket is readable, all pending messages are routed to their
+/// receiver handles and each message for the multicast receiver is passed to the
+/// callback. The callback is passed the router as metadata so that it can send
+/// requests.
+#[cfg(feature = "calloop")]
+pub struct NlRouterSource<T, P> {
+    source: Generic<NlRouter>,
+    multicast: NlRouterReceiverHandle<u16, Genlmsghdr<u8, u16, NoUserHeader>>,
+    data: PhantomData<(T, P)>,
+}
+
+#[cfg(feature = "calloop")]
+impl<T, P> NlRouterSource<T, P> {
+    /// Equivalent of `socket` and `bind` calls. The router is created with
+    /// [`NlRouter::connect_caller_driven`].
+    pub fn connect(
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> Result<Self, RouterError<u16, Buffer>> {
+        let (router, multicast) = NlRouter::connect_caller_driven(proto, pid, groups)?;
+        Ok(NlRouterSource {
+            source: Generic::new(router, Interest::READ, Mode::Level),
+            multicast,
+            data: PhantomData,
+        })
+    }
+
+    /// Get a reference to the router.
+    pub fn get_ref(&self) -> &NlRouter {
+        self.source.get_ref()
+    }
+}
+
+#[cfg(feature = "calloop")]
+impl<T, P> EventSource for NlRouterSource<T, P>
+where
+    T: NlType,
+    P: Size + FromBytesWithInput<Input = usize>,
+{
+    type Event = Result<Nlmsghdr<T, P>, RouterError<T, P>>;
+    type Metadata = NoIoDrop<NlRouter>;
+    type Ret = ();
+    type Error = io::Error;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> Result<PostAction, Self::Error>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        let multicast = &mut self.multicast;
+        self.source.process_events(readiness, token, |_, router| {
+            // Errors have already been delivered to the multicast receiver.
+            let _ = router.process_readable();
+            while let Some(received) = multicast.try_next_typed::<T, P>() {
+                callback(received, router);
+            }
+            Ok(PostAction::Continue)
+        })
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.source.register(poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.source.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop::Result<()> {
+        self.source.unregister(poll)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use crate::test::setup;
+    use std::{
+        collections::VecDeque,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use crate::{
+        consts::socket::Msg,
+        test::{empty_netns, setup},
+    };
+
+    /// Transport that records sent datagrams and echoes them back.
+    #[derive(Clone, Default)]
+    struct EchoTransport {
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+        pending: Arc<Mutex<VecDeque<Vec<u8>>>>,
+        fail_send: Arc<AtomicBool>,
+    }
+
+    impl Transport for EchoTransport {
+        fn send(&self, buf: &[u8], _: Msg) -> Result<usize, io::Error> {
+            if self.fail_send.load(Ordering::SeqCst) {
+                return Err(io::Error::from_raw_os_error(libc::ENOBUFS));
+            }
+            self.sent.lock().push(buf.to_vec());
+            self.pending.lock().push_back(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn recv(&self, buf: &mut [u8], _: Msg) -> Result<(usize, RecvMetadata), io::Error> {
+            let datagram = self
+                .pending
+                .lock()
+                .pop_front()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+            buf[..datagram.len()].copy_from_slice(&datagram);
+            Ok((datagram.len(), RecvMetadata::default()))
+        }
+
+        fn pid(&self) -> Result<u32, io::Error> {
+            Ok(5)
+        }
+
+        fn protocol(&self) -> Result<NlFamily, io::Error> {
+            Ok(NlFamily::Generic)
+        }
+
+        fn add_mcast_membership(&self, _: Groups) -> Result<(), io::Error> {
+            Ok(())
+        }
+
+        fn drop_mcast_membership(&self, _: Groups) -> Result<(), io::Error> {
+            Ok(())
+        }
+
+        fn list_mcast_membership(&self) -> Result<NetlinkBitArray, io::Error> {
+            Ok(NetlinkBitArray::new(0))
+        }
+
+        fn set_nonblocking(&self, _: bool) -> Result<(), io::Error> {
+            Ok(())
+        }
+
+        fn wait_readable(&self, _: Duration) -> Result<bool, io::Error> {
+            Ok(!self.pending.lock().is_empty())
+        }
+    }
+
+    #[test]
+    fn test_transport() {
+        setup();
+
+        let transport = EchoTransport::default();
+        let (sock, _) = NlRouter::with_transport(transport.clone()).unwrap();
+        let payload = GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+            .cmd(CtrlCmd::Getfamily)
+            .version(2)
+            .build()
+            .unwrap();
+        let mut handle = sock
+            .send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+                GenlId::Ctrl,
+                NlmF::empty(),
+                NlPayload::Payload(payload.clone()),
+            )
+            .unwrap();
+
+        let expected = NlmsghdrBuilder::default()
+            .nl_type(GenlId::Ctrl)
+            .nl_flags(NlmF::REQUEST)
+            .nl_pid(5)
+            .nl_seq(0)
+            .nl_payload(NlPayload::Payload(payload))
+            .build()
+            .unwrap();
+        let mut buffer = io::Cursor::new(Vec::new());
+        expected.to_bytes(&mut buffer).unwrap();
+        assert_eq!(*transport.sent.lock(), vec![buffer.into_inner()]);
+
+        let msg = handle.next().unwrap().unwrap();
+        assert_eq!(msg, expected);
+        assert!(handle.next().is_none());
+        drop(handle);
+        assert!(sock.core.lock().is_empty());
+
+        transport.fail_send.store(true, Ordering::SeqCst);
+        assert!(sock
+            .send::<_, Buffer, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+                GenlId::Ctrl,
+                NlmF::empty(),
+                NlPayload::Empty,
+            )
+            .is_err());
+        assert!(sock.core.lock().is_empty());
+    }
 
     #[test]
     fn real_test_mcast_groups() {
@@ -682,17 +1704,17 @@ mod test {
                     .unwrap();
                 vec![ni, ci]
             }
-            (Ok(ni), Err(RouterError::Nlmsgerr(_))) => {
+            (Ok(ni), Err(RouterError::Nlmsgerr(..))) => {
                 sock.add_mcast_membership(Groups::new_groups(&[ni]))
                     .unwrap();
                 vec![ni]
             }
-            (Err(RouterError::Nlmsgerr(_)), Ok(ci)) => {
+            (Err(RouterError::Nlmsgerr(..)), Ok(ci)) => {
                 sock.add_mcast_membership(Groups::new_groups(&[ci]))
                     .unwrap();
                 vec![ci]
             }
-            (Err(RouterError::Nlmsgerr(_)), Err(RouterError::Nlmsgerr(_))) => {
+            (Err(RouterError::Nlmsgerr(..)), Err(RouterError::Nlmsgerr(..))) => {
                 return;
             }
             (Err(e), _) => panic!("Unexpected result from resolve_nl_mcast_group: {e:?}"),
@@ -712,4 +1734,401 @@ mod test {
             assert!(!groups.is_set(*id as usize));
         }
     }
+
+    #[test]
+    fn real_test_timeout() {
+        setup();
+
+        let (sock, mut multicast) =
+            NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        multicast.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(multicast.next(), Some(Err(RouterError::Timeout))));
+        multicast.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(multicast.next(), Some(Err(RouterError::Timeout))));
+
+        sock.set_default_timeout(Some(Duration::from_secs(5)));
+        let id = sock.resolve_genl_family("nlctrl").unwrap();
+        assert_eq!(id, u16::from(GenlId::Ctrl));
+        assert!(sock.core.lock().is_empty());
+    }
+
+    #[test]
+    fn real_test_consistent_dump() {
+        setup();
+
+        let (sock, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        sock.set_consistent_dump_attempts(2);
+        let msgs = sock
+            .send_consistent_dump(
+                GenlId::Ctrl,
+                NlmF::empty(),
+                NlPayload::Payload(
+                    GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                        .cmd(CtrlCmd::Getfamily)
+                        .version(2)
+                        .build()
+                        .unwrap(),
+                ),
+            )
+            .unwrap();
+        assert!(msgs.iter().any(|msg| msg.get_payload().is_some()));
+    }
+
+    #[test]
+    fn real_test_netns() {
+        use crate::{
+            consts::rtnl::{Ifla, RtAddrFamily, Rtm},
+            rtnl::{Ifinfomsg, IfinfomsgBuilder},
+        };
+
+        setup();
+
+        let Some(netns) = empty_netns() else {
+            return;
+        };
+        let config = crate::router::RouterConfigBuilder::default()
+            .netns(netns)
+            .build()
+            .unwrap();
+        let (sock, _) =
+            NlRouter::connect_with_config(NlFamily::Route, None, Groups::empty(), config).unwrap();
+        let names = sock
+            .send::<_, _, Rtm, Ifinfomsg>(
+                Rtm::Getlink,
+                NlmF::DUMP | NlmF::ACK,
+                NlPayload::Payload(
+                    IfinfomsgBuilder::default()
+                        .ifi_family(RtAddrFamily::Unspecified)
+                        .build()
+                        .unwrap(),
+                ),
+            )
+            .unwrap()
+            .filter_map(|msg| {
+                msg.unwrap().get_payload().map(|payload| {
+                    payload
+                        .rtattrs()
+                        .get_attr_handle()
+                        .get_attr_payload_as_with_len::<String>(Ifla::Ifname)
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["lo".to_string()]);
+    }
+
+    #[test]
+    fn real_test_ext_ack_attr_path() {
+        use crate::{
+            attr::AttrSchema,
+            consts::{
+                nl::NlAttrKind,
+                rtnl::{Ifla, IflaInfo, RtAddrFamily, Rtm},
+            },
+            rtnl::{Ifinfomsg, IfinfomsgBuilder, RtattrBuilder},
+            types::RtBuffer,
+            Header,
+        };
+
+        /// `IFLA_BR_GROUP_ADDR`, a 6 byte MAC address.
+        const IFLA_BR_GROUP_ADDR: u16 = 20;
+
+        setup();
+
+        let Some(netns) = empty_netns() else {
+            return;
+        };
+        let config = crate::router::RouterConfigBuilder::default()
+            .netns(netns)
+            .build()
+            .unwrap();
+        let (sock, _) =
+            NlRouter::connect_with_config(NlFamily::Route, None, Groups::empty(), config).unwrap();
+        sock.enable_ext_ack(true).unwrap();
+        sock.register_attr_schema(
+            Rtm::Newlink,
+            Ifinfomsg::header_size(),
+            AttrSchema::new().nested(
+                Ifla::Linkinfo,
+                "IFLA_LINKINFO",
+                AttrSchema::new()
+                    .attr(IflaInfo::Kind, "IFLA_INFO_KIND")
+                    .nested(
+                        IflaInfo::Data,
+                        "IFLA_INFO_DATA",
+                        AttrSchema::new().attr(IFLA_BR_GROUP_ADDR, "IFLA_BR_GROUP_ADDR"),
+                    ),
+            ),
+        );
+
+        let data = [RtattrBuilder::default()
+            .rta_type(IFLA_BR_GROUP_ADDR)
+            .rta_payload(vec![0u8; 7])
+            .build()
+            .unwrap()]
+        .into_iter()
+        .collect::<RtBuffer<u16, Buffer>>();
+        let info = [
+            RtattrBuilder::default()
+                .rta_type(IflaInfo::Kind)
+                .rta_payload("bridge\0")
+                .build()
+                .unwrap(),
+            RtattrBuilder::default()
+                .rta_type(IflaInfo::Data)
+                .rta_payload(data)
+                .build()
+                .unwrap(),
+        ]
+        .into_iter()
+        .collect::<RtBuffer<IflaInfo, Buffer>>();
+        let attrs = [
+            RtattrBuilder::default()
+                .rta_type(Ifla::Ifname)
+                .rta_payload("br-test\0")
+                .build()
+                .unwrap(),
+            RtattrBuilder::default()
+                .rta_type(Ifla::Linkinfo)
+                .rta_payload(info)
+                .build()
+                .unwrap(),
+        ]
+        .into_iter()
+        .collect::<RtBuffer<Ifla, Buffer>>();
+        let err = sock
+            .send::<_, _, Rtm, Ifinfomsg>(
+                Rtm::Newlink,
+                NlmF::CREATE | NlmF::EXCL | NlmF::ACK,
+                NlPayload::Payload(
+                    IfinfomsgBuilder::default()
+                        .ifi_family(RtAddrFamily::Unspecified)
+                        .rtattrs(attrs)
+                        .build()
+                        .unwrap(),
+                ),
+            )
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        let RouterError::Nlmsgerr(ref e, ref path) = err else {
+            panic!("Unexpected error {err:?}");
+        };
+        if *e.error() == -libc::EOPNOTSUPP {
+            eprintln!("Skipping test: bridge links are not supported by this kernel");
+            return;
+        }
+        let ext_ack = e.get_ext_ack().unwrap();
+        assert!(ext_ack.offset().is_some());
+        let policy = ext_ack.policy().as_ref().unwrap();
+        assert_eq!(policy.attr_type(), &Some(NlAttrKind::Binary));
+        assert_eq!(policy.max_length(), &Some(6));
+        assert_eq!(
+            path.as_ref().map(|path| path.to_string()).as_deref(),
+            Some("IFLA_LINKINFO/IFLA_INFO_DATA/IFLA_BR_GROUP_ADDR"),
+        );
+        let msg = err.to_string();
+        assert!(
+            msg.starts_with("invalid attribute IFLA_LINKINFO/IFLA_INFO_DATA/IFLA_BR_GROUP_ADDR: ")
+        );
+        assert!(msg.ends_with(" (expected binary of at most 6 bytes)"));
+    }
+
+    #[test]
+    fn real_test_genl_policy() {
+        use crate::consts::nl::NlAttrKind;
+
+        setup();
+
+        let (sock, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        let policy = sock
+            .resolve_genl_policy("nlctrl", Some(CtrlCmd::Getfamily.into()))
+            .unwrap();
+        assert!(policy.op(CtrlCmd::Getfamily).unwrap().do_policy().is_some());
+        assert_eq!(
+            policy
+                .do_attr_policy(CtrlCmd::Getfamily, CtrlAttr::FamilyId)
+                .unwrap()
+                .attr_type(),
+            &Some(NlAttrKind::U16),
+        );
+        assert_eq!(
+            policy
+                .do_attr_policy(CtrlCmd::Getfamily, CtrlAttr::FamilyName)
+                .unwrap()
+                .attr_type(),
+            &Some(NlAttrKind::NulString),
+        );
+    }
+
+    #[test]
+    fn real_test_family_info() {
+        use crate::consts::genl::GenlOpFlags;
+
+        setup();
+
+        let (sock, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        let info = sock.get_family_info("nlctrl").unwrap();
+        assert_eq!(*info.id(), u16::from(GenlId::Ctrl));
+        assert!(info.mcast_group("notify").is_some());
+        let flags = info.op(CtrlCmd::Getfamily).unwrap().flags();
+        assert!(flags.contains(GenlOpFlags::CMD_CAP_DO | GenlOpFlags::CMD_CAP_DUMP));
+        assert!(sock
+            .list_families()
+            .unwrap()
+            .iter()
+            .any(|family| family.name() == "nlctrl"));
+    }
+
+    #[test]
+    fn real_test_attach_filter() {
+        setup();
+
+        let (sock, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        sock.set_default_timeout(Some(Duration::from_secs(5)));
+        // Replies to Getfamily are Newfamily messages, which only pass the filter
+        // because they are addressed to the router.
+        sock.attach_filter(&SocketFilter::new().genl_cmd(CtrlCmd::Delfamily))
+            .unwrap();
+        assert_eq!(
+            sock.resolve_genl_family("nlctrl").unwrap(),
+            u16::from(GenlId::Ctrl)
+        );
+    }
+
+    #[test]
+    fn real_test_send_batch() {
+        setup();
+
+        let (sock, _) = NlRouter::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        let handles = sock
+            .send_batch::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>, _>(
+                ["nlctrl", "not-a-family", "nlctrl"].iter().map(|name| {
+                    (
+                        GenlId::Ctrl,
+                        NlmF::ACK,
+                        NlPayload::Payload(
+                            GenlmsghdrBuilder::default()
+                                .cmd(CtrlCmd::Getfamily)
+                                .version(2)
+                                .attrs(
+                                    once(
+                                        NlattrBuilder::default()
+                                            .nla_type(
+                                                AttrTypeBuilder::default()
+                                                    .nla_type(CtrlAttr::FamilyName)
+                                                    .build()
+                                                    .unwrap(),
+                                            )
+                                            .nla_payload(*name)
+                                            .build()
+                                            .unwrap(),
+                                    )
+                                    .collect::<GenlBuffer<_, _>>(),
+                                )
+                                .build()
+                                .unwrap(),
+                        ),
+                    )
+                }),
+            )
+            .unwrap();
+        assert_eq!(handles.len(), 3);
+
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.collect::<Result<Vec<_>, _>>())
+            .collect::<Vec<_>>();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(RouterError::Nlmsgerr(..))));
+        assert!(results[2].is_ok());
+        assert!(sock.core.lock().is_empty());
+
+        assert!(sock
+            .send_batch::<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>, _>(
+                []
+            )
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn real_test_caller_driven() {
+        setup();
+
+        let (sock, mut multicast) =
+            NlRouter::connect_caller_driven(NlFamily::Generic, None, Groups::empty()).unwrap();
+        sock.process_readable().unwrap();
+
+        multicast.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(multicast.next(), Some(Err(RouterError::Timeout))));
+
+        sock.set_default_timeout(Some(Duration::from_secs(5)));
+        let id = sock.resolve_genl_family("nlctrl").unwrap();
+        assert_eq!(id, u16::from(GenlId::Ctrl));
+        assert!(sock.core.lock().is_empty());
+
+        let mut handle = sock
+            .send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+                GenlId::Ctrl,
+                NlmF::DUMP,
+                NlPayload::Payload(
+                    GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                        .cmd(CtrlCmd::Getfamily)
+                        .version(2)
+                        .build()
+                        .unwrap(),
+                ),
+            )
+            .unwrap();
+        let mut pollfd = libc::pollfd {
+            fd: sock.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut pollfd as *mut _, 1, 5000) }, 1);
+        sock.process_readable().unwrap();
+        assert!(handle.receiver.try_recv().is_ok());
+        assert!(handle.all(|msg| msg.is_ok()));
+    }
+
+    #[cfg(feature = "calloop")]
+    #[test]
+    fn real_test_calloop_router_source() {
+        setup();
+
+        let source =
+            NlRouterSource::<u16, Buffer>::connect(NlFamily::Generic, None, Groups::empty())
+                .unwrap();
+        let mut handle = source
+            .get_ref()
+            .send::<_, _, GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(
+                GenlId::Ctrl,
+                NlmF::DUMP,
+                NlPayload::Payload(
+                    GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                        .cmd(CtrlCmd::Getfamily)
+                        .version(2)
+                        .build()
+                        .unwrap(),
+                ),
+            )
+            .unwrap();
+
+        let mut event_loop = calloop::EventLoop::<usize>::try_new().unwrap();
+        event_loop
+            .handle()
+            .insert_source(source, |_, _, multicast_count| *multicast_count += 1)
+            .unwrap();
+        let mut multicast_count = 0;
+        event_loop
+            .dispatch(Some(Duration::from_secs(5)), &mut multicast_count)
+            .unwrap();
+        assert_eq!(multicast_count, 0);
+        assert!(matches!(
+            handle.try_next_typed::<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>(),
+            Some(Ok(_))
+        ));
+    }
 }
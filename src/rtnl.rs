@@ -545,6 +545,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::result_large_err)]
     fn real_test_ifinfomsg() {
         setup();
 
@@ -0,0 +1,311 @@
+//! Abstraction over the async runtime used by the asynchronous socket and router.
+//!
+//! [`NlSocketHandle`][crate::socket::asynchronous::NlSocketHandle] and
+//! [`NlRouter`][crate::router::asynchronous::NlRouter] do not depend on a
+//! specific executor. Instead, they are generic over a [`Runtime`] that registers
+//! the socket with a reactor, spawns the router's background task and provides
+//! timers.
+//!
+//! ## Backends
+//! * `tokio`: [`TokioRuntime`] uses [tokio](https://tokio.rs) and must be used from
+//!   within a tokio runtime. The `async` feature enables this backend.
+//! * `async-io`: [`AsyncIoRuntime`] uses [async-io](https://docs.rs/async-io), the
+//!   reactor shared by smol and async-std. Background tasks run on a dedicated
+//!   thread unless a spawner for an executor is provided with
+//!   [`AsyncIoRuntime::with_spawner`].
+//!
+//! If both backends are enabled, [`DefaultRuntime`] is [`TokioRuntime`]. Other
+//! executors can be supported by implementing [`Runtime`] and [`AsyncSocket`].
+
+use std::{
+    future::Future,
+    io,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+use std::task::ready;
+#[cfg(feature = "async-io")]
+use std::{pin::Pin, sync::Arc, thread};
+
+use crate::socket::NlSocket;
+
+/// A nonblocking netlink socket registered with a reactor.
+pub trait AsyncSocket: Send + Sync + 'static {
+    /// Get a reference to the underlying socket.
+    fn get_ref(&self) -> &NlSocket;
+
+    /// Deregister the socket from the reactor and return the underlying socket.
+    fn into_inner(self) -> io::Result<NlSocket>
+    where
+        Self: Sized;
+
+    /// Attempt a read operation, registering interest in read readiness if the
+    /// operation would block.
+    fn poll_read_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>>;
+
+    /// Attempt a write operation, registering interest in write readiness if the
+    /// operation would block.
+    fn poll_write_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>>;
+}
+
+/// An async runtime capable of driving netlink sockets.
+pub trait Runtime: Clone + Send + Sync + 'static {
+    /// Socket type registered with the reactor of this runtime.
+    type Socket: AsyncSocket;
+    /// Future returned by [`Runtime::sleep_until`].
+    type Sleep: Future + Send + Sync + 'static;
+
+    /// Register a nonblocking socket with the reactor.
+    fn register(&self, socket: NlSocket) -> io::Result<Self::Socket>;
+
+    /// Run a future to completion in the background.
+    fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static;
+
+    /// Return a future that completes at `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep;
+}
+
+/// Runtime backed by [tokio](https://tokio.rs).
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl AsyncSocket for tokio::io::unix::AsyncFd<NlSocket> {
+    fn get_ref(&self) -> &NlSocket {
+        tokio::io::unix::AsyncFd::get_ref(self)
+    }
+
+    fn into_inner(self) -> io::Result<NlSocket> {
+        Ok(tokio::io::unix::AsyncFd::into_inner(self))
+    }
+
+    fn poll_read_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            let mut guard = ready!(self.poll_read_ready(cx))?;
+            if let Ok(res) = guard.try_io(|socket| op(socket.get_ref())) {
+                return Poll::Ready(res);
+            }
+        }
+    }
+
+    fn poll_write_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            let mut guard = ready!(self.poll_write_ready(cx))?;
+            if let Ok(res) = guard.try_io(|socket| op(socket.get_ref())) {
+                return Poll::Ready(res);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Runtime for TokioRuntime {
+    type Socket = tokio::io::unix::AsyncFd<NlSocket>;
+    type Sleep = tokio::time::Sleep;
+
+    fn register(&self, socket: NlSocket) -> io::Result<Self::Socket> {
+        tokio::io::unix::AsyncFd::new(socket)
+    }
+
+    fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(future);
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep {
+        tokio::time::sleep_until(deadline.into())
+    }
+}
+
+/// A boxed future passed to the spawner of an [`AsyncIoRuntime`].
+#[cfg(feature = "async-io")]
+pub type BoxedTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runtime backed by [async-io](https://docs.rs/async-io).
+///
+/// By default, background tasks are run on a dedicated thread using
+/// [`async_io::block_on`]. Use [`AsyncIoRuntime::with_spawner`] to run them on
+/// an executor such as smol or async-std instead.
+#[cfg(feature = "async-io")]
+#[derive(Clone, Default)]
+pub struct AsyncIoRuntime {
+    spawner: Option<Arc<dyn Fn(BoxedTask) + Send + Sync>>,
+}
+
+#[cfg(feature = "async-io")]
+impl AsyncIoRuntime {
+    /// Create a runtime that passes background tasks to `spawner`.
+    ///
+    /// ```no_run
+    /// use neli::runtime::AsyncIoRuntime;
+    ///
+    /// let runtime = AsyncIoRuntime::with_spawner(|task| {
+    ///     std::thread::spawn(move || async_io::block_on(task));
+    /// });
+    /// ```
+    pub fn with_spawner<S>(spawner: S) -> Self
+    where
+        S: Fn(BoxedTask) + Send + Sync + 'static,
+    {
+        AsyncIoRuntime {
+            spawner: Some(Arc::new(spawner)),
+        }
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl AsyncSocket for async_io::Async<NlSocket> {
+    fn get_ref(&self) -> &NlSocket {
+        async_io::Async::get_ref(self)
+    }
+
+    fn into_inner(self) -> io::Result<NlSocket> {
+        async_io::Async::into_inner(self)
+    }
+
+    fn poll_read_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match op(self.get_ref()) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.poll_readable(cx))?;
+                }
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn poll_write_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match op(self.get_ref()) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.poll_writable(cx))?;
+                }
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl Runtime for AsyncIoRuntime {
+    type Socket = async_io::Async<NlSocket>;
+    type Sleep = async_io::Timer;
+
+    fn register(&self, socket: NlSocket) -> io::Result<Self::Socket> {
+        async_io::Async::new(socket)
+    }
+
+    fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match self.spawner {
+            Some(ref spawner) => spawner(Box::pin(future)),
+            None => {
+                thread::spawn(move || async_io::block_on(future));
+            }
+        }
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep {
+        async_io::Timer::at(deadline)
+    }
+}
+
+/// Placeholder runtime used as [`DefaultRuntime`] when no runtime backend feature
+/// is enabled.
+///
+/// This type cannot be constructed. A custom [`Runtime`] must be provided
+/// explicitly in this case.
+#[cfg(not(any(feature = "tokio", feature = "async-io")))]
+#[derive(Clone, Copy, Debug)]
+pub enum NoRuntime {}
+
+#[cfg(not(any(feature = "tokio", feature = "async-io")))]
+impl AsyncSocket for NoRuntime {
+    fn get_ref(&self) -> &NlSocket {
+        match *self {}
+    }
+
+    fn into_inner(self) -> io::Result<NlSocket> {
+        match self {}
+    }
+
+    fn poll_read_with<T>(
+        &self,
+        _: &mut Context<'_>,
+        _: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        match *self {}
+    }
+
+    fn poll_write_with<T>(
+        &self,
+        _: &mut Context<'_>,
+        _: &mut dyn FnMut(&NlSocket) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        match *self {}
+    }
+}
+
+#[cfg(not(any(feature = "tokio", feature = "async-io")))]
+impl Runtime for NoRuntime {
+    type Socket = NoRuntime;
+    type Sleep = std::future::Pending<()>;
+
+    fn register(&self, _: NlSocket) -> io::Result<Self::Socket> {
+        match *self {}
+    }
+
+    fn spawn<F>(&self, _: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match *self {}
+    }
+
+    fn sleep_until(&self, _: Instant) -> Self::Sleep {
+        match *self {}
+    }
+}
+
+/// The runtime used when no runtime is specified explicitly.
+#[cfg(feature = "tokio")]
+pub type DefaultRuntime = TokioRuntime;
+/// The runtime used when no runtime is specified explicitly.
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+pub type DefaultRuntime = AsyncIoRuntime;
+/// The runtime used when no runtime is specified explicitly.
+#[cfg(not(any(feature = "tokio", feature = "async-io")))]
+pub type DefaultRuntime = NoRuntime;
@@ -1,46 +1,115 @@
+#[cfg(feature = "tokio")]
+use std::os::unix::io::IntoRawFd;
 use std::{
     fmt::Debug,
-    io::Cursor,
-    os::unix::io::{AsRawFd, IntoRawFd, RawFd},
+    future::poll_fn,
+    io::{Cursor, IoSlice},
+    mem::size_of,
+    os::unix::io::{AsRawFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use log::trace;
-use tokio::io::unix::AsyncFd;
+use log::{trace, warn};
+use parking_lot::Mutex;
 
 use crate::{
-    consts::{nl::*, socket::*},
+    consts::{alignto, nl::*, socket::*},
     err::SocketError,
     iter::NlBufferIter,
     nl::Nlmsghdr,
-    socket::shared::NlSocket,
+    runtime::{AsyncSocket, DefaultRuntime, Runtime},
+    socket::{
+        capture::{Capture, Direction},
+        filter::SocketFilter,
+        netns::NetNs,
+        shared::{NlSocket, RecvMetadata},
+    },
     types::NlBuffer,
     utils::{
         asynchronous::{BufferPool, BufferPoolGuard},
-        Groups, NetlinkBitArray,
+        BufferGrowth, BufferPoolConfig, Groups, NetlinkBitArray, SendBuffer,
     },
     FromBytesWithInput, Size, ToBytes,
 };
 
-/// Tokio-enabled Netlink socket struct
-pub struct NlSocketHandle {
-    pub(super) socket: AsyncFd<super::NlSocket>,
+/// Asynchronous netlink socket struct.
+///
+/// The socket is driven by the [`Runtime`] `R`. See [`crate::runtime`] for the
+/// available backends.
+pub struct NlSocketHandle<R: Runtime = DefaultRuntime> {
+    pub(super) socket: R::Socket,
     pool: BufferPool,
+    adaptive: AtomicBool,
     pid: u32,
+    capture: Mutex<Option<(Arc<dyn Capture>, NlFamily)>>,
 }
 
+#[cfg(any(feature = "tokio", feature = "async-io"))]
 impl NlSocketHandle {
-    /// Set up asynchronous socket handle.
+    /// Set up asynchronous socket handle using the [`DefaultRuntime`].
     pub fn connect(proto: NlFamily, pid: Option<u32>, groups: Groups) -> Result<Self, SocketError> {
-        let socket = NlSocket::connect(proto, pid, groups)?;
+        Self::connect_with_runtime(&DefaultRuntime::default(), proto, pid, groups)
+    }
+
+    /// Set up asynchronous socket handle inside the network namespace `netns`
+    /// using the [`DefaultRuntime`].
+    pub fn connect_in_netns(
+        netns: &NetNs,
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> Result<Self, SocketError> {
+        Self::connect_in_netns_with_runtime(&DefaultRuntime::default(), netns, proto, pid, groups)
+    }
+}
+
+impl<R: Runtime> NlSocketHandle<R> {
+    /// Set up asynchronous socket handle driven by the provided runtime.
+    pub fn connect_with_runtime(
+        runtime: &R,
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> Result<Self, SocketError> {
+        Self::from_socket(runtime, NlSocket::connect(proto, pid, groups)?)
+    }
+
+    /// Set up asynchronous socket handle inside the network namespace `netns`
+    /// driven by the provided runtime.
+    pub fn connect_in_netns_with_runtime(
+        runtime: &R,
+        netns: &NetNs,
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> Result<Self, SocketError> {
+        Self::from_socket(
+            runtime,
+            NlSocket::connect_in_netns(netns, proto, pid, groups)?,
+        )
+    }
+
+    pub(crate) fn from_socket(runtime: &R, socket: NlSocket) -> Result<Self, SocketError> {
         socket.nonblock()?;
         let pid = socket.pid()?;
         Ok(NlSocketHandle {
-            socket: AsyncFd::new(socket)?,
+            socket: runtime.register(socket)?,
             pool: BufferPool::default(),
+            adaptive: AtomicBool::new(false),
             pid,
+            capture: Mutex::new(None),
         })
     }
 
+    /// Deregister the socket from the runtime and return the underlying socket.
+    pub fn into_inner(self) -> Result<NlSocket, SocketError> {
+        self.socket.into_inner().map_err(SocketError::from)
+    }
+
     /// Join multicast groups for a socket.
     pub fn add_mcast_membership(&self, groups: Groups) -> Result<(), SocketError> {
         self.socket
@@ -70,27 +139,145 @@ impl NlSocketHandle {
         self.pid
     }
 
+    /// Pass every datagram sent or received on this socket to `capture`.
+    ///
+    /// Passing [`None`] stops an active capture.
+    pub fn set_capture(&self, capture: Option<Arc<dyn Capture>>) -> Result<(), SocketError> {
+        let capture = match capture {
+            Some(c) => Some((c, self.socket.get_ref().protocol()?)),
+            None => None,
+        };
+        *self.capture.lock() = capture;
+        Ok(())
+    }
+
+    /// Replace the buffers used for receive operations with a pool described by
+    /// `config`.
+    ///
+    /// [`BufferGrowth::OnDemand`] enables adaptive buffers as with
+    /// [`NlSocketHandle::enable_adaptive_buffers`].
+    pub fn with_buffer_pool(mut self, config: BufferPoolConfig) -> Self {
+        self.pool = BufferPool::new(&config);
+        self.adaptive = AtomicBool::new(*config.growth() == BufferGrowth::OnDemand);
+        self
+    }
+
+    /// If [`true`] is passed in, peek at the size of each datagram before
+    /// receiving it and grow the receive buffer if the datagram does not fit.
+    ///
+    /// Without this, datagrams larger than the buffer return
+    /// [`SocketError::Truncated`] from [`NlSocketHandle::recv`] and
+    /// [`NlSocketHandle::recv_all`]. Grown buffers keep their allocation when
+    /// returned to the pool.
+    pub fn enable_adaptive_buffers(&self, enable: bool) {
+        self.adaptive.store(enable, Ordering::Relaxed);
+    }
+
+    async fn recv_buffer(&self) -> Result<(BufferPoolGuard<'_>, RecvMetadata), SocketError> {
+        let mut buffer = self.pool.acquire().await;
+        let adaptive = self.adaptive.load(Ordering::Relaxed);
+        let (size, metadata) = poll_fn(|cx| {
+            self.socket.poll_read_with(cx, &mut |socket| {
+                if adaptive {
+                    let (size, _) = socket.recv_msg([0u8; 0], Msg::PEEK | Msg::TRUNC)?;
+                    if size > buffer.len() {
+                        trace!("Growing receive buffer to {size} bytes");
+                        buffer.resize(size, 0);
+                    }
+                }
+                socket.recv_msg(buffer.as_mut_slice(), Msg::TRUNC)
+            })
+        })
+        .await?;
+        if size > buffer.len() {
+            return Err(SocketError::Truncated {
+                size,
+                capacity: buffer.len(),
+            });
+        }
+        buffer.reduce_size(size);
+        Ok((buffer, metadata))
+    }
+
+    fn capture_datagram(&self, direction: Direction, groups: &Groups, datagram: &[u8]) {
+        let capture = self.capture.lock().clone();
+        if let Some((capture, protocol)) = capture {
+            if let Err(e) = capture.capture(direction, protocol, groups, datagram) {
+                warn!("Failed to capture datagram: {e}");
+            }
+        }
+    }
+
     /// Send a message on the socket asynchronously.
     pub async fn send<T, P>(&self, msg: &Nlmsghdr<T, P>) -> Result<(), SocketError>
     where
         T: NlType,
         P: Size + ToBytes,
     {
-        let mut buffer = Cursor::new(vec![0; msg.padded_size()]);
-        msg.to_bytes(&mut buffer)?;
-        loop {
-            let mut guard = self.socket.writable().await?;
-            match guard.try_io(|socket| socket.get_ref().send(buffer.get_ref(), Msg::empty())) {
-                Ok(Ok(_)) => {
-                    break;
-                }
-                Ok(Err(e)) => return Err(SocketError::from(e)),
-                Err(_) => (),
-            };
+        self.send_serialized(msg).await
+    }
+
+    /// Send all messages in an [`NlBuffer`] asynchronously in a single system call.
+    pub async fn send_batch<T, P>(&self, msgs: &NlBuffer<T, P>) -> Result<(), SocketError>
+    where
+        T: NlType,
+        P: Size + ToBytes,
+    {
+        self.send_serialized(msgs).await
+    }
+
+    /// Send `msg` followed by `payload` asynchronously in a single datagram
+    /// without copying `payload`.
+    ///
+    /// See [`NlSocketHandle::send_with_payload`][crate::socket::synchronous::NlSocketHandle::send_with_payload]
+    /// for how `payload` is appended to `msg`.
+    pub async fn send_with_payload<T, P>(
+        &self,
+        msg: &Nlmsghdr<T, P>,
+        payload: &[u8],
+    ) -> Result<(), SocketError>
+    where
+        T: NlType,
+        P: Size + ToBytes,
+    {
+        let mut buffer = SendBuffer::take();
+        msg.to_bytes(&mut Cursor::new(&mut *buffer))?;
+        let nl_len = u32::try_from(buffer.len() + payload.len())
+            .map_err(|_| SocketError::new("Message is too large to send"))?;
+        buffer[..size_of::<u32>()].copy_from_slice(&nl_len.to_ne_bytes());
+        let padding = [0; libc::NLA_ALIGNTO as usize];
+        let parts: [&[u8]; 3] = [
+            &buffer,
+            payload,
+            &padding[..alignto(payload.len()) - payload.len()],
+        ];
+        let bufs = parts.map(IoSlice::new);
+        poll_fn(|cx| {
+            self.socket
+                .poll_write_with(cx, &mut |socket| socket.send_vectored(&bufs, Msg::empty()))
+        })
+        .await?;
+        if self.capture.lock().is_some() {
+            self.capture_datagram(Direction::Outgoing, &Groups::empty(), &parts.concat());
         }
         Ok(())
     }
 
+    async fn send_serialized<M>(&self, msg: &M) -> Result<(), SocketError>
+    where
+        M: ToBytes,
+    {
+        let mut buffer = SendBuffer::take();
+        msg.to_bytes(&mut Cursor::new(&mut *buffer))?;
+        poll_fn(|cx| {
+            self.socket
+                .poll_write_with(cx, &mut |socket| socket.send(&*buffer, Msg::empty()))
+        })
+        .await?;
+        self.capture_datagram(Direction::Outgoing, &Groups::empty(), &buffer);
+        Ok(())
+    }
+
     /// Receive a message from the socket asynchronously.
     pub async fn recv<T, P>(
         &self,
@@ -99,23 +286,14 @@ impl NlSocketHandle {
         T: NlType,
         P: Size + FromBytesWithInput<Input = usize>,
     {
-        let groups;
-        let mut buffer = self.pool.acquire().await;
-        loop {
-            let mut guard = self.socket.readable().await?;
-            match guard.try_io(|socket| socket.get_ref().recv(buffer.as_mut_slice(), Msg::empty()))
-            {
-                Ok(Ok((bytes, group))) => {
-                    buffer.reduce_size(bytes);
-                    groups = group;
-                    break;
-                }
-                Ok(Err(e)) => return Err(SocketError::from(e)),
-                Err(_) => (),
-            };
-        }
+        let (buffer, metadata) = self.recv_buffer().await?;
         trace!("Buffer received: {:?}", buffer.as_ref());
-        Ok((NlBufferIter::new(Cursor::new(buffer)), groups))
+        self.capture_datagram(Direction::Incoming, metadata.groups(), buffer.as_ref());
+        let groups = metadata.groups().clone();
+        Ok((
+            NlBufferIter::with_metadata(Cursor::new(buffer), metadata),
+            groups,
+        ))
     }
 
     /// Parse all [`Nlmsghdr`][crate::nl::Nlmsghdr] structs sent in
@@ -131,26 +309,13 @@ impl NlSocketHandle {
         T: NlType + Debug,
         P: Size + FromBytesWithInput<Input = usize> + Debug,
     {
-        let groups;
-        let mut buffer = self.pool.acquire().await;
-        let bytes_read;
-        loop {
-            let mut guard = self.socket.readable().await?;
-            match guard.try_io(|socket| socket.get_ref().recv(buffer.as_mut_slice(), Msg::empty()))
-            {
-                Ok(Ok((bytes, group))) => {
-                    if bytes == 0 {
-                        return Ok((NlBuffer::new(), Groups::empty()));
-                    }
-                    groups = group;
-                    bytes_read = bytes;
-                    buffer.reduce_size(bytes);
-                    break;
-                }
-                Ok(Err(e)) => return Err(SocketError::from(e)),
-                Err(_) => (),
-            };
+        let (buffer, metadata) = self.recv_buffer().await?;
+        let groups = metadata.groups().clone();
+        let bytes_read = buffer.len();
+        if bytes_read == 0 {
+            return Ok((NlBuffer::new(), Groups::empty()));
         }
+        self.capture_datagram(Direction::Incoming, &groups, buffer.as_ref());
 
         let vec = NlBuffer::from_bytes_with_input(&mut Cursor::new(buffer), bytes_read)?;
 
@@ -196,6 +361,23 @@ impl NlSocketHandle {
             .map_err(SocketError::from)
     }
 
+    /// If [`true`] is passed in, suppress `ENOBUFS` errors when the socket receive
+    /// buffer overruns. If [`false`] is passed in, report overruns as errors.
+    pub fn enable_no_enobufs(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .enable_no_enobufs(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if `ENOBUFS` errors are suppressed for this socket.
+    pub fn get_no_enobufs_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_ref()
+            .get_no_enobufs_enabled()
+            .map_err(SocketError::from)
+    }
+
     /// If [`true`] is passed in, enable strict checking for this socket. If [`false`]
     /// is passed in, disable strict checking for for this socket.
     /// Only supported by `NlFamily::Route` sockets.
@@ -216,16 +398,193 @@ impl NlSocketHandle {
             .get_strict_checking_enabled()
             .map_err(SocketError::from)
     }
+
+    /// Set the size of the send buffer for the socket.
+    ///
+    /// The kernel will double the value provided to account for bookkeeping overhead.
+    /// The doubled value is capped by the value in `/proc/sys/net/core/wmem_max`.
+    ///
+    /// See `socket(7)` documentation for `SO_SNDBUF` for more information.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .set_send_buffer_size(size)
+            .map_err(SocketError::from)
+    }
+
+    /// Return the size of the send buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_send_buffer_size(&self) -> Result<usize, SocketError> {
+        self.socket
+            .get_ref()
+            .get_send_buffer_size()
+            .map_err(SocketError::from)
+    }
+
+    /// Return the size of the receive buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_recv_buffer_size(&self) -> Result<usize, SocketError> {
+        self.socket
+            .get_ref()
+            .get_recv_buffer_size()
+            .map_err(SocketError::from)
+    }
+
+    /// Set the size of the receive buffer for the socket, ignoring the limit in
+    /// `/proc/sys/net/core/rmem_max`.
+    ///
+    /// Requires `CAP_NET_ADMIN`. See `socket(7)` documentation for `SO_RCVBUFFORCE`
+    /// for more information.
+    pub fn force_recv_buffer_size(&self, size: usize) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .force_recv_buffer_size(size)
+            .map_err(SocketError::from)
+    }
+
+    /// Set the timeout for blocking receive operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A receive operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .set_recv_timeout(timeout)
+            .map_err(SocketError::from)
+    }
+
+    /// Return the timeout for blocking receive operations.
+    pub fn get_recv_timeout(&self) -> Result<Option<Duration>, SocketError> {
+        self.socket
+            .get_ref()
+            .get_recv_timeout()
+            .map_err(SocketError::from)
+    }
+
+    /// Set the timeout for blocking send operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A send operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .set_send_timeout(timeout)
+            .map_err(SocketError::from)
+    }
+
+    /// Return the timeout for blocking send operations.
+    pub fn get_send_timeout(&self) -> Result<Option<Duration>, SocketError> {
+        self.socket
+            .get_ref()
+            .get_send_timeout()
+            .map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, report errors delivering multicast messages sent
+    /// from this socket. If [`false`] is passed in, only report errors if no
+    /// listener received the message.
+    pub fn enable_broadcast_error(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .enable_broadcast_error(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if multicast delivery errors are reported for this socket.
+    pub fn get_broadcast_error_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_ref()
+            .get_broadcast_error_enabled()
+            .map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, omit the payload of the original request from
+    /// error messages. If [`false`] is passed in, include the original request.
+    pub fn enable_cap_ack(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .enable_cap_ack(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if the original request is omitted from error messages.
+    pub fn get_cap_ack_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_ref()
+            .get_cap_ack_enabled()
+            .map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, ask the kernel to attach the destination multicast
+    /// group to each received message as `NETLINK_PKTINFO` control data. If
+    /// [`false`] is passed in, disable this.
+    pub fn enable_pktinfo(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .enable_pktinfo(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if `NETLINK_PKTINFO` control data is enabled for this
+    /// socket.
+    pub fn get_pktinfo_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_ref()
+            .get_pktinfo_enabled()
+            .map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, receive multicast messages from all network
+    /// namespaces that have an ID assigned in the namespace of the socket. If
+    /// [`false`] is passed in, only receive messages from the namespace of the
+    /// socket.
+    ///
+    /// Requires `CAP_NET_BROADCAST`.
+    pub fn enable_listen_all_nsid(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .enable_listen_all_nsid(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if multicast messages from all network namespaces are
+    /// received by this socket.
+    pub fn get_listen_all_nsid_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_ref()
+            .get_listen_all_nsid_enabled()
+            .map_err(SocketError::from)
+    }
+
+    /// Attach the classic BPF program compiled from `filter` to the socket,
+    /// replacing any filter that is already attached.
+    pub fn attach_filter(&self, filter: &SocketFilter) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .attach_filter(filter)
+            .map_err(SocketError::from)
+    }
+
+    /// Remove the filter attached with [`NlSocketHandle::attach_filter`].
+    pub fn detach_filter(&self) -> Result<(), SocketError> {
+        self.socket
+            .get_ref()
+            .detach_filter()
+            .map_err(SocketError::from)
+    }
 }
 
-impl AsRawFd for NlSocketHandle {
+impl<R: Runtime> AsRawFd for NlSocketHandle<R> {
     fn as_raw_fd(&self) -> RawFd {
         self.socket.get_ref().as_raw_fd()
     }
 }
 
-impl IntoRawFd for NlSocketHandle {
+#[cfg(feature = "tokio")]
+impl IntoRawFd for NlSocketHandle<crate::runtime::TokioRuntime> {
     fn into_raw_fd(self) -> RawFd {
-        self.socket.into_inner().into_raw_fd()
+        tokio::io::unix::AsyncFd::into_inner(self.socket).into_raw_fd()
     }
 }
@@ -0,0 +1,579 @@
+//! Capture of netlink traffic for debugging.
+//!
+//! A [`Capture`] can be attached to a socket handle or router with `set_capture`.
+//! Every datagram sent or received through the handle is then passed to the
+//! capture along with the netlink protocol of the socket.
+//!
+//! [`PcapWriter`] writes the datagrams to a pcap file using the
+//! `LINKTYPE_NETLINK` link type, the same format produced by capturing on an
+//! `nlmon` interface. The resulting file can be opened in Wireshark or
+//! `tcpdump` without root privileges or the `nlmon` kernel module. The
+//! multicast group a received datagram was sent to is stored in the link-layer
+//! address field of the cooked header, which is empty in `nlmon` captures.
+//!
+//! [`PcapReader`] reads the datagrams back, for example to replay a session with
+//! `testing::ReplayTransport`.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use neli::{
+//!     consts::socket::NlFamily,
+//!     socket::{capture::PcapWriter, synchronous::NlSocketHandle},
+//!     utils::Groups,
+//! };
+//!
+//! let socket = NlSocketHandle::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+//! socket
+//!     .set_capture(Some(Arc::new(PcapWriter::create("neli.pcap").unwrap())))
+//!     .unwrap();
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use parking_lot::Mutex;
+
+use crate::{consts::socket::NlFamily, utils::Groups};
+
+/// Link type for netlink captures with a Linux cooked header.
+pub const LINKTYPE_NETLINK: u32 = 253;
+
+/// Hardware type reported in the cooked header of netlink captures.
+const ARPHRD_NETLINK: u16 = 824;
+
+/// Maximum number of bytes of each datagram recorded in a capture.
+const SNAPLEN: u32 = 65535;
+
+/// Magic number of pcap files with microsecond timestamps.
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+
+/// Magic number of pcap files with nanosecond timestamps.
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+/// Length of the Linux cooked header preceding each datagram.
+const COOKED_HEADER_LEN: usize = 16;
+
+/// Direction of a captured datagram.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Datagram received by the socket.
+    Incoming,
+    /// Datagram sent by the socket.
+    Outgoing,
+}
+
+impl Direction {
+    /// Packet type in the cooked header.
+    fn packet_type(self) -> u16 {
+        match self {
+            Direction::Incoming => 0,
+            Direction::Outgoing => 4,
+        }
+    }
+
+    fn from_packet_type(packet_type: u16) -> Self {
+        match packet_type {
+            4 => Direction::Outgoing,
+            _ => Direction::Incoming,
+        }
+    }
+}
+
+/// Hook called with every datagram sent or received on a socket.
+pub trait Capture: Send + Sync {
+    /// Record a datagram sent or received on a socket of family `protocol`.
+    ///
+    /// `groups` contains the multicast groups a received datagram was sent to
+    /// and is empty for unicast and sent datagrams.
+    ///
+    /// Errors are logged and do not fail the send or receive operation.
+    fn capture(
+        &self,
+        direction: Direction,
+        protocol: NlFamily,
+        groups: &Groups,
+        datagram: &[u8],
+    ) -> Result<(), io::Error>;
+}
+
+/// [`Capture`] implementation that writes datagrams to a pcap file with the
+/// [`LINKTYPE_NETLINK`] link type.
+pub struct PcapWriter<W> {
+    writer: Mutex<W>,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Create a pcap file at `path`, truncating it if it exists.
+    pub fn create<P>(path: P) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        PcapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W> PcapWriter<W>
+where
+    W: Write,
+{
+    /// Write the pcap file header to `writer` and return a capture that appends
+    /// records to it.
+    pub fn new(mut writer: W) -> Result<Self, io::Error> {
+        writer.write_all(&MAGIC_MICROS.to_ne_bytes())?;
+        writer.write_all(&2u16.to_ne_bytes())?;
+        writer.write_all(&4u16.to_ne_bytes())?;
+        // Timezone offset and timestamp accuracy.
+        writer.write_all(&0i32.to_ne_bytes())?;
+        writer.write_all(&0u32.to_ne_bytes())?;
+        writer.write_all(&SNAPLEN.to_ne_bytes())?;
+        writer.write_all(&LINKTYPE_NETLINK.to_ne_bytes())?;
+        Ok(PcapWriter {
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Append a record for `datagram` with the given timestamp.
+    ///
+    /// Only the first of `groups` is recorded.
+    pub fn write_packet(
+        &self,
+        timestamp: SystemTime,
+        direction: Direction,
+        protocol: NlFamily,
+        groups: &Groups,
+        datagram: &[u8],
+    ) -> Result<(), io::Error> {
+        let since_epoch = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let protocol = u16::try_from(libc::c_int::from(protocol))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let group = groups.as_groups().first().copied();
+        let orig_len = u32::try_from(datagram.len() + COOKED_HEADER_LEN).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Datagram is too long for a pcap record",
+            )
+        })?;
+        let incl_len = orig_len.min(SNAPLEN);
+        if incl_len < orig_len {
+            warn!(
+                "Truncating captured datagram of {} bytes to {} bytes",
+                datagram.len(),
+                incl_len as usize - COOKED_HEADER_LEN
+            );
+        }
+
+        let mut writer = self.writer.lock();
+        writer.write_all(&(since_epoch.as_secs() as u32).to_ne_bytes())?;
+        writer.write_all(&since_epoch.subsec_micros().to_ne_bytes())?;
+        writer.write_all(&incl_len.to_ne_bytes())?;
+        writer.write_all(&orig_len.to_ne_bytes())?;
+        // Linux cooked header; unlike the netlink payload, these fields are
+        // big endian.
+        writer.write_all(&direction.packet_type().to_be_bytes())?;
+        writer.write_all(&ARPHRD_NETLINK.to_be_bytes())?;
+        let mut address = [0; 8];
+        match group {
+            Some(group) => {
+                writer.write_all(&4u16.to_be_bytes())?;
+                address[..4].copy_from_slice(&group.to_be_bytes());
+            }
+            None => writer.write_all(&0u16.to_be_bytes())?,
+        }
+        writer.write_all(&address)?;
+        writer.write_all(&protocol.to_be_bytes())?;
+        writer.write_all(&datagram[..incl_len as usize - COOKED_HEADER_LEN])?;
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&self) -> Result<(), io::Error> {
+        self.writer.lock().flush()
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl<W> Capture for PcapWriter<W>
+where
+    W: Write + Send,
+{
+    fn capture(
+        &self,
+        direction: Direction,
+        protocol: NlFamily,
+        groups: &Groups,
+        datagram: &[u8],
+    ) -> Result<(), io::Error> {
+        self.write_packet(SystemTime::now(), direction, protocol, groups, datagram)
+    }
+}
+
+/// Datagram read from a capture file.
+pub struct CapturedDatagram {
+    /// Time at which the datagram was captured.
+    pub timestamp: SystemTime,
+    /// Whether the datagram was sent or received.
+    pub direction: Direction,
+    /// Netlink protocol family of the socket.
+    pub protocol: NlFamily,
+    /// Multicast groups the datagram was sent to.
+    pub groups: Groups,
+    /// Contents of the datagram, truncated to the snapshot length of the
+    /// capture.
+    pub datagram: Vec<u8>,
+}
+
+/// Iterator over the datagrams in a pcap file with the [`LINKTYPE_NETLINK`] link
+/// type, such as the files written by [`PcapWriter`] or captured on an `nlmon`
+/// interface.
+pub struct PcapReader<R> {
+    reader: R,
+    swapped: bool,
+    nanos: bool,
+    snaplen: u32,
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Open the pcap file at `path`.
+    pub fn open<P>(path: P) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        PcapReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R> PcapReader<R>
+where
+    R: Read,
+{
+    /// Read and validate the pcap file header from `reader`.
+    pub fn new(mut reader: R) -> Result<Self, io::Error> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header)?;
+        let magic = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]);
+        let (swapped, nanos) = match magic {
+            MAGIC_MICROS => (false, false),
+            MAGIC_NANOS => (false, true),
+            m if m == MAGIC_MICROS.swap_bytes() => (true, false),
+            m if m == MAGIC_NANOS.swap_bytes() => (true, true),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Not a pcap file",
+                ))
+            }
+        };
+        let mut pcap = PcapReader {
+            reader,
+            swapped,
+            nanos,
+            snaplen: 0,
+        };
+        pcap.snaplen = pcap.u32_at(&header, 16);
+        let linktype = pcap.u32_at(&header, 20);
+        if linktype != LINKTYPE_NETLINK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected link type {LINKTYPE_NETLINK}; found {linktype}"),
+            ));
+        }
+        Ok(pcap)
+    }
+
+    fn u32_at(&self, bytes: &[u8], offset: usize) -> u32 {
+        let value = u32::from_ne_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ]);
+        if self.swapped {
+            value.swap_bytes()
+        } else {
+            value
+        }
+    }
+
+    fn read_datagram(&mut self) -> Result<Option<CapturedDatagram>, io::Error> {
+        let mut header = [0; 16];
+        let mut read = 0;
+        while read < header.len() {
+            match self.reader.read(&mut header[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        match read {
+            0 => return Ok(None),
+            16 => (),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Truncated packet header",
+                ))
+            }
+        }
+        let secs = u64::from(self.u32_at(&header, 0));
+        let fraction = self.u32_at(&header, 4);
+        let nanos = if self.nanos {
+            Some(fraction)
+        } else {
+            fraction.checked_mul(1000)
+        }
+        .filter(|nanos| *nanos < 1_000_000_000)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid packet timestamp"))?;
+        let incl_len = self.u32_at(&header, 8);
+        if incl_len > self.snaplen {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Packet length {incl_len} exceeds the snapshot length {}",
+                    self.snaplen
+                ),
+            ));
+        }
+        let incl_len = incl_len as usize;
+
+        let mut packet = vec![0; incl_len];
+        self.reader.read_exact(&mut packet)?;
+        if incl_len < COOKED_HEADER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Packet is shorter than the cooked header",
+            ));
+        }
+        let be_u16 = |offset: usize| u16::from_be_bytes([packet[offset], packet[offset + 1]]);
+        let direction = Direction::from_packet_type(be_u16(0));
+        let groups = if be_u16(4) >= 4 {
+            let group = u32::from_be_bytes([packet[6], packet[7], packet[8], packet[9]]);
+            Groups::new_groups(&[group])
+        } else {
+            Groups::empty()
+        };
+        let protocol = NlFamily::from(libc::c_int::from(be_u16(14)));
+        packet.drain(..COOKED_HEADER_LEN);
+
+        Ok(Some(CapturedDatagram {
+            timestamp: UNIX_EPOCH + Duration::new(secs, nanos),
+            direction,
+            protocol,
+            groups,
+            datagram: packet,
+        }))
+    }
+}
+
+impl<R> Iterator for PcapReader<R>
+where
+    R: Read,
+{
+    type Item = Result<CapturedDatagram, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_datagram().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_pcap_writer() {
+        let writer = PcapWriter::new(Vec::new()).unwrap();
+        writer
+            .write_packet(
+                UNIX_EPOCH + Duration::new(2, 3000),
+                Direction::Outgoing,
+                NlFamily::Generic,
+                &Groups::empty(),
+                &[1, 2, 3, 4],
+            )
+            .unwrap();
+        let bytes = writer.into_inner();
+
+        let mut expected = Vec::new();
+        for field in [0xa1b2_c3d4u32.to_ne_bytes(), [0; 4], [0; 4], [0; 4]] {
+            expected.extend_from_slice(&field);
+        }
+        expected[4..6].copy_from_slice(&2u16.to_ne_bytes());
+        expected[6..8].copy_from_slice(&4u16.to_ne_bytes());
+        expected.extend_from_slice(&65535u32.to_ne_bytes());
+        expected.extend_from_slice(&253u32.to_ne_bytes());
+        for field in [2u32, 3, 20, 20] {
+            expected.extend_from_slice(&field.to_ne_bytes());
+        }
+        expected.extend_from_slice(&[0, 4, 3, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 16]);
+        expected.extend_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(bytes, expected);
+
+        let writer = PcapWriter::new(Vec::new()).unwrap();
+        writer
+            .write_packet(
+                UNIX_EPOCH,
+                Direction::Incoming,
+                NlFamily::Generic,
+                &Groups::empty(),
+                &[0; 70000],
+            )
+            .unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes[32..36], 65535u32.to_ne_bytes());
+        assert_eq!(bytes[36..40], 70016u32.to_ne_bytes());
+        assert_eq!(bytes.len(), 40 + 65535);
+    }
+
+    #[test]
+    fn test_pcap_reader() {
+        let writer = PcapWriter::new(Vec::new()).unwrap();
+        writer
+            .write_packet(
+                UNIX_EPOCH + Duration::new(2, 3000),
+                Direction::Outgoing,
+                NlFamily::Route,
+                &Groups::empty(),
+                &[1, 2, 3, 4],
+            )
+            .unwrap();
+        writer
+            .write_packet(
+                UNIX_EPOCH + Duration::new(5, 0),
+                Direction::Incoming,
+                NlFamily::Route,
+                &Groups::new_groups(&[40]),
+                &[5, 6],
+            )
+            .unwrap();
+        let bytes = writer.into_inner();
+
+        let datagrams = PcapReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].timestamp, UNIX_EPOCH + Duration::new(2, 3000));
+        assert_eq!(datagrams[0].direction, Direction::Outgoing);
+        assert_eq!(datagrams[0].protocol, NlFamily::Route);
+        assert!(datagrams[0].groups.is_empty());
+        assert_eq!(datagrams[0].datagram, vec![1, 2, 3, 4]);
+        assert_eq!(datagrams[1].direction, Direction::Incoming);
+        assert_eq!(datagrams[1].groups.as_groups(), vec![40]);
+        assert_eq!(datagrams[1].datagram, vec![5, 6]);
+
+        assert!(PcapReader::new(&bytes[..10]).is_err());
+
+        let mut invalid = bytes.clone();
+        invalid[28..32].copy_from_slice(&u32::MAX.to_ne_bytes());
+        assert!(matches!(
+            PcapReader::new(invalid.as_slice()).unwrap().next(),
+            Some(Err(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+
+        let mut invalid = bytes.clone();
+        invalid[16..20].copy_from_slice(&4u32.to_ne_bytes());
+        assert!(matches!(
+            PcapReader::new(invalid.as_slice()).unwrap().next(),
+            Some(Err(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+
+        let mut invalid = bytes.clone();
+        invalid[..4].copy_from_slice(&0xa1b2_3c4du32.to_ne_bytes());
+        invalid[28..32].copy_from_slice(&1_000_000_000u32.to_ne_bytes());
+        assert!(matches!(
+            PcapReader::new(invalid.as_slice()).unwrap().next(),
+            Some(Err(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+
+        // Cut the second record in the middle of its header.
+        let mut reader = PcapReader::new(&bytes[..bytes.len() - 26]).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn real_test_capture() {
+        use std::sync::Arc;
+
+        use crate::{
+            consts::{
+                genl::{CtrlAttr, CtrlCmd},
+                nl::{GenlId, NlmF},
+            },
+            genl::{GenlmsghdrBuilder, NoUserHeader},
+            nl::{NlPayload, NlmsghdrBuilder},
+            socket::synchronous::NlSocketHandle,
+            test::setup,
+            types::Buffer,
+        };
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(Direction, NlFamily, Vec<u8>)>>);
+
+        impl Capture for Recorder {
+            fn capture(
+                &self,
+                direction: Direction,
+                protocol: NlFamily,
+                _: &Groups,
+                datagram: &[u8],
+            ) -> Result<(), io::Error> {
+                self.0.lock().push((direction, protocol, datagram.to_vec()));
+                Ok(())
+            }
+        }
+
+        setup();
+
+        let recorder = Arc::new(Recorder::default());
+        let socket = NlSocketHandle::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        socket
+            .set_capture(Some(Arc::clone(&recorder) as Arc<dyn Capture>))
+            .unwrap();
+        socket
+            .send(
+                &NlmsghdrBuilder::default()
+                    .nl_type(GenlId::Ctrl)
+                    .nl_flags(NlmF::REQUEST)
+                    .nl_payload(NlPayload::Payload(
+                        GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                            .cmd(CtrlCmd::Getfamily)
+                            .version(2)
+                            .build()
+                            .unwrap(),
+                    ))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        socket.recv::<GenlId, Buffer>().unwrap();
+        socket.set_capture(None).unwrap();
+
+        let captured = recorder.0.lock();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0].0, Direction::Outgoing);
+        assert_eq!(captured[0].1, NlFamily::Generic);
+        assert_eq!(captured[0].2.len(), 20);
+        assert_eq!(captured[1].0, Direction::Incoming);
+        assert_eq!(captured[1].1, NlFamily::Generic);
+    }
+}
@@ -0,0 +1,385 @@
+//! Classic BPF socket filters for netlink sockets.
+//!
+//! A [`SocketFilter`] compiles predicates over the first netlink message of a
+//! datagram into a classic BPF program. Attaching it with
+//! [`NlSocket::attach_filter`][crate::socket::NlSocket::attach_filter] makes the
+//! kernel drop datagrams that do not match before they are queued on the socket,
+//! so a process subscribed to a busy multicast group is only woken up for the
+//! messages it handles.
+//!
+//! A datagram is accepted if all predicates match. Only the first message of a
+//! datagram is inspected, which covers multicast notifications as they carry a
+//! single message. `NLMSG_NOOP`, `NLMSG_ERROR`, `NLMSG_DONE` and
+//! `NLMSG_OVERRUN` messages are always accepted so that ACKs, errors and the
+//! end of dumps still reach requests sent on a filtered socket.
+//!
+//! Other replies to requests are filtered like multicast messages. Use
+//! [`SocketFilter::accept_pid`] with the port ID of the socket to accept every
+//! message addressed to it. Routers do this when attaching a filter so that
+//! responses are never dropped.
+//!
+//! ```no_run
+//! use neli::{
+//!     consts::{rtnl::Rtm, socket::NlFamily},
+//!     socket::{filter::SocketFilter, NlSocket},
+//!     utils::Groups,
+//! };
+//!
+//! let socket = NlSocket::connect(NlFamily::Route, None, Groups::empty()).unwrap();
+//! let filter = SocketFilter::new()
+//!     .nl_type_in([Rtm::Newneigh, Rtm::Delneigh])
+//!     .ifindex(2);
+//! socket.attach_filter(&filter).unwrap();
+//! ```
+
+use std::io;
+
+use libc::{
+    sock_filter, BPF_ABS, BPF_B, BPF_H, BPF_JA, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_MAXINSNS,
+    BPF_RET, BPF_W,
+};
+
+use crate::consts::nl::Nlmsg;
+
+/// Offset of `nlmsg_type` in the netlink header.
+const NL_TYPE_OFFSET: u32 = 4;
+/// Offset of `nlmsg_pid` in the netlink header.
+const NL_PID_OFFSET: u32 = 12;
+/// Length of the netlink header, which is followed by the family header.
+const NLMSGHDR_LEN: u32 = 16;
+/// Offset of the interface index in `ifinfomsg`, `ifaddrmsg` and `ndmsg`.
+const IFINDEX_OFFSET: u32 = 4;
+/// Return value accepting the whole datagram.
+const ACCEPT: u32 = u32::MAX;
+/// Return value dropping the datagram.
+const REJECT: u32 = 0;
+
+/// Width of a field loaded by the filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Width {
+    U8,
+    U16,
+    U32,
+}
+
+impl Width {
+    fn size(self) -> u32 {
+        match self {
+            Width::U8 => BPF_B,
+            Width::U16 => BPF_H,
+            Width::U32 => BPF_W,
+        }
+    }
+}
+
+/// A field that must be equal to one of a set of values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Predicate {
+    offset: u32,
+    width: Width,
+    values: Vec<u32>,
+}
+
+impl Predicate {
+    /// Classic BPF loads fields in network byte order while netlink fields are in
+    /// host byte order, so values are converted to what the load will produce.
+    fn new<I>(offset: u32, width: Width, values: I) -> Self
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        let values = values
+            .into_iter()
+            .map(|value| match width {
+                Width::U8 => value,
+                Width::U16 => u32::from(u16::from_be_bytes((value as u16).to_ne_bytes())),
+                Width::U32 => u32::from_be_bytes(value.to_ne_bytes()),
+            })
+            .collect();
+        Predicate {
+            offset,
+            width,
+            values,
+        }
+    }
+
+    /// Load the field, jump past the predicate if it is equal to one of the
+    /// values and drop the datagram otherwise.
+    fn compile(&self, program: &mut Vec<sock_filter>) -> Result<(), io::Error> {
+        if self.values.len() > u8::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A predicate can match at most 255 values",
+            ));
+        }
+        program.push(stmt(BPF_LD | self.width.size() | BPF_ABS, self.offset));
+        for (i, value) in self.values.iter().enumerate() {
+            program.push(jump(
+                BPF_JMP | BPF_JEQ | BPF_K,
+                *value,
+                (self.values.len() - i) as u8,
+                0,
+            ));
+        }
+        program.push(stmt(BPF_RET | BPF_K, REJECT));
+        Ok(())
+    }
+}
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Builder for a classic BPF program filtering datagrams received on a netlink
+/// socket.
+///
+/// Offsets passed to the field predicates are relative to the start of the
+/// family header following the netlink header, such as
+/// [`Genlmsghdr`][crate::genl::Genlmsghdr] or
+/// [`Ifinfomsg`][crate::rtnl::Ifinfomsg]. Fields are compared in host byte
+/// order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocketFilter {
+    pid: Option<u32>,
+    predicates: Vec<Predicate>,
+}
+
+impl SocketFilter {
+    /// Create a filter accepting every datagram.
+    pub fn new() -> Self {
+        SocketFilter::default()
+    }
+
+    /// Accept every message with the `nlmsg_pid` `pid` regardless of the other
+    /// predicates.
+    ///
+    /// Passing the port ID of the socket accepts all replies to requests sent
+    /// from it.
+    pub fn accept_pid(mut self, pid: u32) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Only accept messages with one of the given `nlmsg_type` values.
+    pub fn nl_type_in<I, T>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<u16>,
+    {
+        self.predicates.push(Predicate::new(
+            NL_TYPE_OFFSET,
+            Width::U16,
+            types.into_iter().map(|ty| u32::from(ty.into())),
+        ));
+        self
+    }
+
+    /// Only accept generic netlink messages with the command `cmd`.
+    pub fn genl_cmd<C>(self, cmd: C) -> Self
+    where
+        C: Into<u8>,
+    {
+        self.u8_in(0, [cmd.into()])
+    }
+
+    /// Only accept rtnetlink link, address and neighbor messages for the
+    /// interface `ifindex`.
+    ///
+    /// The interface index is at the same offset in
+    /// [`Ifinfomsg`][crate::rtnl::Ifinfomsg], [`Ifaddrmsg`][crate::rtnl::Ifaddrmsg]
+    /// and [`Ndmsg`][crate::rtnl::Ndmsg].
+    pub fn ifindex(self, ifindex: i32) -> Self {
+        self.u32_in(IFINDEX_OFFSET, [ifindex as u32])
+    }
+
+    /// Only accept messages with one of `values` in the `u8` at `offset` in the
+    /// family header.
+    pub fn u8_in<I>(mut self, offset: u32, values: I) -> Self
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        self.predicates.push(Predicate::new(
+            NLMSGHDR_LEN + offset,
+            Width::U8,
+            values.into_iter().map(u32::from),
+        ));
+        self
+    }
+
+    /// Only accept messages with one of `values` in the `u16` at `offset` in the
+    /// family header.
+    pub fn u16_in<I>(mut self, offset: u32, values: I) -> Self
+    where
+        I: IntoIterator<Item = u16>,
+    {
+        self.predicates.push(Predicate::new(
+            NLMSGHDR_LEN + offset,
+            Width::U16,
+            values.into_iter().map(u32::from),
+        ));
+        self
+    }
+
+    /// Only accept messages with one of `values` in the `u32` at `offset` in the
+    /// family header.
+    pub fn u32_in<I>(mut self, offset: u32, values: I) -> Self
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        self.predicates
+            .push(Predicate::new(NLMSGHDR_LEN + offset, Width::U32, values));
+        self
+    }
+
+    /// Compile the filter into classic BPF instructions.
+    ///
+    /// Returns an error if a predicate matches more than 255 values or the
+    /// program exceeds the maximum number of instructions.
+    pub fn compile(&self) -> Result<Vec<sock_filter>, io::Error> {
+        let mut program = Vec::new();
+        let control = Predicate::new(
+            NL_TYPE_OFFSET,
+            Width::U16,
+            [Nlmsg::Noop, Nlmsg::Error, Nlmsg::Done, Nlmsg::Overrun]
+                .into_iter()
+                .map(|ty| u32::from(u16::from(ty))),
+        );
+        program.push(stmt(
+            BPF_LD | control.width.size() | BPF_ABS,
+            control.offset,
+        ));
+        for (i, value) in control.values.iter().enumerate() {
+            // Skip the remaining comparisons and the jump over the accept.
+            program.push(jump(
+                BPF_JMP | BPF_JEQ | BPF_K,
+                *value,
+                (control.values.len() - i) as u8,
+                0,
+            ));
+        }
+        program.push(stmt(BPF_JMP | BPF_JA, 1));
+        program.push(stmt(BPF_RET | BPF_K, ACCEPT));
+
+        if let Some(pid) = self.pid {
+            let pid = Predicate::new(NL_PID_OFFSET, Width::U32, [pid]);
+            program.push(stmt(BPF_LD | pid.width.size() | BPF_ABS, pid.offset));
+            program.push(jump(BPF_JMP | BPF_JEQ | BPF_K, pid.values[0], 0, 1));
+            program.push(stmt(BPF_RET | BPF_K, ACCEPT));
+        }
+
+        for predicate in self.predicates.iter() {
+            predicate.compile(&mut program)?;
+        }
+        program.push(stmt(BPF_RET | BPF_K, ACCEPT));
+
+        if program.len() > BPF_MAXINSNS as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Filter exceeds the maximum number of BPF instructions",
+            ));
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{consts::rtnl::Rtm, test::setup};
+
+    /// Run a classic BPF program restricted to the instructions generated by
+    /// [`SocketFilter`].
+    fn run(program: &[sock_filter], packet: &[u8]) -> u32 {
+        let mut acc = 0;
+        let mut pc = 0;
+        loop {
+            let insn = program[pc];
+            let code = u32::from(insn.code);
+            pc += 1;
+            if code == BPF_RET | BPF_K {
+                return insn.k;
+            } else if code == BPF_JMP | BPF_JA {
+                pc += insn.k as usize;
+            } else if code == BPF_JMP | BPF_JEQ | BPF_K {
+                pc += usize::from(if acc == insn.k { insn.jt } else { insn.jf });
+            } else {
+                let offset = insn.k as usize;
+                let size = match code & 0x18 {
+                    BPF_B => 1,
+                    BPF_H => 2,
+                    _ => 4,
+                };
+                let Some(bytes) = packet.get(offset..offset + size) else {
+                    return REJECT;
+                };
+                acc = bytes.iter().fold(0, |acc, b| (acc << 8) | u32::from(*b));
+            }
+        }
+    }
+
+    fn message(nl_type: u16, family_header: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&(NLMSGHDR_LEN + family_header.len() as u32).to_ne_bytes());
+        packet.extend_from_slice(&nl_type.to_ne_bytes());
+        packet.extend_from_slice(&[0; 10]);
+        packet.extend_from_slice(family_header);
+        packet
+    }
+
+    fn ifinfomsg(ifindex: i32) -> Vec<u8> {
+        let mut header = vec![0; 4];
+        header.extend_from_slice(&ifindex.to_ne_bytes());
+        header.extend_from_slice(&[0; 8]);
+        header
+    }
+
+    #[test]
+    fn test_filter() {
+        setup();
+
+        let program = SocketFilter::new()
+            .nl_type_in([Rtm::Newlink, Rtm::Dellink])
+            .ifindex(3)
+            .compile()
+            .unwrap();
+        let newlink = u16::from(Rtm::Newlink);
+        let newaddr = u16::from(Rtm::Newaddr);
+        assert_eq!(run(&program, &message(newlink, &ifinfomsg(3))), ACCEPT);
+        assert_eq!(run(&program, &message(newlink, &ifinfomsg(4))), REJECT);
+        assert_eq!(run(&program, &message(newaddr, &ifinfomsg(3))), REJECT);
+        assert_eq!(
+            run(&program, &message(u16::from(Nlmsg::Error), &[0; 20])),
+            ACCEPT
+        );
+        assert_eq!(run(&program, &message(newlink, &[])), REJECT);
+
+        let program = SocketFilter::new().compile().unwrap();
+        assert_eq!(run(&program, &message(newaddr, &[])), ACCEPT);
+
+        let program = SocketFilter::new()
+            .accept_pid(1234)
+            .nl_type_in([Rtm::Newlink])
+            .compile()
+            .unwrap();
+        let mut reply = message(newaddr, &[]);
+        reply[12..16].copy_from_slice(&1234u32.to_ne_bytes());
+        assert_eq!(run(&program, &reply), ACCEPT);
+        reply[12..16].copy_from_slice(&4321u32.to_ne_bytes());
+        assert_eq!(run(&program, &reply), REJECT);
+        assert_eq!(run(&program, &message(newlink, &[])), ACCEPT);
+
+        assert!(SocketFilter::new()
+            .u16_in(0, 0..=u16::from(u8::MAX))
+            .compile()
+            .is_err());
+    }
+}
@@ -11,9 +11,40 @@
 //!   are meant to provide an interface that is more idiomatic for
 //!   the library.
 //!
+//! ## Transports
+//! The synchronous [`NlSocketHandle`][crate::socket::synchronous::NlSocketHandle]
+//! is generic over a [`Transport`]. [`NlSocket`] is the default transport and
+//! other implementations can replace the kernel in tests.
+//!
+//! ## Capture
+//! Socket handles and routers accept a
+//! [`Capture`][crate::socket::capture::Capture] hook through `set_capture`.
+//! [`PcapWriter`][crate::socket::capture::PcapWriter] writes the traffic to a
+//! pcap file that Wireshark dissects like an `nlmon` interface capture.
+//!
+//! ## Filters
+//! [`SocketFilter`][crate::socket::filter::SocketFilter] builds a classic BPF
+//! program over the netlink header and family header that
+//! [`NlSocket::attach_filter`] attaches to the socket, so the kernel drops
+//! unwanted multicast messages before waking up the process.
+//!
+//! ## Network namespaces
+//! Sockets and socket handles have `connect_in_netns` constructors that create
+//! the socket inside the network namespace referred to by a
+//! [`NetNs`][crate::socket::netns::NetNs] without moving the calling thread.
+//! Routers take the namespace from the `RouterConfig` passed to
+//! `connect_with_config`.
+//!
 //! ## Features
 //! The `async` feature exposed by `cargo` allows the socket to use
-//! Rust's [tokio](https://tokio.rs) for async IO.
+//! Rust's [tokio](https://tokio.rs) for async IO. The asynchronous API itself is
+//! runtime-agnostic and is available with the `async-core` feature. The `tokio` and
+//! `async-io` features provide backends for it. See the `runtime` module for details.
+//!
+//! The `mio` feature implements `mio::event::Source` for the synchronous
+//! `NlSocketHandle` and the `calloop` feature provides
+//! `synchronous::NlSocketSource`, a `calloop` event source that passes parsed
+//! messages to a callback.
 //!
 //! ## Additional methods
 //!
@@ -29,19 +60,37 @@
 //! that large could be a problem on the stack.
 //!
 //! neli now uses [`BufferPool`][crate::utils::synchronous::BufferPool] to manage
-//! parallel message receive operations. Memory usage can be tuned using the following
-//! environment variables at compile time:
+//! parallel message receive operations. Memory usage can be tuned for each socket
+//! with a [`BufferPoolConfig`][crate::utils::BufferPoolConfig] passed to
+//! `with_buffer_pool` on socket handles or in the `RouterConfig` of a router.
+//! The defaults can be changed using the following environment variables at
+//! compile time:
 //! * `NELI_AUTO_BUFFER_LEN`: This configures how many bytes are allocated for each
 //!   buffer in the buffer pool.
 //! * `NELI_MAX_PARALLEL_READ_OPS`: This configures how many buffers of size
 //!   `NELI_AUTO_BUFFER_LEN` are allocated for parallel receive operations.
+//!
+//! Datagrams that do not fit in a buffer are reported as
+//! [`SocketError::Truncated`][crate::err::SocketError::Truncated]. Socket handles
+//! and routers can instead peek at the size of each datagram with `MSG_PEEK` and
+//! `MSG_TRUNC` and grow the buffer on demand with
+//! [`BufferGrowth::OnDemand`][crate::utils::BufferGrowth::OnDemand] or
+//! `enable_adaptive_buffers`.
 
 /// Asynchronous socket operations
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub mod asynchronous;
+#[cfg(any(feature = "sync", feature = "async-core"))]
+pub mod capture;
+pub mod filter;
+pub mod netns;
 mod shared;
 /// Synchronous socket operations
 #[cfg(feature = "sync")]
 pub mod synchronous;
+mod transport;
 
-pub use crate::socket::shared::NlSocket;
+pub use crate::socket::{
+    shared::{NlSocket, RecvMetadata},
+    transport::Transport,
+};
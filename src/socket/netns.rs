@@ -0,0 +1,156 @@
+//! Network namespaces for netlink sockets.
+//!
+//! A netlink socket talks to the network namespace it was created in, no matter
+//! which namespace the thread using it is in. [`NetNs`] refers to a network
+//! namespace and the `connect_in_netns` constructors of sockets and socket
+//! handles create a socket inside it. Routers accept it in their `RouterConfig`.
+//! The socket is created on a short-lived helper thread that enters the
+//! namespace with `setns`, so the calling thread never changes namespace and
+//! the returned socket can be used from any thread.
+//!
+//! Entering a network namespace requires `CAP_SYS_ADMIN`.
+//!
+//! ```no_run
+//! use neli::{
+//!     consts::socket::NlFamily,
+//!     socket::{netns::NetNs, NlSocket},
+//!     utils::Groups,
+//! };
+//!
+//! let netns = NetNs::from_name("blue").unwrap();
+//! let socket = NlSocket::connect_in_netns(&netns, NlFamily::Route, None, Groups::empty()).unwrap();
+//! ```
+
+use std::{
+    fs::File,
+    io,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    path::Path,
+    thread,
+};
+
+/// Directory where `ip netns` mounts named network namespaces.
+const NAMED_NETNS_DIR: &str = "/run/netns";
+
+/// Handle to a network namespace.
+#[derive(Debug)]
+pub struct NetNs(OwnedFd);
+
+impl NetNs {
+    /// Open the network namespace at `path`, such as `/proc/<pid>/ns/net` or
+    /// `/run/netns/<name>`.
+    pub fn open<P>(path: P) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(NetNs(OwnedFd::from(File::open(path)?)))
+    }
+
+    /// Open the network namespace of the process `pid`.
+    pub fn from_pid(pid: u32) -> Result<Self, io::Error> {
+        NetNs::open(format!("/proc/{pid}/ns/net"))
+    }
+
+    /// Open the network namespace created by `ip netns add <name>`.
+    ///
+    /// Names containing `/` or equal to `.` or `..` are rejected so that only
+    /// namespaces in `/run/netns` can be opened.
+    pub fn from_name(name: &str) -> Result<Self, io::Error> {
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid network namespace name {name:?}"),
+            ));
+        }
+        NetNs::open(Path::new(NAMED_NETNS_DIR).join(name))
+    }
+
+    /// Run `f` on a helper thread that has entered this network namespace.
+    pub(crate) fn run<F, T>(&self, f: F) -> Result<T, io::Error>
+    where
+        F: FnOnce() -> Result<T, io::Error> + Send,
+        T: Send,
+    {
+        let fd = self.0.as_fd();
+        thread::scope(|scope| {
+            scope
+                .spawn(move || {
+                    enter(fd)?;
+                    f()
+                })
+                .join()
+                .unwrap_or_else(|_| Err(helper_panicked()))
+        })
+    }
+
+    /// Run `f` on a helper thread that has entered this network namespace
+    /// without blocking the calling task while waiting for the result.
+    #[cfg(feature = "async-core")]
+    pub(crate) async fn run_async<F, T>(&self, f: F) -> Result<T, io::Error>
+    where
+        F: FnOnce() -> Result<T, io::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let fd = self.0.try_clone()?;
+        let (sender, receiver) = futures_channel::oneshot::channel();
+        thread::Builder::new().spawn(move || {
+            let _ = sender.send(enter(fd.as_fd()).and_then(|()| f()));
+        })?;
+        receiver.await.unwrap_or_else(|_| Err(helper_panicked()))
+    }
+}
+
+/// Move the calling thread into the network namespace `fd`.
+fn enter(fd: BorrowedFd<'_>) -> Result<(), io::Error> {
+    if unsafe { libc::setns(fd.as_raw_fd(), libc::CLONE_NEWNET) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn helper_panicked() -> io::Error {
+    io::Error::other("Network namespace helper thread panicked")
+}
+
+impl From<OwnedFd> for NetNs {
+    fn from(fd: OwnedFd) -> Self {
+        NetNs(fd)
+    }
+}
+
+impl From<NetNs> for OwnedFd {
+    fn from(netns: NetNs) -> Self {
+        netns.0
+    }
+}
+
+impl AsFd for NetNs {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for NetNs {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test::setup;
+
+    #[test]
+    fn test_from_name() {
+        setup();
+
+        for name in ["", ".", "..", "../../proc/self/ns/net", "a/b"] {
+            assert_eq!(
+                NetNs::from_name(name).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+}
@@ -1,19 +1,50 @@
 use std::{
-    io,
+    io::{self, IoSlice},
     mem::{size_of, zeroed, MaybeUninit},
-    os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd},
+    time::Duration,
 };
 
+use getset::Getters;
 use libc::{c_int, c_void, sockaddr, sockaddr_nl};
 
-#[cfg(feature = "async")]
-use crate::socket::asynchronous;
 #[cfg(feature = "sync")]
 use crate::socket::synchronous;
 use crate::{
     consts::socket::*,
+    socket::{filter::SocketFilter, netns::NetNs},
     utils::{Groups, NetlinkBitArray},
 };
+#[cfg(feature = "async-core")]
+use crate::{err::SocketError, runtime::Runtime, socket::asynchronous};
+
+/// Size of the buffer for control messages received with
+/// [`NlSocket::recv_msg`], large enough for `NETLINK_PKTINFO` and
+/// `NETLINK_LISTEN_ALL_NSID`.
+const CMSG_BUFFER_LEN: usize = 64;
+
+/// Information about a received datagram beyond its contents.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Getters)]
+pub struct RecvMetadata {
+    /// Port ID of the sender. This is 0 for the kernel.
+    #[getset(get = "pub")]
+    pid: u32,
+    /// Multicast groups the datagram was sent to. Group numbers above 32 are only
+    /// reported if `NETLINK_PKTINFO` is enabled on the socket.
+    #[getset(get = "pub")]
+    groups: Groups,
+    /// ID of the network namespace the datagram originated from. Only reported
+    /// if `NETLINK_LISTEN_ALL_NSID` is enabled on the socket.
+    #[getset(get = "pub")]
+    nsid: Option<i32>,
+}
+
+impl RecvMetadata {
+    /// Create metadata for a datagram.
+    pub fn new(pid: u32, groups: Groups, nsid: Option<i32>) -> Self {
+        RecvMetadata { pid, groups, nsid }
+    }
+}
 
 /// Low level access to a netlink socket.
 pub struct NlSocket {
@@ -44,6 +75,20 @@ impl NlSocket {
         Ok(s)
     }
 
+    /// Equivalent of [`NlSocket::connect`] creating the socket inside the
+    /// network namespace `netns`.
+    ///
+    /// The socket is created on a helper thread that enters `netns`, so the
+    /// calling thread stays in its namespace.
+    pub fn connect_in_netns(
+        netns: &NetNs,
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> Result<Self, io::Error> {
+        netns.run(|| NlSocket::connect(proto, pid, groups))
+    }
+
     /// Set underlying socket file descriptor to be blocking.
     pub fn block(&self) -> Result<(), io::Error> {
         match unsafe {
@@ -116,36 +161,13 @@ impl NlSocket {
     ///
     /// See `socket(7)` documentation for `SO_RCVBUF` for more information.
     pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), io::Error> {
-        let size = size as c_int;
-        match unsafe {
-            libc::setsockopt(
-                self.fd,
-                libc::SOL_SOCKET,
-                libc::SO_RCVBUF,
-                &size as *const _ as *const c_void,
-                size_of::<c_int>() as libc::socklen_t,
-            )
-        } {
-            0 => Ok(()),
-            _ => Err(io::Error::last_os_error()),
-        }
+        self.set_option(libc::SOL_SOCKET, libc::SO_RCVBUF, &buffer_size(size)?)
     }
 
     /// Join multicast groups for a socket.
     pub fn add_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
         for group in groups.as_groups() {
-            match unsafe {
-                libc::setsockopt(
-                    self.fd,
-                    libc::SOL_NETLINK,
-                    libc::NETLINK_ADD_MEMBERSHIP,
-                    &group as *const _ as *const libc::c_void,
-                    size_of::<u32>() as libc::socklen_t,
-                )
-            } {
-                0 => (),
-                _ => return Err(io::Error::last_os_error()),
-            }
+            self.set_option(libc::SOL_NETLINK, libc::NETLINK_ADD_MEMBERSHIP, &group)?;
         }
         Ok(())
     }
@@ -153,18 +175,7 @@ impl NlSocket {
     /// Leave multicast groups for a socket.
     pub fn drop_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
         for group in groups.as_groups() {
-            match unsafe {
-                libc::setsockopt(
-                    self.fd,
-                    libc::SOL_NETLINK,
-                    libc::NETLINK_DROP_MEMBERSHIP,
-                    &group as *const _ as *const libc::c_void,
-                    size_of::<u32>() as libc::socklen_t,
-                )
-            } {
-                0 => (),
-                _ => return Err(io::Error::last_os_error()),
-            }
+            self.set_option(libc::SOL_NETLINK, libc::NETLINK_DROP_MEMBERSHIP, &group)?;
         }
         Ok(())
     }
@@ -223,6 +234,22 @@ impl NlSocket {
         }
     }
 
+    /// Send the concatenation of `bufs` as a single datagram using `sendmsg`.
+    pub fn send_vectored(
+        &self,
+        bufs: &[IoSlice<'_>],
+        flags: Msg,
+    ) -> Result<libc::size_t, io::Error> {
+        let mut msg = unsafe { zeroed::<libc::msghdr>() };
+        // IoSlice is guaranteed to be ABI compatible with iovec.
+        msg.msg_iov = bufs.as_ptr() as *mut libc::iovec;
+        msg.msg_iovlen = bufs.len() as _;
+        match unsafe { libc::sendmsg(self.fd, &msg, flags.bits() as i32) } {
+            i if i >= 0 => Ok(i as libc::size_t),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
     /// Receive message encoded as byte slice from the netlink socket.
     pub fn recv<B>(&self, mut buf: B, flags: Msg) -> Result<(libc::size_t, Groups), io::Error>
     where
@@ -248,6 +275,67 @@ impl NlSocket {
         }
     }
 
+    /// Receive message encoded as byte slice from the netlink socket along with
+    /// the [`RecvMetadata`] of the datagram.
+    ///
+    /// Unlike [`NlSocket::recv`], this uses `recvmsg` and reads the control data
+    /// enabled with [`NlSocket::enable_pktinfo`] and
+    /// [`NlSocket::enable_listen_all_nsid`].
+    pub fn recv_msg<B>(
+        &self,
+        mut buf: B,
+        flags: Msg,
+    ) -> Result<(libc::size_t, RecvMetadata), io::Error>
+    where
+        B: AsMut<[u8]>,
+    {
+        let buf = buf.as_mut();
+        let mut addr = unsafe { zeroed::<sockaddr_nl>() };
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        };
+        // u64 elements keep the buffer aligned for cmsghdr.
+        let mut cmsg_buf = [0u64; CMSG_BUFFER_LEN / size_of::<u64>()];
+        let mut msg = unsafe { zeroed::<libc::msghdr>() };
+        msg.msg_name = &mut addr as *mut _ as *mut c_void;
+        msg.msg_namelen = size_of::<sockaddr_nl>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = CMSG_BUFFER_LEN as _;
+
+        let read = match unsafe { libc::recvmsg(self.fd, &mut msg, flags.bits() as i32) } {
+            i if i >= 0 => i as libc::size_t,
+            _ => return Err(io::Error::last_os_error()),
+        };
+
+        let mut group = None;
+        let mut nsid = None;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while let Some(hdr) = unsafe { cmsg.as_ref() } {
+            if hdr.cmsg_level == libc::SOL_NETLINK {
+                let data = unsafe { libc::CMSG_DATA(cmsg) };
+                match hdr.cmsg_type {
+                    libc::NETLINK_PKTINFO => {
+                        group = Some(unsafe { (data as *const u32).read_unaligned() })
+                    }
+                    libc::NETLINK_LISTEN_ALL_NSID => {
+                        nsid = Some(unsafe { (data as *const i32).read_unaligned() })
+                    }
+                    _ => (),
+                }
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        let groups = match group {
+            Some(group) if group != 0 => Groups::new_groups(&[group]),
+            _ => Groups::new_bitmask(addr.nl_groups),
+        };
+        Ok((read, RecvMetadata::new(addr.nl_pid, groups, nsid)))
+    }
+
     /// Get the PID for this socket.
     pub fn pid(&self) -> Result<u32, io::Error> {
         let mut sock_len = size_of::<libc::sockaddr_nl>() as u32;
@@ -264,39 +352,40 @@ impl NlSocket {
         }
     }
 
+    /// Get the netlink protocol family of this socket.
+    pub fn protocol(&self) -> Result<NlFamily, io::Error> {
+        self.get_option::<c_int>(libc::SOL_SOCKET, libc::SO_PROTOCOL)
+            .map(NlFamily::from)
+    }
+
     /// If [`true`] is passed in, enable extended ACKs for this socket. If [`false`]
     /// is passed in, disable extended ACKs for this socket.
     pub fn enable_ext_ack(&self, enable: bool) -> Result<(), io::Error> {
-        match unsafe {
-            libc::setsockopt(
-                self.fd,
-                libc::SOL_NETLINK,
-                libc::NETLINK_EXT_ACK,
-                &c_int::from(enable) as *const _ as *const libc::c_void,
-                size_of::<i32>() as libc::socklen_t,
-            )
-        } {
-            0 => Ok(()),
-            _ => Err(io::Error::last_os_error()),
-        }
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_EXT_ACK,
+            &c_int::from(enable),
+        )
     }
 
     /// Return [`true`] if an extended ACK is enabled for this socket.
     pub fn get_ext_ack_enabled(&self) -> Result<bool, io::Error> {
-        let mut sock_len = size_of::<libc::c_int>() as libc::socklen_t;
-        let mut sock_val: MaybeUninit<libc::c_int> = MaybeUninit::uninit();
-        match unsafe {
-            libc::getsockopt(
-                self.fd,
-                libc::SOL_NETLINK,
-                libc::NETLINK_EXT_ACK,
-                &mut sock_val as *mut _ as *mut libc::c_void,
-                &mut sock_len as *mut _ as *mut libc::socklen_t,
-            )
-        } {
-            0 => Ok(unsafe { sock_val.assume_init() } != 0),
-            _ => Err(io::Error::last_os_error()),
-        }
+        self.get_bool_option(libc::NETLINK_EXT_ACK)
+    }
+
+    /// If [`true`] is passed in, suppress `ENOBUFS` errors when the socket receive
+    /// buffer overruns. If [`false`] is passed in, report overruns as errors.
+    pub fn enable_no_enobufs(&self, enable: bool) -> Result<(), io::Error> {
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_NO_ENOBUFS,
+            &c_int::from(enable),
+        )
+    }
+
+    /// Return [`true`] if `ENOBUFS` errors are suppressed for this socket.
+    pub fn get_no_enobufs_enabled(&self) -> Result<bool, io::Error> {
+        self.get_bool_option(libc::NETLINK_NO_ENOBUFS)
     }
 
     /// If [`true`] is passed in, enable strict checking for this socket. If [`false`]
@@ -304,13 +393,176 @@ impl NlSocket {
     /// Only supported by `NlFamily::Route` sockets.
     /// Requires Linux >= 4.20.
     pub fn enable_strict_checking(&self, enable: bool) -> Result<(), io::Error> {
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_GET_STRICT_CHK,
+            &c_int::from(enable),
+        )
+    }
+
+    /// Return [`true`] if strict checking is enabled for this socket.
+    /// Only supported by `NlFamily::Route` sockets.
+    /// Requires Linux >= 4.20.
+    pub fn get_strict_checking_enabled(&self) -> Result<bool, io::Error> {
+        self.get_bool_option(libc::NETLINK_GET_STRICT_CHK)
+    }
+
+    /// Set the size of the send buffer for the socket.
+    ///
+    /// The kernel will double the value provided to account for bookkeeping overhead.
+    /// The doubled value is capped by the value in `/proc/sys/net/core/wmem_max`.
+    ///
+    /// See `socket(7)` documentation for `SO_SNDBUF` for more information.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), io::Error> {
+        self.set_option(libc::SOL_SOCKET, libc::SO_SNDBUF, &buffer_size(size)?)
+    }
+
+    /// Return the size of the send buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_send_buffer_size(&self) -> Result<usize, io::Error> {
+        self.get_option::<c_int>(libc::SOL_SOCKET, libc::SO_SNDBUF)
+            .map(|size| size as usize)
+    }
+
+    /// Return the size of the receive buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_recv_buffer_size(&self) -> Result<usize, io::Error> {
+        self.get_option::<c_int>(libc::SOL_SOCKET, libc::SO_RCVBUF)
+            .map(|size| size as usize)
+    }
+
+    /// Set the size of the receive buffer for the socket, ignoring the limit in
+    /// `/proc/sys/net/core/rmem_max`.
+    ///
+    /// Requires `CAP_NET_ADMIN`. See `socket(7)` documentation for `SO_RCVBUFFORCE`
+    /// for more information.
+    pub fn force_recv_buffer_size(&self, size: usize) -> Result<(), io::Error> {
+        self.set_option(libc::SOL_SOCKET, libc::SO_RCVBUFFORCE, &buffer_size(size)?)
+    }
+
+    /// Set the timeout for blocking receive operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A receive operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) -> Result<(), io::Error> {
+        self.set_timeout(libc::SO_RCVTIMEO, timeout)
+    }
+
+    /// Return the timeout for blocking receive operations.
+    pub fn get_recv_timeout(&self) -> Result<Option<Duration>, io::Error> {
+        self.get_timeout(libc::SO_RCVTIMEO)
+    }
+
+    /// Set the timeout for blocking send operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A send operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) -> Result<(), io::Error> {
+        self.set_timeout(libc::SO_SNDTIMEO, timeout)
+    }
+
+    /// Return the timeout for blocking send operations.
+    pub fn get_send_timeout(&self) -> Result<Option<Duration>, io::Error> {
+        self.get_timeout(libc::SO_SNDTIMEO)
+    }
+
+    /// If [`true`] is passed in, report errors delivering multicast messages sent
+    /// from this socket. If [`false`] is passed in, only report errors if no
+    /// listener received the message.
+    pub fn enable_broadcast_error(&self, enable: bool) -> Result<(), io::Error> {
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_BROADCAST_ERROR,
+            &c_int::from(enable),
+        )
+    }
+
+    /// Return [`true`] if multicast delivery errors are reported for this socket.
+    pub fn get_broadcast_error_enabled(&self) -> Result<bool, io::Error> {
+        self.get_bool_option(libc::NETLINK_BROADCAST_ERROR)
+    }
+
+    /// If [`true`] is passed in, omit the payload of the original request from
+    /// error messages. If [`false`] is passed in, include the original request.
+    pub fn enable_cap_ack(&self, enable: bool) -> Result<(), io::Error> {
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_CAP_ACK,
+            &c_int::from(enable),
+        )
+    }
+
+    /// Return [`true`] if the original request is omitted from error messages.
+    pub fn get_cap_ack_enabled(&self) -> Result<bool, io::Error> {
+        self.get_bool_option(libc::NETLINK_CAP_ACK)
+    }
+
+    /// If [`true`] is passed in, ask the kernel to attach the destination multicast
+    /// group to each received message as `NETLINK_PKTINFO` control data. If
+    /// [`false`] is passed in, disable this.
+    pub fn enable_pktinfo(&self, enable: bool) -> Result<(), io::Error> {
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_PKTINFO,
+            &c_int::from(enable),
+        )
+    }
+
+    /// Return [`true`] if `NETLINK_PKTINFO` control data is enabled for this
+    /// socket.
+    pub fn get_pktinfo_enabled(&self) -> Result<bool, io::Error> {
+        self.get_bool_option(libc::NETLINK_PKTINFO)
+    }
+
+    /// If [`true`] is passed in, receive multicast messages from all network
+    /// namespaces that have an ID assigned in the namespace of the socket. If
+    /// [`false`] is passed in, only receive messages from the namespace of the
+    /// socket.
+    ///
+    /// Requires `CAP_NET_BROADCAST`.
+    pub fn enable_listen_all_nsid(&self, enable: bool) -> Result<(), io::Error> {
+        self.set_option(
+            libc::SOL_NETLINK,
+            libc::NETLINK_LISTEN_ALL_NSID,
+            &c_int::from(enable),
+        )
+    }
+
+    /// Return [`true`] if multicast messages from all network namespaces are
+    /// received by this socket.
+    pub fn get_listen_all_nsid_enabled(&self) -> Result<bool, io::Error> {
+        self.get_bool_option(libc::NETLINK_LISTEN_ALL_NSID)
+    }
+
+    /// Attach the classic BPF program compiled from `filter` to the socket,
+    /// replacing any filter that is already attached.
+    ///
+    /// The filter also applies to replies to requests sent from this socket.
+    /// See [`SocketFilter::accept_pid`] to accept them.
+    pub fn attach_filter(&self, filter: &SocketFilter) -> Result<(), io::Error> {
+        let mut program = filter.compile()?;
+        let fprog = libc::sock_fprog {
+            len: program.len() as libc::c_ushort,
+            filter: program.as_mut_ptr(),
+        };
+        self.set_option(libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &fprog)
+    }
+
+    /// Remove the filter attached with [`NlSocket::attach_filter`].
+    pub fn detach_filter(&self) -> Result<(), io::Error> {
+        self.set_option(libc::SOL_SOCKET, libc::SO_DETACH_FILTER, &c_int::from(0))
+    }
+
+    fn set_option<T>(&self, level: c_int, name: c_int, value: &T) -> Result<(), io::Error> {
         match unsafe {
             libc::setsockopt(
                 self.fd,
-                libc::SOL_NETLINK,
-                libc::NETLINK_GET_STRICT_CHK,
-                &libc::c_int::from(enable) as *const _ as *const libc::c_void,
-                size_of::<libc::c_int>() as libc::socklen_t,
+                level,
+                name,
+                value as *const _ as *const c_void,
+                size_of::<T>() as libc::socklen_t,
             )
         } {
             0 => Ok(()),
@@ -318,25 +570,72 @@ impl NlSocket {
         }
     }
 
-    /// Return [`true`] if strict checking is enabled for this socket.
-    /// Only supported by `NlFamily::Route` sockets.
-    /// Requires Linux >= 4.20.
-    pub fn get_strict_checking_enabled(&self) -> Result<bool, io::Error> {
-        let mut sock_len = size_of::<libc::c_int>() as libc::socklen_t;
-        let mut sock_val: MaybeUninit<libc::c_int> = MaybeUninit::uninit();
+    fn get_option<T>(&self, level: c_int, name: c_int) -> Result<T, io::Error> {
+        let mut len = size_of::<T>() as libc::socklen_t;
+        let mut value: MaybeUninit<T> = MaybeUninit::zeroed();
         match unsafe {
             libc::getsockopt(
                 self.fd,
-                libc::SOL_NETLINK,
-                libc::NETLINK_GET_STRICT_CHK,
-                &mut sock_val as *mut _ as *mut libc::c_void,
-                &mut sock_len as *mut _ as *mut libc::socklen_t,
+                level,
+                name,
+                value.as_mut_ptr() as *mut c_void,
+                &mut len as *mut _,
             )
         } {
-            0 => Ok(unsafe { sock_val.assume_init() } != 0),
+            0 => Ok(unsafe { value.assume_init() }),
             _ => Err(io::Error::last_os_error()),
         }
     }
+
+    fn get_bool_option(&self, name: c_int) -> Result<bool, io::Error> {
+        self.get_option::<c_int>(libc::SOL_NETLINK, name)
+            .map(|value| value != 0)
+    }
+
+    fn set_timeout(&self, name: c_int, timeout: Option<Duration>) -> Result<(), io::Error> {
+        let timeval = match timeout {
+            Some(t) if t.is_zero() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Cannot set a zero timeout",
+                ))
+            }
+            Some(t) => {
+                let mut timeval = libc::timeval {
+                    tv_sec: t.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+                    tv_usec: t.subsec_micros() as libc::suseconds_t,
+                };
+                // A zero timeval disables the timeout so round up instead.
+                if timeval.tv_sec == 0 && timeval.tv_usec == 0 {
+                    timeval.tv_usec = 1;
+                }
+                timeval
+            }
+            None => libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+        };
+        self.set_option(libc::SOL_SOCKET, name, &timeval)
+    }
+
+    fn get_timeout(&self, name: c_int) -> Result<Option<Duration>, io::Error> {
+        let timeval = self.get_option::<libc::timeval>(libc::SOL_SOCKET, name)?;
+        Ok(match (timeval.tv_sec, timeval.tv_usec) {
+            (0, 0) => None,
+            (sec, usec) => Some(Duration::new(sec as u64, usec as u32 * 1000)),
+        })
+    }
+}
+
+/// Convert a buffer size to the `c_int` expected by `SO_RCVBUF` and `SO_SNDBUF`.
+fn buffer_size(size: usize) -> Result<c_int, io::Error> {
+    c_int::try_from(size).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Buffer size {size} does not fit in a C int"),
+        )
+    })
 }
 
 #[cfg(feature = "sync")]
@@ -346,10 +645,13 @@ impl From<synchronous::NlSocketHandle> for NlSocket {
     }
 }
 
-#[cfg(feature = "async")]
-impl From<asynchronous::NlSocketHandle> for NlSocket {
-    fn from(s: asynchronous::NlSocketHandle) -> Self {
-        s.socket.into_inner()
+/// Fails if the runtime cannot deregister the socket from its reactor.
+#[cfg(feature = "async-core")]
+impl<R: Runtime> TryFrom<asynchronous::NlSocketHandle<R>> for NlSocket {
+    type Error = SocketError;
+
+    fn try_from(s: asynchronous::NlSocketHandle<R>) -> Result<Self, Self::Error> {
+        s.into_inner()
     }
 }
 
@@ -359,6 +661,12 @@ impl AsRawFd for NlSocket {
     }
 }
 
+impl AsFd for NlSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
 impl IntoRawFd for NlSocket {
     fn into_raw_fd(self) -> RawFd {
         let fd = self.fd;
@@ -407,6 +715,18 @@ mod test {
         assert!(s.get_ext_ack_enabled().unwrap());
     }
 
+    #[test]
+    fn real_no_enobufs() {
+        setup();
+
+        let s = NlSocket::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        assert!(!s.get_no_enobufs_enabled().unwrap());
+        s.enable_no_enobufs(true).unwrap();
+        assert!(s.get_no_enobufs_enabled().unwrap());
+        s.enable_no_enobufs(false).unwrap();
+        assert!(!s.get_no_enobufs_enabled().unwrap());
+    }
+
     #[test]
     fn real_strict_checking() {
         setup();
@@ -416,4 +736,234 @@ mod test {
         s.enable_strict_checking(true).unwrap();
         assert!(s.get_strict_checking_enabled().unwrap());
     }
+
+    #[test]
+    fn real_test_recv_msg() {
+        use std::io::Cursor;
+
+        use crate::{
+            consts::{
+                genl::{CtrlAttr, CtrlCmd},
+                nl::{GenlId, NlmF},
+            },
+            genl::{GenlmsghdrBuilder, NoUserHeader},
+            nl::{NlPayload, NlmsghdrBuilder},
+            Size, ToBytes,
+        };
+
+        setup();
+
+        let s = NlSocket::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        s.enable_pktinfo(true).unwrap();
+        let msg = NlmsghdrBuilder::default()
+            .nl_type(GenlId::Ctrl)
+            .nl_flags(NlmF::REQUEST | NlmF::DUMP)
+            .nl_payload(NlPayload::Payload(
+                GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                    .cmd(CtrlCmd::Getfamily)
+                    .version(2)
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        let mut buffer = Cursor::new(vec![0; msg.padded_size()]);
+        msg.to_bytes(&mut buffer).unwrap();
+        s.send(buffer.get_ref(), Msg::empty()).unwrap();
+
+        let (size, _) = s.recv_msg([0u8; 0], Msg::PEEK | Msg::TRUNC).unwrap();
+        assert!(size > 0);
+        let mut buf = vec![0; 32768];
+        let (read, metadata) = s.recv_msg(&mut buf[..], Msg::TRUNC).unwrap();
+        assert_eq!(read, size);
+        assert_eq!(metadata, RecvMetadata::new(0, Groups::empty(), None));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn real_test_send_with_payload() {
+        use std::io::Cursor;
+
+        use crate::{
+            consts::{
+                genl::{CtrlAttr, CtrlCmd},
+                nl::{GenlId, NlmF},
+            },
+            genl::{AttrTypeBuilder, Genlmsghdr, GenlmsghdrBuilder, NlattrBuilder, NoUserHeader},
+            nl::{NlPayload, NlmsghdrBuilder},
+            ToBytes,
+        };
+
+        setup();
+
+        let s =
+            synchronous::NlSocketHandle::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        // A pre-serialized attribute is appended to a request without attributes.
+        let attr = NlattrBuilder::default()
+            .nla_type(
+                AttrTypeBuilder::default()
+                    .nla_type(CtrlAttr::FamilyName)
+                    .build()
+                    .unwrap(),
+            )
+            .nla_payload("nlctrl")
+            .build()
+            .unwrap();
+        let mut payload = Cursor::new(Vec::new());
+        attr.to_bytes(&mut payload).unwrap();
+        let msg = NlmsghdrBuilder::default()
+            .nl_type(GenlId::Ctrl)
+            .nl_flags(NlmF::REQUEST)
+            .nl_payload(NlPayload::Payload(
+                GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                    .cmd(CtrlCmd::Getfamily)
+                    .version(2)
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        s.send_with_payload(&msg, payload.get_ref()).unwrap();
+
+        let (msgs, _) = s
+            .recv_all::<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>()
+            .unwrap();
+        let msg = msgs.iter().next().unwrap();
+        assert_eq!(
+            msg.get_payload()
+                .unwrap()
+                .attrs()
+                .get_attr_handle()
+                .get_attr_payload_as::<u16>(CtrlAttr::FamilyId)
+                .unwrap(),
+            u16::from(GenlId::Ctrl)
+        );
+    }
+
+    #[test]
+    fn real_test_filter() {
+        use std::io::Cursor;
+
+        use crate::{
+            consts::{
+                genl::{CtrlAttr, CtrlCmd},
+                nl::{GenlId, NlmF, Nlmsg},
+            },
+            genl::{AttrTypeBuilder, GenlmsghdrBuilder, NlattrBuilder, NoUserHeader},
+            nl::{NlPayload, NlmsghdrBuilder},
+            types::GenlBuffer,
+            ToBytes,
+        };
+
+        setup();
+
+        let s = NlSocket::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        let attrs = vec![NlattrBuilder::default()
+            .nla_type(
+                AttrTypeBuilder::default()
+                    .nla_type(CtrlAttr::FamilyName)
+                    .build()
+                    .unwrap(),
+            )
+            .nla_payload("nlctrl")
+            .build()
+            .unwrap()]
+        .into_iter()
+        .collect::<GenlBuffer<_, _>>();
+        let msg = NlmsghdrBuilder::default()
+            .nl_type(GenlId::Ctrl)
+            .nl_flags(NlmF::REQUEST | NlmF::ACK)
+            .nl_payload(NlPayload::Payload(
+                GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                    .cmd(CtrlCmd::Getfamily)
+                    .version(2)
+                    .attrs(attrs)
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        let mut request = Cursor::new(Vec::new());
+        msg.to_bytes(&mut request).unwrap();
+        let mut buf = vec![0; 32768];
+        let mut first_type = |s: &NlSocket| {
+            s.send(request.get_ref(), Msg::empty()).unwrap();
+            s.recv(&mut buf[..], Msg::empty()).unwrap();
+            u16::from_ne_bytes([buf[4], buf[5]])
+        };
+
+        // The reply is a NEWFAMILY message so only the ACK passes the filter.
+        s.attach_filter(&SocketFilter::new().genl_cmd(CtrlCmd::Delfamily))
+            .unwrap();
+        assert_eq!(first_type(&s), u16::from(Nlmsg::Error));
+        s.detach_filter().unwrap();
+        assert_eq!(first_type(&s), u16::from(GenlId::Ctrl));
+    }
+
+    #[test]
+    fn real_netlink_options() {
+        setup();
+
+        let s = NlSocket::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        for (enable, get) in [
+            (
+                NlSocket::enable_broadcast_error as fn(&NlSocket, bool) -> _,
+                NlSocket::get_broadcast_error_enabled as fn(&NlSocket) -> _,
+            ),
+            (NlSocket::enable_cap_ack, NlSocket::get_cap_ack_enabled),
+            (NlSocket::enable_pktinfo, NlSocket::get_pktinfo_enabled),
+        ] {
+            assert!(!get(&s).unwrap());
+            enable(&s, true).unwrap();
+            assert!(get(&s).unwrap());
+            enable(&s, false).unwrap();
+            assert!(!get(&s).unwrap());
+        }
+
+        assert!(!s.get_listen_all_nsid_enabled().unwrap());
+        // NETLINK_LISTEN_ALL_NSID requires CAP_NET_BROADCAST.
+        match s.enable_listen_all_nsid(true) {
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => return,
+            res => res.unwrap(),
+        }
+        assert!(s.get_listen_all_nsid_enabled().unwrap());
+        s.enable_listen_all_nsid(false).unwrap();
+        assert!(!s.get_listen_all_nsid_enabled().unwrap());
+    }
+
+    #[test]
+    fn real_socket_options() {
+        setup();
+
+        let s = NlSocket::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        s.set_send_buffer_size(8192).unwrap();
+        assert_eq!(s.get_send_buffer_size().unwrap(), 16384);
+        s.set_recv_buffer_size(8192).unwrap();
+        assert_eq!(s.get_recv_buffer_size().unwrap(), 16384);
+        assert_eq!(
+            s.set_recv_buffer_size(usize::MAX).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        assert_eq!(s.get_recv_timeout().unwrap(), None);
+        s.set_recv_timeout(Some(Duration::from_millis(1500)))
+            .unwrap();
+        assert_eq!(
+            s.get_recv_timeout().unwrap(),
+            Some(Duration::from_millis(1500))
+        );
+        s.set_recv_timeout(None).unwrap();
+        assert_eq!(s.get_recv_timeout().unwrap(), None);
+        assert!(s.set_send_timeout(Some(Duration::ZERO)).is_err());
+        s.set_send_timeout(Some(Duration::from_nanos(1))).unwrap();
+        // The kernel rounds timeouts up to a whole number of jiffies.
+        assert!(s.get_send_timeout().unwrap().is_some());
+
+        let mut buf = [0; 64];
+        s.set_recv_timeout(Some(Duration::from_millis(10))).unwrap();
+        assert!(matches!(
+            s.recv(&mut buf[..], Msg::empty()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock
+        ));
+    }
 }
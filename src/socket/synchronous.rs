@@ -1,30 +1,57 @@
+#[cfg(any(feature = "mio", feature = "calloop"))]
+use std::io;
+#[cfg(feature = "calloop")]
+use std::marker::PhantomData;
 use std::{
     fmt::Debug,
-    io::Cursor,
-    os::unix::io::{AsRawFd, IntoRawFd, RawFd},
+    io::{Cursor, IoSlice},
+    mem::size_of,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use log::trace;
+#[cfg(feature = "calloop")]
+use calloop::{
+    generic::{Generic, NoIoDrop},
+    EventSource, Interest, Mode, Poll, PostAction, Readiness, Token, TokenFactory,
+};
+use log::{trace, warn};
+use parking_lot::Mutex;
 
 use crate::{
-    consts::{nl::*, socket::*},
+    consts::{alignto, nl::*, socket::*},
     err::SocketError,
     iter::NlBufferIter,
     nl::Nlmsghdr,
-    socket::shared::NlSocket,
+    socket::{
+        capture::{Capture, Direction},
+        filter::SocketFilter,
+        netns::NetNs,
+        shared::{NlSocket, RecvMetadata},
+        Transport,
+    },
     types::NlBuffer,
     utils::{
         synchronous::{BufferPool, BufferPoolGuard},
-        Groups, NetlinkBitArray,
+        BufferGrowth, BufferPoolConfig, Groups, NetlinkBitArray, SendBuffer,
     },
     FromBytesWithInput, Size, ToBytes,
 };
 
 /// Higher level handle for socket operations.
-pub struct NlSocketHandle {
-    pub(super) socket: NlSocket,
+///
+/// The handle is generic over the [`Transport`] used to exchange messages,
+/// which defaults to a netlink socket.
+pub struct NlSocketHandle<S = NlSocket> {
+    pub(super) socket: S,
     pid: u32,
     pool: BufferPool,
+    adaptive: AtomicBool,
+    capture: Mutex<Option<(Arc<dyn Capture>, NlFamily)>>,
 }
 
 impl NlSocketHandle {
@@ -32,14 +59,44 @@ impl NlSocketHandle {
     pub fn connect(proto: NlFamily, pid: Option<u32>, groups: Groups) -> Result<Self, SocketError> {
         let socket = NlSocket::connect(proto, pid, groups)?;
         socket.block()?;
-        let pid = socket.pid()?;
+        Self::with_transport(socket)
+    }
+
+    /// Equivalent of [`NlSocketHandle::connect`] creating the socket inside the
+    /// network namespace `netns`.
+    pub fn connect_in_netns(
+        netns: &NetNs,
+        proto: NlFamily,
+        pid: Option<u32>,
+        groups: Groups,
+    ) -> Result<Self, SocketError> {
+        let socket = NlSocket::connect_in_netns(netns, proto, pid, groups)?;
+        socket.block()?;
+        Self::with_transport(socket)
+    }
+}
+
+impl<S> NlSocketHandle<S>
+where
+    S: Transport,
+{
+    /// Create a handle that exchanges messages using `transport`.
+    pub fn with_transport(transport: S) -> Result<Self, SocketError> {
+        let pid = transport.pid()?;
         Ok(NlSocketHandle {
-            socket,
+            socket: transport,
             pid,
             pool: BufferPool::default(),
+            adaptive: AtomicBool::new(false),
+            capture: Mutex::new(None),
         })
     }
 
+    /// Get a reference to the underlying transport.
+    pub fn transport(&self) -> &S {
+        &self.socket
+    }
+
     /// Join multicast groups for a socket.
     pub fn add_mcast_membership(&self, groups: Groups) -> Result<(), SocketError> {
         self.socket
@@ -66,6 +123,67 @@ impl NlSocketHandle {
         self.pid
     }
 
+    /// Pass every datagram sent or received on this socket to `capture`.
+    ///
+    /// Passing [`None`] stops an active capture.
+    pub fn set_capture(&self, capture: Option<Arc<dyn Capture>>) -> Result<(), SocketError> {
+        let capture = match capture {
+            Some(c) => Some((c, self.socket.protocol()?)),
+            None => None,
+        };
+        *self.capture.lock() = capture;
+        Ok(())
+    }
+
+    /// Replace the buffers used for receive operations with a pool described by
+    /// `config`.
+    ///
+    /// [`BufferGrowth::OnDemand`] enables adaptive buffers as with
+    /// [`NlSocketHandle::enable_adaptive_buffers`].
+    pub fn with_buffer_pool(mut self, config: BufferPoolConfig) -> Self {
+        self.pool = BufferPool::new(&config);
+        self.adaptive = AtomicBool::new(*config.growth() == BufferGrowth::OnDemand);
+        self
+    }
+
+    /// If [`true`] is passed in, peek at the size of each datagram before
+    /// receiving it and grow the receive buffer if the datagram does not fit.
+    ///
+    /// Without this, datagrams larger than the buffer return
+    /// [`SocketError::Truncated`] from [`NlSocketHandle::recv`] and
+    /// [`NlSocketHandle::recv_all`]. Grown buffers keep their allocation when
+    /// returned to the pool.
+    pub fn enable_adaptive_buffers(&self, enable: bool) {
+        self.adaptive.store(enable, Ordering::Relaxed);
+    }
+
+    fn recv_buffer(&self) -> Result<(BufferPoolGuard<'_>, RecvMetadata), SocketError> {
+        let mut buffer = self.pool.acquire();
+        if self.adaptive.load(Ordering::Relaxed) {
+            let (size, _) = self.socket.recv(&mut [], Msg::PEEK | Msg::TRUNC)?;
+            if size > buffer.len() {
+                trace!("Growing receive buffer to {size} bytes");
+                buffer.resize(size, 0);
+            }
+        }
+        let capacity = buffer.len();
+        let (size, metadata) = self.socket.recv(buffer.as_mut(), Msg::TRUNC)?;
+        if size > capacity {
+            return Err(SocketError::Truncated { size, capacity });
+        }
+        buffer.reduce_size(size);
+        Ok((buffer, metadata))
+    }
+
+    fn capture_datagram(&self, direction: Direction, groups: &Groups, datagram: &[u8]) {
+        let capture = self.capture.lock().clone();
+        if let Some((capture, protocol)) = capture {
+            if let Err(e) = capture.capture(direction, protocol, groups, datagram) {
+                warn!("Failed to capture datagram: {e}");
+            }
+        }
+    }
+
     /// Convenience function to send an [`Nlmsghdr`] struct
     pub fn send<T, P>(&self, msg: &Nlmsghdr<T, P>) -> Result<(), SocketError>
     where
@@ -73,11 +191,66 @@ impl NlSocketHandle {
         P: Size + ToBytes + Debug,
     {
         trace!("Message sent:\n{msg:?}");
+        self.send_serialized(msg)
+    }
+
+    /// Send all messages in an [`NlBuffer`] in a single system call.
+    pub fn send_batch<T, P>(&self, msgs: &NlBuffer<T, P>) -> Result<(), SocketError>
+    where
+        T: NlType + Debug,
+        P: Size + ToBytes + Debug,
+    {
+        trace!("Messages sent:\n{msgs:?}");
+        self.send_serialized(msgs)
+    }
+
+    /// Send `msg` followed by `payload` in a single datagram without copying
+    /// `payload`.
+    ///
+    /// `payload` is appended after the padded message using scatter-gather IO
+    /// and the length in the header of `msg` is extended to include it. Length
+    /// fields inside the payload of `msg` that cover `payload`, such as the
+    /// length of a trailing attribute header, must already account for it.
+    pub fn send_with_payload<T, P>(
+        &self,
+        msg: &Nlmsghdr<T, P>,
+        payload: &[u8],
+    ) -> Result<(), SocketError>
+    where
+        T: NlType + Debug,
+        P: Size + ToBytes + Debug,
+    {
+        trace!("Message sent with {} byte payload:\n{msg:?}", payload.len());
+
+        let mut buffer = SendBuffer::take();
+        msg.to_bytes(&mut Cursor::new(&mut *buffer))?;
+        let nl_len = u32::try_from(buffer.len() + payload.len())
+            .map_err(|_| SocketError::new("Message is too large to send"))?;
+        buffer[..size_of::<u32>()].copy_from_slice(&nl_len.to_ne_bytes());
+        let padding = [0; libc::NLA_ALIGNTO as usize];
+        let parts: [&[u8]; 3] = [
+            &buffer,
+            payload,
+            &padding[..alignto(payload.len()) - payload.len()],
+        ];
+        self.socket
+            .send_vectored(&parts.map(IoSlice::new), Msg::empty())?;
+        if self.capture.lock().is_some() {
+            self.capture_datagram(Direction::Outgoing, &Groups::empty(), &parts.concat());
+        }
+
+        Ok(())
+    }
 
-        let mut buffer = Cursor::new(vec![0; msg.padded_size()]);
-        msg.to_bytes(&mut buffer)?;
-        trace!("Buffer sent: {:?}", buffer.get_ref());
-        self.socket.send(buffer.get_ref(), Msg::empty())?;
+    fn send_serialized<M>(&self, msg: &M) -> Result<(), SocketError>
+    where
+        M: ToBytes,
+    {
+        let mut buffer = SendBuffer::take();
+        msg.to_bytes(&mut Cursor::new(&mut *buffer))?;
+        trace!("Buffer sent: {:?}", buffer.as_slice());
+        self.socket.send(&buffer, Msg::empty())?;
+        self.capture_datagram(Direction::Outgoing, &Groups::empty(), &buffer);
 
         Ok(())
     }
@@ -97,11 +270,14 @@ impl NlSocketHandle {
         T: NlType + Debug,
         P: Size + FromBytesWithInput<Input = usize> + Debug,
     {
-        let mut buffer = self.pool.acquire();
-        let (mem_read, groups) = self.socket.recv(&mut buffer, Msg::empty())?;
-        buffer.reduce_size(mem_read);
+        let (buffer, metadata) = self.recv_buffer()?;
         trace!("Buffer received: {:?}", buffer.as_ref());
-        Ok((NlBufferIter::new(Cursor::new(buffer)), groups))
+        self.capture_datagram(Direction::Incoming, metadata.groups(), buffer.as_ref());
+        let groups = metadata.groups().clone();
+        Ok((
+            NlBufferIter::with_metadata(Cursor::new(buffer), metadata),
+            groups,
+        ))
     }
 
     /// Parse all [`Nlmsghdr`] structs sent in
@@ -117,12 +293,13 @@ impl NlSocketHandle {
         T: NlType + Debug,
         P: Size + FromBytesWithInput<Input = usize> + Debug,
     {
-        let mut buffer = self.pool.acquire();
-        let (mem_read, groups) = self.socket.recv(&mut buffer, Msg::empty())?;
+        let (buffer, metadata) = self.recv_buffer()?;
+        let groups = metadata.groups().clone();
+        let mem_read = buffer.len();
         if mem_read == 0 {
             return Ok((NlBuffer::new(), Groups::empty()));
         }
-        buffer.reduce_size(mem_read);
+        self.capture_datagram(Direction::Incoming, &groups, buffer.as_ref());
 
         let vec = NlBuffer::from_bytes_with_input(&mut Cursor::new(buffer), mem_read)?;
 
@@ -131,6 +308,12 @@ impl NlSocketHandle {
         Ok((vec, groups))
     }
 
+    pub(in super::super) fn set_nonblock(&self) -> Result<(), SocketError> {
+        self.socket.set_nonblocking(true).map_err(SocketError::from)
+    }
+}
+
+impl NlSocketHandle {
     /// Set the size of the receive buffer for the socket.
     ///
     /// This can be useful when communicating with a service that sends a high volume of
@@ -163,6 +346,21 @@ impl NlSocketHandle {
         self.socket.get_ext_ack_enabled().map_err(SocketError::from)
     }
 
+    /// If [`true`] is passed in, suppress `ENOBUFS` errors when the socket receive
+    /// buffer overruns. If [`false`] is passed in, report overruns as errors.
+    pub fn enable_no_enobufs(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .enable_no_enobufs(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if `ENOBUFS` errors are suppressed for this socket.
+    pub fn get_no_enobufs_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_no_enobufs_enabled()
+            .map_err(SocketError::from)
+    }
+
     /// If [`true`] is passed in, enable strict checking for this socket. If [`false`]
     /// is passed in, disable strict checking for for this socket.
     /// Only supported by `NlFamily::Route` sockets.
@@ -182,19 +380,383 @@ impl NlSocketHandle {
             .map_err(SocketError::from)
     }
 
-    pub(in super::super) fn set_nonblock(&self) -> Result<(), SocketError> {
-        self.socket.nonblock().map_err(SocketError::from)
+    /// Set the size of the send buffer for the socket.
+    ///
+    /// The kernel will double the value provided to account for bookkeeping overhead.
+    /// The doubled value is capped by the value in `/proc/sys/net/core/wmem_max`.
+    ///
+    /// See `socket(7)` documentation for `SO_SNDBUF` for more information.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), SocketError> {
+        self.socket
+            .set_send_buffer_size(size)
+            .map_err(SocketError::from)
+    }
+
+    /// Return the size of the send buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_send_buffer_size(&self) -> Result<usize, SocketError> {
+        self.socket
+            .get_send_buffer_size()
+            .map_err(SocketError::from)
+    }
+
+    /// Return the size of the receive buffer for the socket, including the kernel
+    /// bookkeeping overhead.
+    pub fn get_recv_buffer_size(&self) -> Result<usize, SocketError> {
+        self.socket
+            .get_recv_buffer_size()
+            .map_err(SocketError::from)
+    }
+
+    /// Set the size of the receive buffer for the socket, ignoring the limit in
+    /// `/proc/sys/net/core/rmem_max`.
+    ///
+    /// Requires `CAP_NET_ADMIN`. See `socket(7)` documentation for `SO_RCVBUFFORCE`
+    /// for more information.
+    pub fn force_recv_buffer_size(&self, size: usize) -> Result<(), SocketError> {
+        self.socket
+            .force_recv_buffer_size(size)
+            .map_err(SocketError::from)
+    }
+
+    /// Set the timeout for blocking receive operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A receive operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) -> Result<(), SocketError> {
+        self.socket
+            .set_recv_timeout(timeout)
+            .map_err(SocketError::from)
+    }
+
+    /// Return the timeout for blocking receive operations.
+    pub fn get_recv_timeout(&self) -> Result<Option<Duration>, SocketError> {
+        self.socket.get_recv_timeout().map_err(SocketError::from)
+    }
+
+    /// Set the timeout for blocking send operations. [`None`] blocks
+    /// indefinitely.
+    ///
+    /// A send operation that times out returns an error of kind
+    /// [`std::io::ErrorKind::WouldBlock`]. A zero [`Duration`] is rejected.
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) -> Result<(), SocketError> {
+        self.socket
+            .set_send_timeout(timeout)
+            .map_err(SocketError::from)
+    }
+
+    /// Return the timeout for blocking send operations.
+    pub fn get_send_timeout(&self) -> Result<Option<Duration>, SocketError> {
+        self.socket.get_send_timeout().map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, report errors delivering multicast messages sent
+    /// from this socket. If [`false`] is passed in, only report errors if no
+    /// listener received the message.
+    pub fn enable_broadcast_error(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .enable_broadcast_error(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if multicast delivery errors are reported for this socket.
+    pub fn get_broadcast_error_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_broadcast_error_enabled()
+            .map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, omit the payload of the original request from
+    /// error messages. If [`false`] is passed in, include the original request.
+    pub fn enable_cap_ack(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .enable_cap_ack(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if the original request is omitted from error messages.
+    pub fn get_cap_ack_enabled(&self) -> Result<bool, SocketError> {
+        self.socket.get_cap_ack_enabled().map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, ask the kernel to attach the destination multicast
+    /// group to each received message as `NETLINK_PKTINFO` control data. If
+    /// [`false`] is passed in, disable this.
+    pub fn enable_pktinfo(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .enable_pktinfo(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if `NETLINK_PKTINFO` control data is enabled for this
+    /// socket.
+    pub fn get_pktinfo_enabled(&self) -> Result<bool, SocketError> {
+        self.socket.get_pktinfo_enabled().map_err(SocketError::from)
+    }
+
+    /// If [`true`] is passed in, receive multicast messages from all network
+    /// namespaces that have an ID assigned in the namespace of the socket. If
+    /// [`false`] is passed in, only receive messages from the namespace of the
+    /// socket.
+    ///
+    /// Requires `CAP_NET_BROADCAST`.
+    pub fn enable_listen_all_nsid(&self, enable: bool) -> Result<(), SocketError> {
+        self.socket
+            .enable_listen_all_nsid(enable)
+            .map_err(SocketError::from)
+    }
+
+    /// Return [`true`] if multicast messages from all network namespaces are
+    /// received by this socket.
+    pub fn get_listen_all_nsid_enabled(&self) -> Result<bool, SocketError> {
+        self.socket
+            .get_listen_all_nsid_enabled()
+            .map_err(SocketError::from)
+    }
+
+    /// Attach the classic BPF program compiled from `filter` to the socket,
+    /// replacing any filter that is already attached.
+    pub fn attach_filter(&self, filter: &SocketFilter) -> Result<(), SocketError> {
+        self.socket.attach_filter(filter).map_err(SocketError::from)
+    }
+
+    /// Remove the filter attached with [`NlSocketHandle::attach_filter`].
+    pub fn detach_filter(&self) -> Result<(), SocketError> {
+        self.socket.detach_filter().map_err(SocketError::from)
     }
 }
 
-impl AsRawFd for NlSocketHandle {
+impl<S> AsRawFd for NlSocketHandle<S>
+where
+    S: AsRawFd,
+{
     fn as_raw_fd(&self) -> RawFd {
         self.socket.as_raw_fd()
     }
 }
 
+impl<S> AsFd for NlSocketHandle<S>
+where
+    S: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
 impl IntoRawFd for NlSocketHandle {
     fn into_raw_fd(self) -> RawFd {
         self.socket.into_raw_fd()
     }
 }
+
+/// Registering the socket with a [`mio::Registry`] puts it in nonblocking mode.
+#[cfg(feature = "mio")]
+impl mio::event::Source for NlSocketHandle {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        self.socket.nonblock()?;
+        mio::unix::SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
+/// A [`calloop`] event source that reads all pending messages from a socket and
+/// passes each parsed [`Nlmsghdr`] to the callback.
+///
+/// Messages that fail to parse and `ENOBUFS` errors from a receive buffer overrun
+/// are passed to the callback as errors without removing the source. Other
+/// receive errors are returned from the event loop.
+///
+/// The callback is passed the socket as metadata so that it can send messages.
+#[cfg(feature = "calloop")]
+pub struct NlSocketSource<T, P> {
+    source: Generic<NlSocketHandle, SocketError>,
+    data: PhantomData<(T, P)>,
+}
+
+#[cfg(feature = "calloop")]
+impl<T, P> NlSocketSource<T, P> {
+    /// Create an event source from a socket, putting it in nonblocking mode.
+    pub fn new(socket: NlSocketHandle) -> Result<Self, SocketError> {
+        socket.set_nonblock()?;
+        Ok(NlSocketSource {
+            source: Generic::new_with_error(socket, Interest::READ, Mode::Level),
+            data: PhantomData,
+        })
+    }
+
+    /// Get a reference to the socket.
+    pub fn get_ref(&self) -> &NlSocketHandle {
+        self.source.get_ref()
+    }
+
+    /// Return the socket in blocking mode.
+    pub fn into_inner(self) -> Result<NlSocketHandle, SocketError> {
+        let socket = self.source.unwrap();
+        socket.socket.block()?;
+        Ok(socket)
+    }
+}
+
+#[cfg(feature = "calloop")]
+impl<T, P> EventSource for NlSocketSource<T, P>
+where
+    T: NlType + Debug,
+    P: Size + FromBytesWithInput<Input = usize> + Debug,
+{
+    type Event = Result<Nlmsghdr<T, P>, SocketError>;
+    type Metadata = NoIoDrop<NlSocketHandle>;
+    type Ret = ();
+    type Error = SocketError;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> Result<PostAction, Self::Error>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        self.source
+            .process_events(readiness, token, |_, socket| loop {
+                let msgs = match socket.recv::<T, P>() {
+                    Ok((iter, _)) => iter.collect::<Vec<_>>(),
+                    Err(SocketError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                        return Ok(PostAction::Continue);
+                    }
+                    Err(SocketError::Io(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                        vec![Err(SocketError::Io(e))]
+                    }
+                    Err(e) => return Err(e),
+                };
+                for msg in msgs {
+                    callback(msg, socket);
+                }
+            })
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.source.register(poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.source.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop::Result<()> {
+        self.source.unregister(poll)
+    }
+}
+
+#[cfg(all(test, any(feature = "mio", feature = "calloop")))]
+mod test {
+    use super::*;
+
+    use crate::{
+        consts::genl::{CtrlAttr, CtrlCmd},
+        genl::{GenlmsghdrBuilder, NoUserHeader},
+        nl::{NlPayload, NlmsghdrBuilder},
+        test::setup,
+        types::Buffer,
+    };
+
+    fn send_ctrl_dump(socket: &NlSocketHandle) {
+        let msg = NlmsghdrBuilder::default()
+            .nl_type(GenlId::Ctrl)
+            .nl_flags(NlmF::REQUEST | NlmF::DUMP)
+            .nl_payload(NlPayload::Payload(
+                GenlmsghdrBuilder::<CtrlCmd, CtrlAttr, NoUserHeader>::default()
+                    .cmd(CtrlCmd::Getfamily)
+                    .version(2)
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        socket.send(&msg).unwrap();
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn real_test_mio_source() {
+        setup();
+
+        let mut socket = NlSocketHandle::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        let mut poll = mio::Poll::new().unwrap();
+        poll.registry()
+            .register(&mut socket, mio::Token(0), mio::Interest::READABLE)
+            .unwrap();
+        send_ctrl_dump(&socket);
+
+        let mut events = mio::Events::with_capacity(1);
+        let mut done = false;
+        while !done {
+            poll.poll(&mut events, Some(std::time::Duration::from_secs(5)))
+                .unwrap();
+            assert!(!events.is_empty());
+            loop {
+                match socket.recv::<u16, Buffer>() {
+                    Ok((iter, _)) => {
+                        for msg in iter {
+                            done |= *msg.unwrap().nl_type() == u16::from(Nlmsg::Done);
+                        }
+                    }
+                    Err(SocketError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => panic!("{e}"),
+                }
+            }
+        }
+        poll.registry().deregister(&mut socket).unwrap();
+    }
+
+    #[cfg(feature = "calloop")]
+    #[test]
+    fn real_test_calloop_source() {
+        setup();
+
+        let socket = NlSocketHandle::connect(NlFamily::Generic, None, Groups::empty()).unwrap();
+        send_ctrl_dump(&socket);
+        let source = NlSocketSource::<u16, Buffer>::new(socket).unwrap();
+
+        let mut event_loop = calloop::EventLoop::<Vec<u16>>::try_new().unwrap();
+        event_loop
+            .handle()
+            .insert_source(source, |msg, _, types| types.push(*msg.unwrap().nl_type()))
+            .unwrap();
+        let mut types = Vec::new();
+        while !types.contains(&u16::from(Nlmsg::Done)) {
+            event_loop
+                .dispatch(Some(std::time::Duration::from_secs(5)), &mut types)
+                .unwrap();
+            assert!(!types.is_empty());
+        }
+        assert!(types.contains(&u16::from(GenlId::Ctrl)));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    io::{self, IoSlice},
+    os::unix::io::AsRawFd,
+    time::Duration,
+};
+
+use crate::{
+    consts::socket::{Msg, NlFamily},
+    socket::shared::{NlSocket, RecvMetadata},
+    utils::{Groups, NetlinkBitArray},
+};
+
+/// Datagram transport for netlink messages.
+///
+/// [`NlSocket`] is the transport that communicates with the kernel. Other
+/// implementations, such as an in-memory fake kernel, can be used with
+/// [`NlSocketHandle`][crate::socket::synchronous::NlSocketHandle] and
+/// [`NlRouter`][crate::router::synchronous::NlRouter] to exercise code without
+/// a kernel or elevated privileges.
+///
+/// Each call to [`Transport::send`] and [`Transport::recv`] transfers exactly
+/// one datagram that may contain multiple netlink messages.
+pub trait Transport: Send + Sync {
+    /// Send a datagram.
+    fn send(&self, buf: &[u8], flags: Msg) -> Result<usize, io::Error>;
+
+    /// Send the concatenation of `bufs` as a single datagram.
+    ///
+    /// The default implementation copies `bufs` into a contiguous buffer and
+    /// calls [`Transport::send`].
+    fn send_vectored(&self, bufs: &[IoSlice<'_>], flags: Msg) -> Result<usize, io::Error> {
+        let buf = bufs
+            .iter()
+            .flat_map(|b| b.iter().copied())
+            .collect::<Vec<_>>();
+        self.send(&buf, flags)
+    }
+
+    /// Receive a datagram, returning the number of bytes read and the
+    /// [`RecvMetadata`] of the datagram.
+    ///
+    /// If the transport is nonblocking and no datagram is available, an error
+    /// with [`io::ErrorKind::WouldBlock`] is returned.
+    ///
+    /// [`Msg::PEEK`] leaves the datagram in the receive queue and [`Msg::TRUNC`]
+    /// returns the full size of the datagram even if it did not fit in `buf`,
+    /// as for netlink sockets.
+    fn recv(&self, buf: &mut [u8], flags: Msg) -> Result<(usize, RecvMetadata), io::Error>;
+
+    /// Get the PID for this transport.
+    fn pid(&self) -> Result<u32, io::Error>;
+
+    /// Get the netlink protocol family for this transport.
+    fn protocol(&self) -> Result<NlFamily, io::Error>;
+
+    /// Join multicast groups.
+    fn add_mcast_membership(&self, groups: Groups) -> Result<(), io::Error>;
+
+    /// Leave multicast groups.
+    fn drop_mcast_membership(&self, groups: Groups) -> Result<(), io::Error>;
+
+    /// List joined multicast groups.
+    fn list_mcast_membership(&self) -> Result<NetlinkBitArray, io::Error>;
+
+    /// Set whether [`Transport::recv`] waits for a datagram to become available.
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error>;
+
+    /// Wait up to `timeout` for a datagram to become available, returning
+    /// [`true`] if [`Transport::recv`] will not block.
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, io::Error>;
+}
+
+impl Transport for NlSocket {
+    fn send(&self, buf: &[u8], flags: Msg) -> Result<usize, io::Error> {
+        NlSocket::send(self, buf, flags)
+    }
+
+    fn send_vectored(&self, bufs: &[IoSlice<'_>], flags: Msg) -> Result<usize, io::Error> {
+        NlSocket::send_vectored(self, bufs, flags)
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: Msg) -> Result<(usize, RecvMetadata), io::Error> {
+        NlSocket::recv_msg(self, buf, flags)
+    }
+
+    fn pid(&self) -> Result<u32, io::Error> {
+        NlSocket::pid(self)
+    }
+
+    fn protocol(&self) -> Result<NlFamily, io::Error> {
+        NlSocket::protocol(self)
+    }
+
+    fn add_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
+        NlSocket::add_mcast_membership(self, groups)
+    }
+
+    fn drop_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
+        NlSocket::drop_mcast_membership(self, groups)
+    }
+
+    fn list_mcast_membership(&self) -> Result<NetlinkBitArray, io::Error> {
+        NlSocket::list_mcast_membership(self)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error> {
+        if nonblocking {
+            self.nonblock()
+        } else {
+            self.block()
+        }
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, io::Error> {
+        let mut pollfd = libc::pollfd {
+            fd: self.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe {
+            libc::poll(
+                &mut pollfd as *mut _,
+                1,
+                timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+            )
+        } {
+            i if i >= 0 => Ok(i > 0),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
@@ -0,0 +1,989 @@
+//! In-process emulation of the kernel side of `NETLINK_GENERIC` for tests.
+//!
+//! [`FakeKernel`] answers `CtrlCmd::Getfamily` requests for the families and
+//! multicast groups registered with it, including `nlctrl` itself. Requests for
+//! other families are passed to a handler registered with the family that
+//! decides whether to acknowledge the request, return an error or reply with
+//! messages. Replies are sent using the same flags and message types as the
+//! kernel, including `NLM_F_MULTI` and `NLMSG_DONE` for dumps.
+//!
+//! [`FakeKernel::connect`] creates a [`FakeTransport`] which can be used with
+//! [`NlSocketHandle::with_transport`][crate::socket::synchronous::NlSocketHandle::with_transport]
+//! and [`NlRouter::with_transport`][crate::router::synchronous::NlRouter::with_transport].
+//!
+//! ```
+//! use neli::{
+//!     consts::socket::NlFamily,
+//!     router::synchronous::NlRouter,
+//!     testing::{FakeKernel, FakeReply},
+//! };
+//!
+//! let kernel = FakeKernel::new();
+//! let id = kernel.add_family("example", 1, &["events"], |_| FakeReply::Ack);
+//! let (router, _) = NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+//! assert_eq!(router.resolve_genl_family("example").unwrap(), id);
+//! assert_eq!(
+//!     router.resolve_nl_mcast_group("example", "events").unwrap(),
+//!     kernel.mcast_group_id("example", "events").unwrap(),
+//! );
+//! ```
+//!
+//! [`ReplayTransport`] replays a session recorded with
+//! [`PcapWriter`][crate::socket::capture::PcapWriter] in place of the kernel.
+
+mod replay;
+
+use std::{
+    collections::VecDeque,
+    io::{self, Cursor},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use parking_lot::{Condvar, Mutex};
+
+use crate::{
+    consts::{
+        genl::{CtrlAttr, CtrlAttrMcastGrp, CtrlCmd},
+        nl::{GenlId, NlmF, Nlmsg},
+        socket::{Msg, NlFamily},
+    },
+    err::{NlmsgerrBuilder, NlmsghdrAckBuilder, NlmsghdrErrBuilder, SocketError},
+    genl::{AttrTypeBuilder, Genlmsghdr, GenlmsghdrBuilder, Nlattr, NlattrBuilder, NoUserHeader},
+    nl::{NlPayload, Nlmsghdr, NlmsghdrBuilder},
+    socket::{RecvMetadata, Transport},
+    types::{Buffer, GenlBuffer, NlBuffer},
+    utils::{Groups, NetlinkBitArray},
+    FromBytesWithInput, Size, ToBytes,
+};
+
+pub use crate::testing::replay::ReplayTransport;
+
+/// Generic netlink message as seen by the fake kernel.
+pub type FakeMessage = Genlmsghdr<u8, u16>;
+
+/// Handler deciding how the fake kernel replies to a request for a family.
+pub type FakeHandler = dyn Fn(&Nlmsghdr<u16, FakeMessage>) -> FakeReply + Send + Sync;
+
+/// Reply of the fake kernel to a request.
+#[derive(Clone, Debug)]
+pub enum FakeReply {
+    /// Acknowledge the request. An ACK is only sent if `NLM_F_ACK` was
+    /// requested.
+    Ack,
+    /// Reject the request with the given positive errno.
+    Error(i32),
+    /// Reply with a single message, followed by an ACK if `NLM_F_ACK` was
+    /// requested.
+    Message(FakeMessage),
+    /// Reply with a multipart message terminated by `NLMSG_DONE`.
+    Dump(Vec<FakeMessage>),
+}
+
+const CTRL_VERSION: u8 = 2;
+const CTRL_NOTIFY_GROUP: u32 = 0x10;
+const FIRST_FAMILY_ID: u16 = 0x11;
+
+struct Family {
+    id: u16,
+    name: String,
+    version: u8,
+    mcast_groups: Vec<(String, u32)>,
+    handler: Option<Arc<FakeHandler>>,
+}
+
+struct KernelState {
+    families: Vec<Family>,
+    next_family_id: u16,
+    next_group_id: u32,
+    next_pid: u32,
+    sockets: Vec<Weak<SocketState>>,
+}
+
+/// Emulation of the kernel side of `NETLINK_GENERIC`.
+///
+/// Cloning a [`FakeKernel`] returns a handle to the same kernel.
+#[derive(Clone)]
+pub struct FakeKernel {
+    state: Arc<Mutex<KernelState>>,
+}
+
+impl Default for FakeKernel {
+    fn default() -> Self {
+        FakeKernel::new()
+    }
+}
+
+impl FakeKernel {
+    /// Create a kernel with only the `nlctrl` family registered.
+    pub fn new() -> Self {
+        FakeKernel {
+            state: Arc::new(Mutex::new(KernelState {
+                families: vec![Family {
+                    id: GenlId::Ctrl.into(),
+                    name: "nlctrl".to_string(),
+                    version: CTRL_VERSION,
+                    mcast_groups: vec![("notify".to_string(), CTRL_NOTIFY_GROUP)],
+                    handler: None,
+                }],
+                next_family_id: FIRST_FAMILY_ID,
+                next_group_id: CTRL_NOTIFY_GROUP + 1,
+                next_pid: 1,
+                sockets: Vec::new(),
+            })),
+        }
+    }
+
+    /// Register a family with the given multicast groups and return its ID.
+    ///
+    /// `handler` is called for every request sent to the family. Like the
+    /// kernel, a `CtrlCmd::Newfamily` notification is sent to the `notify`
+    /// group of `nlctrl`.
+    pub fn add_family<F>(&self, name: &str, version: u8, mcast_groups: &[&str], handler: F) -> u16
+    where
+        F: Fn(&Nlmsghdr<u16, FakeMessage>) -> FakeReply + Send + Sync + 'static,
+    {
+        let notification = {
+            let mut state = self.state.lock();
+            let id = state.next_family_id;
+            state.next_family_id += 1;
+            let mut groups = Vec::with_capacity(mcast_groups.len());
+            for group in mcast_groups {
+                groups.push((group.to_string(), state.next_group_id));
+                state.next_group_id += 1;
+            }
+            let family = Family {
+                id,
+                name: name.to_string(),
+                version,
+                mcast_groups: groups,
+                handler: Some(Arc::new(handler)),
+            };
+            let notification = family_message(&family, CtrlCmd::Newfamily);
+            state.families.push(family);
+            (id, notification)
+        };
+        self.notify_ctrl(notification.1);
+        notification.0
+    }
+
+    /// Unregister a family, sending a `CtrlCmd::Delfamily` notification to the
+    /// `notify` group of `nlctrl`. Returns [`false`] if no family with this name
+    /// was registered.
+    pub fn remove_family(&self, name: &str) -> bool {
+        let notification = {
+            let mut state = self.state.lock();
+            let Some(index) = state.families.iter().position(|f| f.name == name) else {
+                return false;
+            };
+            let family = state.families.remove(index);
+            family_message(&family, CtrlCmd::Delfamily)
+        };
+        self.notify_ctrl(notification);
+        true
+    }
+
+    /// Get the ID of a registered multicast group.
+    pub fn mcast_group_id(&self, family: &str, group: &str) -> Option<u32> {
+        self.state
+            .lock()
+            .families
+            .iter()
+            .find(|f| f.name == family)?
+            .mcast_groups
+            .iter()
+            .find(|(name, _)| name == group)
+            .map(|(_, id)| *id)
+    }
+
+    /// Create a transport connected to this kernel with a newly allocated PID.
+    ///
+    /// Only [`NlFamily::Generic`] is emulated.
+    pub fn connect(&self, proto: NlFamily) -> FakeTransport {
+        if proto != NlFamily::Generic {
+            warn!("Fake kernel only emulates NETLINK_GENERIC; got {proto:?}");
+        }
+        let mut state = self.state.lock();
+        let pid = state.next_pid;
+        state.next_pid += 1;
+        let socket = Arc::new(SocketState::new(pid));
+        state.sockets.retain(|s| s.strong_count() > 0);
+        state.sockets.push(Arc::downgrade(&socket));
+        FakeTransport {
+            kernel: self.clone(),
+            socket,
+        }
+    }
+
+    /// Send a message to all transports that joined the multicast group `group`.
+    pub fn notify(&self, group: u32, nl_type: u16, msg: FakeMessage) -> Result<(), SocketError> {
+        let datagram = serialize(
+            &NlmsghdrBuilder::default()
+                .nl_type(nl_type)
+                .nl_flags(NlmF::empty())
+                .nl_payload(NlPayload::Payload(msg))
+                .build()?,
+        )?;
+        let sockets = self
+            .state
+            .lock()
+            .sockets
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+        for socket in sockets {
+            if socket.groups.lock().contains(&group) {
+                socket.deliver(datagram.clone(), Groups::new_groups(&[group]));
+            }
+        }
+        Ok(())
+    }
+
+    fn notify_ctrl(&self, msg: Result<FakeMessage, SocketError>) {
+        if let Err(e) = msg.and_then(|msg| self.notify(CTRL_NOTIFY_GROUP, GenlId::Ctrl.into(), msg))
+        {
+            warn!("Failed to send nlctrl notification: {e}");
+        }
+    }
+
+    fn handle_request(
+        &self,
+        request: &Nlmsghdr<u16, FakeMessage>,
+    ) -> Result<FakeReply, SocketError> {
+        let Some(payload) = request.get_payload() else {
+            return Ok(FakeReply::Error(libc::EINVAL));
+        };
+        if *request.nl_type() == u16::from(GenlId::Ctrl) {
+            return self.handle_ctrl(request, payload);
+        }
+        let handler = self
+            .state
+            .lock()
+            .families
+            .iter()
+            .find(|f| f.id == *request.nl_type())
+            .and_then(|f| f.handler.clone());
+        Ok(match handler {
+            Some(handler) => handler(request),
+            None => FakeReply::Error(libc::ENOENT),
+        })
+    }
+
+    fn handle_ctrl(
+        &self,
+        request: &Nlmsghdr<u16, FakeMessage>,
+        payload: &FakeMessage,
+    ) -> Result<FakeReply, SocketError> {
+        if *payload.cmd() != u8::from(CtrlCmd::Getfamily) {
+            return Ok(FakeReply::Error(libc::EOPNOTSUPP));
+        }
+        let state = self.state.lock();
+        if request.nl_flags().contains(NlmF::DUMP) {
+            return Ok(FakeReply::Dump(
+                state
+                    .families
+                    .iter()
+                    .map(|f| family_message(f, CtrlCmd::Newfamily))
+                    .collect::<Result<_, _>>()?,
+            ));
+        }
+        let attrs = payload.attrs().get_attr_handle();
+        let family = if let Ok(id) = attrs.get_attr_payload_as::<u16>(CtrlAttr::FamilyId.into()) {
+            state.families.iter().find(|f| f.id == id)
+        } else if let Ok(name) =
+            attrs.get_attr_payload_as_with_len::<String>(CtrlAttr::FamilyName.into())
+        {
+            state.families.iter().find(|f| f.name == name)
+        } else {
+            return Ok(FakeReply::Error(libc::EINVAL));
+        };
+        Ok(match family {
+            Some(f) => FakeReply::Message(family_message(f, CtrlCmd::Newfamily)?),
+            None => FakeReply::Error(libc::ENOENT),
+        })
+    }
+
+    fn reply(
+        &self,
+        request: &Nlmsghdr<u16, FakeMessage>,
+        reply: FakeReply,
+    ) -> Result<Vec<u8>, SocketError> {
+        let mut buffer = NlBuffer::new();
+        let response = |flags, payload| {
+            NlmsghdrBuilder::default()
+                .nl_type(*request.nl_type())
+                .nl_flags(flags)
+                .nl_seq(*request.nl_seq())
+                .nl_pid(*request.nl_pid())
+                .nl_payload(payload)
+                .build()
+        };
+        let ack = request.nl_flags().contains(NlmF::ACK);
+        match reply {
+            FakeReply::Ack => (),
+            FakeReply::Error(errno) => {
+                let error = NlmsgerrBuilder::default()
+                    .error(-errno)
+                    .nlmsg(
+                        NlmsghdrErrBuilder::default()
+                            .nl_type(*request.nl_type())
+                            .nl_flags(*request.nl_flags())
+                            .nl_seq(*request.nl_seq())
+                            .nl_pid(*request.nl_pid())
+                            .nl_payload(request.get_payload().cloned().ok_or_else(|| {
+                                SocketError::new("Request does not have a payload")
+                            })?)
+                            .build()?,
+                    )
+                    .build()?;
+                buffer.push(
+                    NlmsghdrBuilder::default()
+                        .nl_type(Nlmsg::Error.into())
+                        .nl_flags(NlmF::empty())
+                        .nl_seq(*request.nl_seq())
+                        .nl_pid(*request.nl_pid())
+                        .nl_payload(NlPayload::Err(error))
+                        .build()?,
+                );
+                return serialize(&buffer);
+            }
+            FakeReply::Message(msg) => {
+                buffer.push(response(NlmF::empty(), NlPayload::Payload(msg))?)
+            }
+            FakeReply::Dump(msgs) => {
+                for msg in msgs {
+                    buffer.push(response(NlmF::MULTI, NlPayload::Payload(msg))?);
+                }
+                buffer.push(
+                    NlmsghdrBuilder::default()
+                        .nl_type(Nlmsg::Done.into())
+                        .nl_flags(NlmF::MULTI)
+                        .nl_seq(*request.nl_seq())
+                        .nl_pid(*request.nl_pid())
+                        .nl_payload(NlPayload::Empty)
+                        .build()?,
+                );
+                return serialize(&buffer);
+            }
+        }
+        if ack {
+            let ack = NlmsgerrBuilder::default()
+                .nlmsg(
+                    NlmsghdrAckBuilder::default()
+                        .nl_len(*request.nl_len())
+                        .nl_type(*request.nl_type())
+                        .nl_flags(*request.nl_flags())
+                        .nl_seq(*request.nl_seq())
+                        .nl_pid(*request.nl_pid())
+                        .build()?,
+                )
+                .build()?;
+            buffer.push(
+                NlmsghdrBuilder::default()
+                    .nl_type(Nlmsg::Error.into())
+                    .nl_flags(NlmF::empty())
+                    .nl_seq(*request.nl_seq())
+                    .nl_pid(*request.nl_pid())
+                    .nl_payload(NlPayload::Ack(ack))
+                    .build()?,
+            );
+        }
+        serialize(&buffer)
+    }
+}
+
+fn serialize<M>(msg: &M) -> Result<Vec<u8>, SocketError>
+where
+    M: Size + ToBytes,
+{
+    let mut buffer = Cursor::new(vec![0; msg.padded_size()]);
+    msg.to_bytes(&mut buffer)?;
+    Ok(buffer.into_inner())
+}
+
+fn attr<P>(nla_type: u16, payload: P) -> Result<Nlattr<u16, Buffer>, SocketError>
+where
+    P: Size + ToBytes,
+{
+    Ok(NlattrBuilder::default()
+        .nla_type(AttrTypeBuilder::default().nla_type(nla_type).build()?)
+        .nla_payload(payload)
+        .build()?)
+}
+
+fn family_message(family: &Family, cmd: CtrlCmd) -> Result<FakeMessage, SocketError> {
+    let mut attrs = GenlBuffer::new();
+    attrs.push(attr(CtrlAttr::FamilyId.into(), family.id)?);
+    attrs.push(attr(CtrlAttr::FamilyName.into(), family.name.as_str())?);
+    attrs.push(attr(CtrlAttr::Version.into(), u32::from(family.version))?);
+    attrs.push(attr(CtrlAttr::Hdrsize.into(), 0u32)?);
+    attrs.push(attr(CtrlAttr::Maxattr.into(), 0u32)?);
+    if !family.mcast_groups.is_empty() {
+        let mut groups = attr(CtrlAttr::McastGroups.into(), Vec::<u8>::new())?;
+        for (index, (name, id)) in family.mcast_groups.iter().enumerate() {
+            groups = groups.nest(
+                &attr(index as u16 + 1, Vec::<u8>::new())?
+                    .nest(&attr(CtrlAttrMcastGrp::Name.into(), name.as_str())?)?
+                    .nest(&attr(CtrlAttrMcastGrp::Id.into(), *id)?)?,
+            )?;
+        }
+        attrs.push(groups);
+    }
+    Ok(GenlmsghdrBuilder::<u8, u16, NoUserHeader>::default()
+        .cmd(cmd.into())
+        .version(CTRL_VERSION)
+        .attrs(attrs)
+        .build()?)
+}
+
+struct SocketState {
+    pid: u32,
+    queue: Mutex<VecDeque<(Vec<u8>, Groups)>>,
+    readable: Condvar,
+    nonblocking: AtomicBool,
+    groups: Mutex<Vec<u32>>,
+}
+
+impl SocketState {
+    fn new(pid: u32) -> Self {
+        SocketState {
+            pid,
+            queue: Mutex::new(VecDeque::new()),
+            readable: Condvar::new(),
+            nonblocking: AtomicBool::new(false),
+            groups: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn deliver(&self, datagram: Vec<u8>, groups: Groups) {
+        self.queue.lock().push_back((datagram, groups));
+        self.readable.notify_all();
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: Msg) -> Result<(usize, RecvMetadata), io::Error> {
+        let mut queue = self.queue.lock();
+        loop {
+            if let Some((datagram, groups)) = queue.front() {
+                let len = datagram.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                let size = if flags.contains(Msg::TRUNC) {
+                    datagram.len()
+                } else {
+                    len
+                };
+                let metadata = RecvMetadata::new(0, groups.clone(), None);
+                if !flags.contains(Msg::PEEK) {
+                    queue.pop_front();
+                }
+                return Ok((size, metadata));
+            }
+            if self.nonblocking.load(Ordering::SeqCst) {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
+            self.readable.wait(&mut queue);
+        }
+    }
+
+    fn add_mcast_membership(&self, groups: Groups) {
+        let mut joined = self.groups.lock();
+        for group in groups.into_groups() {
+            if !joined.contains(&group) {
+                joined.push(group);
+            }
+        }
+    }
+
+    fn drop_mcast_membership(&self, groups: Groups) {
+        self.groups
+            .lock()
+            .retain(|group| !groups.as_groups().contains(group));
+    }
+
+    fn list_mcast_membership(&self) -> NetlinkBitArray {
+        let joined = self.groups.lock();
+        let mut array = NetlinkBitArray::new(joined.iter().copied().max().unwrap_or(0) as usize);
+        for group in joined.iter() {
+            array.set(*group as usize);
+        }
+        array
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::SeqCst);
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.queue.lock();
+        while queue.is_empty() {
+            if self.readable.wait_until(&mut queue, deadline).timed_out() {
+                break;
+            }
+        }
+        !queue.is_empty()
+    }
+}
+
+/// Transport connected to a [`FakeKernel`].
+pub struct FakeTransport {
+    kernel: FakeKernel,
+    socket: Arc<SocketState>,
+}
+
+impl Transport for FakeTransport {
+    fn send(&self, buf: &[u8], _: Msg) -> Result<usize, io::Error> {
+        let requests =
+            NlBuffer::<u16, FakeMessage>::from_bytes_with_input(&mut Cursor::new(buf), buf.len())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for request in requests.iter() {
+            let datagram = self
+                .kernel
+                .handle_request(request)
+                .and_then(|reply| self.kernel.reply(request, reply))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.socket.deliver(datagram, Groups::empty());
+        }
+        Ok(buf.len())
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: Msg) -> Result<(usize, RecvMetadata), io::Error> {
+        self.socket.recv(buf, flags)
+    }
+
+    fn pid(&self) -> Result<u32, io::Error> {
+        Ok(self.socket.pid)
+    }
+
+    fn protocol(&self) -> Result<NlFamily, io::Error> {
+        Ok(NlFamily::Generic)
+    }
+
+    fn add_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
+        self.socket.add_mcast_membership(groups);
+        Ok(())
+    }
+
+    fn drop_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
+        self.socket.drop_mcast_membership(groups);
+        Ok(())
+    }
+
+    fn list_mcast_membership(&self) -> Result<NetlinkBitArray, io::Error> {
+        Ok(self.socket.list_mcast_membership())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error> {
+        self.socket.set_nonblocking(nonblocking);
+        Ok(())
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, io::Error> {
+        Ok(self.socket.wait_readable(timeout))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        err::RouterError,
+        router::synchronous::NlRouter,
+        socket::synchronous::NlSocketHandle,
+        test::setup,
+        utils::{BufferGrowth, BufferPoolConfigBuilder},
+    };
+
+    fn request(cmd: u8) -> NlPayload<u16, FakeMessage> {
+        NlPayload::Payload(
+            GenlmsghdrBuilder::<u8, u16, NoUserHeader>::default()
+                .cmd(cmd)
+                .version(1)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_ctrl() {
+        setup();
+
+        let kernel = FakeKernel::new();
+        let id = kernel.add_family("first", 1, &[], |_| FakeReply::Ack);
+        let other_id = kernel.add_family("second", 1, &["events", "other"], |_| FakeReply::Ack);
+        let group = kernel.mcast_group_id("second", "other").unwrap();
+
+        let (router, _) = NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        router.set_default_timeout(Some(Duration::from_secs(1)));
+        assert_eq!(
+            router.resolve_genl_family("nlctrl").unwrap(),
+            u16::from(GenlId::Ctrl)
+        );
+        assert_eq!(router.resolve_genl_family("first").unwrap(), id);
+        assert_eq!(router.resolve_genl_family("second").unwrap(), other_id);
+        assert!(matches!(
+            router.resolve_genl_family("missing"),
+            Err(RouterError::Nlmsgerr(e, _)) if *e.error() == -libc::ENOENT
+        ));
+        assert_eq!(
+            router.resolve_nl_mcast_group("second", "other").unwrap(),
+            group
+        );
+        assert_eq!(
+            router.lookup_id(group).unwrap(),
+            ("second".to_string(), "other".to_string())
+        );
+        assert_eq!(
+            router.lookup_id(CTRL_NOTIFY_GROUP).unwrap(),
+            ("nlctrl".to_string(), "notify".to_string())
+        );
+
+        let info = router.get_family_info("second").unwrap();
+        assert_eq!(info.name(), "second");
+        assert_eq!(*info.id(), other_id);
+        assert_eq!(*info.version(), 1);
+        assert_eq!(info.mcast_groups().len(), 2);
+        assert_eq!(*info.mcast_group("other").unwrap().id(), group);
+        assert!(info.mcast_group("missing").is_none());
+        let families = router.list_families().unwrap();
+        assert_eq!(
+            families
+                .iter()
+                .map(|f| f.name().as_str())
+                .collect::<Vec<_>>(),
+            ["nlctrl", "first", "second"]
+        );
+    }
+
+    #[test]
+    fn test_replies() {
+        setup();
+
+        let kernel = FakeKernel::new();
+        let id = kernel.add_family("family", 1, &[], |request| {
+            match request.get_payload().unwrap().cmd() {
+                1 => FakeReply::Ack,
+                2 => FakeReply::Error(libc::EPERM),
+                3 => FakeReply::Message(request.get_payload().unwrap().clone()),
+                _ => FakeReply::Dump(vec![request.get_payload().unwrap().clone(); 3]),
+            }
+        });
+        let (router, _) = NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        router.set_default_timeout(Some(Duration::from_secs(1)));
+
+        let recv = router
+            .send::<_, _, u16, FakeMessage>(id, NlmF::ACK, request(1))
+            .unwrap();
+        let msgs = recv.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(matches!(msgs[0].nl_payload(), NlPayload::Ack(_)));
+
+        let mut recv = router
+            .send::<_, _, u16, FakeMessage>(id, NlmF::ACK, request(2))
+            .unwrap();
+        assert!(matches!(
+            recv.next(),
+            Some(Err(RouterError::Nlmsgerr(e, _))) if *e.error() == -libc::EPERM
+        ));
+
+        let recv = router
+            .send::<_, _, u16, FakeMessage>(id, NlmF::ACK, request(3))
+            .unwrap();
+        let msgs = recv.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[0].get_payload().unwrap().cmd(), &3);
+
+        let recv = router
+            .send::<_, _, u16, FakeMessage>(id, NlmF::DUMP, request(4))
+            .unwrap();
+        let msgs = recv.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs[..3]
+            .iter()
+            .all(|msg| msg.nl_flags().contains(NlmF::MULTI)));
+        assert_eq!(*msgs[3].nl_type(), u16::from(Nlmsg::Done));
+
+        let mut recv = router
+            .send::<_, _, u16, FakeMessage>(id + 1, NlmF::ACK, request(1))
+            .unwrap();
+        assert!(matches!(
+            recv.next(),
+            Some(Err(RouterError::Nlmsgerr(e, _))) if *e.error() == -libc::ENOENT
+        ));
+
+        // A reply that arrives after its request timed out must not end other
+        // requests.
+        let mut late = router
+            .send::<_, _, u16, FakeMessage>(id, NlmF::ACK, request(1))
+            .unwrap();
+        late.set_deadline(Some(Instant::now()));
+        assert!(matches!(late.next(), Some(Err(RouterError::Timeout))));
+        let recv = router
+            .send::<_, _, u16, FakeMessage>(id, NlmF::ACK, request(1))
+            .unwrap();
+        let msgs = recv.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(msgs.len(), 1);
+    }
+
+    #[test]
+    fn test_adaptive_buffers() {
+        setup();
+
+        let kernel = FakeKernel::new();
+        // A dump reply large enough to exceed the default receive buffer.
+        let id = kernel.add_family("family", 1, &[], |request| {
+            FakeReply::Dump(vec![request.get_payload().unwrap().clone(); 4096])
+        });
+        let socket = NlSocketHandle::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        let msg = NlmsghdrBuilder::default()
+            .nl_type(id)
+            .nl_flags(NlmF::REQUEST | NlmF::DUMP)
+            .nl_payload(request(1))
+            .build()
+            .unwrap();
+
+        socket.send(&msg).unwrap();
+        assert!(matches!(
+            socket.recv_all::<u16, FakeMessage>(),
+            Err(SocketError::Truncated { size, capacity }) if size > capacity
+        ));
+
+        socket.enable_adaptive_buffers(true);
+        socket.send(&msg).unwrap();
+        let (msgs, _) = socket.recv_all::<u16, FakeMessage>().unwrap();
+        assert_eq!(msgs.len(), 4097);
+
+        for (growth, truncated) in [(BufferGrowth::Fixed, true), (BufferGrowth::OnDemand, false)] {
+            let config = BufferPoolConfigBuilder::default()
+                .buffers(1)
+                .buffer_size(64)
+                .growth(growth)
+                .build()
+                .unwrap();
+            let socket = NlSocketHandle::with_transport(kernel.connect(NlFamily::Generic))
+                .unwrap()
+                .with_buffer_pool(config);
+            socket.send(&msg).unwrap();
+            match socket.recv_all::<u16, FakeMessage>() {
+                Err(SocketError::Truncated { size, capacity }) => {
+                    assert!(truncated);
+                    assert_eq!(capacity, 64);
+                    assert!(size > capacity);
+                }
+                Ok((msgs, _)) => {
+                    assert!(!truncated);
+                    assert_eq!(msgs.len(), 4097);
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_notifications() {
+        setup();
+
+        let kernel = FakeKernel::new();
+        let (router, mut multicast) =
+            NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        router
+            .add_mcast_membership(Groups::new_groups(&[CTRL_NOTIFY_GROUP]))
+            .unwrap();
+        assert_eq!(
+            router.list_mcast_membership().unwrap().to_vec(),
+            vec![CTRL_NOTIFY_GROUP]
+        );
+
+        let id = kernel.add_family("family", 1, &[], |_| FakeReply::Ack);
+        assert!(kernel.remove_family("family"));
+        assert!(!kernel.remove_family("family"));
+
+        multicast.set_deadline(Some(Instant::now() + Duration::from_secs(1)));
+        for cmd in [CtrlCmd::Newfamily, CtrlCmd::Delfamily] {
+            let msg = multicast
+                .next_typed::<GenlId, Genlmsghdr<CtrlCmd, CtrlAttr>>()
+                .unwrap()
+                .unwrap();
+            let metadata = multicast.metadata().unwrap();
+            assert_eq!(*metadata.pid(), 0);
+            assert_eq!(metadata.groups().as_groups(), vec![CTRL_NOTIFY_GROUP]);
+            let payload = msg.get_payload().unwrap();
+            assert_eq!(*payload.cmd(), cmd);
+            assert_eq!(
+                payload
+                    .attrs()
+                    .get_attr_handle()
+                    .get_attr_payload_as::<u16>(CtrlAttr::FamilyId)
+                    .unwrap(),
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn test_genl_cache() {
+        use std::sync::atomic::AtomicUsize;
+
+        use crate::socket::capture::{Capture, Direction};
+
+        #[derive(Default)]
+        struct Requests(AtomicUsize);
+
+        impl Capture for Requests {
+            fn capture(
+                &self,
+                direction: Direction,
+                _: NlFamily,
+                _: &Groups,
+                _: &[u8],
+            ) -> Result<(), io::Error> {
+                if direction == Direction::Outgoing {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                }
+                Ok(())
+            }
+        }
+
+        setup();
+
+        let kernel = FakeKernel::new();
+        let id = kernel.add_family("family", 1, &["events"], |_| FakeReply::Ack);
+        let group = kernel.mcast_group_id("family", "events").unwrap();
+        let (router, mut multicast) =
+            NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        router.set_default_timeout(Some(Duration::from_secs(1)));
+        let requests = Arc::new(Requests::default());
+        router
+            .set_capture(Some(Arc::clone(&requests) as Arc<dyn Capture>))
+            .unwrap();
+        router.enable_genl_cache(true).unwrap();
+        assert_eq!(
+            router.list_mcast_membership().unwrap().to_vec(),
+            vec![CTRL_NOTIFY_GROUP]
+        );
+
+        assert_eq!(router.resolve_genl_family("family").unwrap(), id);
+        let sent = requests.0.load(Ordering::SeqCst);
+        assert_eq!(router.resolve_genl_family("family").unwrap(), id);
+        assert_eq!(
+            router.resolve_nl_mcast_group("family", "events").unwrap(),
+            group
+        );
+        assert_eq!(
+            router.lookup_id(group).unwrap(),
+            ("family".to_string(), "events".to_string())
+        );
+        assert_eq!(requests.0.load(Ordering::SeqCst), sent);
+
+        assert!(kernel.remove_family("family"));
+        assert!(matches!(
+            router.resolve_genl_family("family"),
+            Err(RouterError::Nlmsgerr(e, _)) if *e.error() == -libc::ENOENT
+        ));
+        let id = kernel.add_family("family", 1, &[], |_| FakeReply::Ack);
+        assert_eq!(router.resolve_genl_family("family").unwrap(), id);
+        assert!(router.resolve_nl_mcast_group("family", "events").is_err());
+
+        multicast.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(
+            multicast.next_typed::<u16, Buffer>(),
+            Some(Err(RouterError::Timeout))
+        ));
+
+        let notify = Groups::new_groups(&[CTRL_NOTIFY_GROUP]);
+        router.add_mcast_membership(notify.clone()).unwrap();
+        assert!(kernel.remove_family("family"));
+        multicast.set_deadline(Some(Instant::now() + Duration::from_secs(1)));
+        assert!(matches!(multicast.next_typed::<u16, Buffer>(), Some(Ok(_))));
+
+        router.drop_mcast_membership(notify).unwrap();
+        assert_eq!(
+            router.list_mcast_membership().unwrap().to_vec(),
+            vec![CTRL_NOTIFY_GROUP]
+        );
+        let id = kernel.add_family("family", 1, &["events"], |_| FakeReply::Ack);
+        assert_eq!(router.resolve_genl_family("family").unwrap(), id);
+        let sent = requests.0.load(Ordering::SeqCst);
+        assert_eq!(
+            router.resolve_nl_mcast_group("family", "events").unwrap(),
+            kernel.mcast_group_id("family", "events").unwrap()
+        );
+        assert_eq!(requests.0.load(Ordering::SeqCst), sent);
+        multicast.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(
+            multicast.next_typed::<u16, Buffer>(),
+            Some(Err(RouterError::Timeout))
+        ));
+
+        router.enable_genl_cache(false).unwrap();
+        assert!(router.list_mcast_membership().unwrap().to_vec().is_empty());
+    }
+
+    #[test]
+    fn test_family_events() {
+        use crate::{genl::FamilyEvent, router::synchronous::FamilyEvents};
+
+        setup();
+
+        let kernel = FakeKernel::new();
+        let (router, multicast) =
+            NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        router.set_default_timeout(Some(Duration::from_secs(1)));
+        let mut events = FamilyEvents::new(router, multicast).unwrap();
+        events.set_deadline(Some(Instant::now() + Duration::from_secs(1)));
+
+        let id = kernel.add_family("family", 1, &["events"], |_| FakeReply::Ack);
+        match events.next().unwrap().unwrap() {
+            FamilyEvent::Added(info) => {
+                assert_eq!(info.name(), "family");
+                assert_eq!(*info.id(), id);
+                assert!(info.mcast_group("events").is_some());
+            }
+            e => panic!("Unexpected event {e:?}"),
+        }
+
+        let family = Family {
+            id,
+            name: "family".to_string(),
+            version: 1,
+            mcast_groups: vec![("other".to_string(), 0x40)],
+            handler: None,
+        };
+        for cmd in [
+            CtrlCmd::NewmcastGrp,
+            CtrlCmd::Getfamily,
+            CtrlCmd::DelmcastGrp,
+        ] {
+            kernel.notify_ctrl(family_message(&family, cmd));
+        }
+        match events.next().unwrap().unwrap() {
+            FamilyEvent::GroupAdded {
+                family_name,
+                family_id,
+                group,
+            } => {
+                assert_eq!(family_name, "family");
+                assert_eq!(family_id, id);
+                assert_eq!(group.name(), "other");
+                assert_eq!(*group.id(), 0x40);
+            }
+            e => panic!("Unexpected event {e:?}"),
+        }
+        assert!(matches!(
+            events.next().unwrap().unwrap(),
+            FamilyEvent::GroupRemoved { group, .. } if group.name() == "other"
+        ));
+
+        assert!(kernel.remove_family("family"));
+        let event = events.next().unwrap().unwrap();
+        assert!(matches!(event, FamilyEvent::Removed(_)));
+        assert_eq!(event.family_name(), "family");
+
+        events.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        assert!(matches!(events.next(), Some(Err(RouterError::Timeout))));
+    }
+}
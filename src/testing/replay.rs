@@ -0,0 +1,281 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::Path,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    consts::{
+        alignto,
+        socket::{Msg, NlFamily},
+    },
+    socket::{
+        capture::{CapturedDatagram, Direction, PcapReader},
+        RecvMetadata, Transport,
+    },
+    testing::SocketState,
+    utils::{Groups, NetlinkBitArray},
+};
+
+/// Length of a netlink message header.
+const NLMSGHDR_LEN: usize = 16;
+
+/// PID used if the recording does not contain one.
+const DEFAULT_PID: u32 = 1;
+
+/// Header fields of a message in a datagram.
+struct Header {
+    offset: usize,
+    nl_type: u16,
+    nl_seq: u32,
+    nl_pid: u32,
+}
+
+fn headers(datagram: &[u8]) -> Vec<Header> {
+    let u32_at = |offset: usize| {
+        u32::from_ne_bytes([
+            datagram[offset],
+            datagram[offset + 1],
+            datagram[offset + 2],
+            datagram[offset + 3],
+        ])
+    };
+    let mut headers = Vec::new();
+    let mut offset = 0;
+    while offset + NLMSGHDR_LEN <= datagram.len() {
+        headers.push(Header {
+            offset,
+            nl_type: u16::from_ne_bytes([datagram[offset + 4], datagram[offset + 5]]),
+            nl_seq: u32_at(offset + 8),
+            nl_pid: u32_at(offset + 12),
+        });
+        let len = u32_at(offset) as usize;
+        if len < NLMSGHDR_LEN {
+            break;
+        }
+        offset += alignto(len);
+    }
+    headers
+}
+
+struct Session {
+    datagrams: VecDeque<CapturedDatagram>,
+    seqs: HashMap<u32, u32>,
+}
+
+/// Transport that replays a recorded session.
+///
+/// A session can be recorded by passing a
+/// [`PcapWriter`][crate::socket::capture::PcapWriter] to
+/// [`NlRouter::set_capture`][crate::router::synchronous::NlRouter::set_capture].
+///
+/// Each datagram sent on the transport is matched with the next request in the
+/// recording, which must contain the same number of messages with the same
+/// message types. The datagrams received after that request in the recording
+/// are then delivered with the sequence numbers and PIDs of responses rewritten
+/// to match the requests that were sent. Datagrams received before the first
+/// request, such as multicast notifications, are delivered immediately.
+///
+/// ```no_run
+/// use neli::{router::synchronous::NlRouter, testing::ReplayTransport};
+///
+/// let (router, _) = NlRouter::with_transport(ReplayTransport::open("session.pcap").unwrap()).unwrap();
+/// let id = router.resolve_genl_family("nl80211").unwrap();
+/// ```
+pub struct ReplayTransport {
+    socket: SocketState,
+    protocol: NlFamily,
+    session: Mutex<Session>,
+}
+
+impl ReplayTransport {
+    /// Replay the session recorded in the pcap file at `path`.
+    pub fn open<P>(path: P) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(ReplayTransport::new(
+            PcapReader::open(path)?.collect::<Result<Vec<_>, _>>()?,
+        ))
+    }
+
+    /// Replay a session from the recorded datagrams.
+    pub fn new<I>(datagrams: I) -> Self
+    where
+        I: IntoIterator<Item = CapturedDatagram>,
+    {
+        let datagrams = datagrams.into_iter().collect::<VecDeque<_>>();
+        let protocol = datagrams
+            .front()
+            .map(|d| d.protocol)
+            .unwrap_or(NlFamily::Generic);
+        let pid = datagrams
+            .iter()
+            .filter(|d| d.direction == Direction::Outgoing)
+            .flat_map(|d| headers(&d.datagram))
+            .map(|h| h.nl_pid)
+            .find(|pid| *pid != 0)
+            .unwrap_or(DEFAULT_PID);
+        let transport = ReplayTransport {
+            socket: SocketState::new(pid),
+            protocol,
+            session: Mutex::new(Session {
+                datagrams,
+                seqs: HashMap::new(),
+            }),
+        };
+        transport.deliver_responses(&mut transport.session.lock());
+        transport
+    }
+
+    /// Return [`true`] if every datagram in the recording has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.session.lock().datagrams.is_empty()
+    }
+
+    fn deliver_responses(&self, session: &mut Session) {
+        while let Some(recorded) = session.datagrams.pop_front() {
+            if recorded.direction == Direction::Outgoing {
+                session.datagrams.push_front(recorded);
+                break;
+            }
+            let CapturedDatagram {
+                mut datagram,
+                groups,
+                ..
+            } = recorded;
+            for header in headers(&datagram) {
+                if header.nl_pid == 0 {
+                    continue;
+                }
+                if let Some(seq) = session.seqs.get(&header.nl_seq) {
+                    datagram[header.offset + 8..header.offset + 12]
+                        .copy_from_slice(&seq.to_ne_bytes());
+                    datagram[header.offset + 12..header.offset + 16]
+                        .copy_from_slice(&self.socket.pid.to_ne_bytes());
+                }
+            }
+            self.socket.deliver(datagram, groups);
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, buf: &[u8], _: Msg) -> Result<usize, io::Error> {
+        let mut session = self.session.lock();
+        let expected = match session.datagrams.front() {
+            Some(d) => headers(&d.datagram),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "No requests left in the recording",
+                ))
+            }
+        };
+        let sent = headers(buf);
+        if sent.len() != expected.len()
+            || sent
+                .iter()
+                .zip(expected.iter())
+                .any(|(s, e)| s.nl_type != e.nl_type)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Request does not match the next request in the recording",
+            ));
+        }
+        session.datagrams.pop_front();
+        for (s, e) in sent.iter().zip(expected.iter()) {
+            session.seqs.insert(e.nl_seq, s.nl_seq);
+        }
+        self.deliver_responses(&mut session);
+        Ok(buf.len())
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: Msg) -> Result<(usize, RecvMetadata), io::Error> {
+        self.socket.recv(buf, flags)
+    }
+
+    fn pid(&self) -> Result<u32, io::Error> {
+        Ok(self.socket.pid)
+    }
+
+    fn protocol(&self) -> Result<NlFamily, io::Error> {
+        Ok(self.protocol)
+    }
+
+    fn add_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
+        self.socket.add_mcast_membership(groups);
+        Ok(())
+    }
+
+    fn drop_mcast_membership(&self, groups: Groups) -> Result<(), io::Error> {
+        self.socket.drop_mcast_membership(groups);
+        Ok(())
+    }
+
+    fn list_mcast_membership(&self) -> Result<NetlinkBitArray, io::Error> {
+        Ok(self.socket.list_mcast_membership())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error> {
+        self.socket.set_nonblocking(nonblocking);
+        Ok(())
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, io::Error> {
+        Ok(self.socket.wait_readable(timeout))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::{
+        router::synchronous::NlRouter,
+        socket::capture::{Capture, PcapWriter},
+        testing::{FakeKernel, FakeReply},
+    };
+
+    #[test]
+    fn test_replay() {
+        let kernel = FakeKernel::new();
+        let id = kernel.add_family("example", 1, &["events"], |_| FakeReply::Ack);
+        let (router, _) = NlRouter::with_transport(kernel.connect(NlFamily::Generic)).unwrap();
+        // Offset the recorded sequence numbers from those of the replay.
+        router.resolve_genl_family("example").unwrap();
+
+        let writer = Arc::new(PcapWriter::new(Vec::new()).unwrap());
+        router
+            .set_capture(Some(Arc::clone(&writer) as Arc<dyn Capture>))
+            .unwrap();
+        router.resolve_genl_family("example").unwrap();
+        router.resolve_nl_mcast_group("example", "events").unwrap();
+        router.set_capture(None).unwrap();
+        drop(router);
+        let bytes = Arc::try_unwrap(writer)
+            .ok()
+            .expect("capture still referenced")
+            .into_inner();
+
+        let replay = ReplayTransport::new(
+            PcapReader::new(bytes.as_slice())
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+        );
+        let (router, _) = NlRouter::with_transport(replay).unwrap();
+        assert_eq!(router.resolve_genl_family("example").unwrap(), id);
+        assert_eq!(
+            router.resolve_nl_mcast_group("example", "events").unwrap(),
+            kernel.mcast_group_id("example", "events").unwrap(),
+        );
+        assert!(router.resolve_genl_family("example").is_err());
+    }
+}
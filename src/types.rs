@@ -8,7 +8,7 @@
 
 use std::{
     fmt::{self, Debug},
-    io::{Read, Write},
+    io::Read,
     iter::FromIterator,
     slice::{Iter, IterMut},
 };
@@ -48,7 +48,10 @@ impl FromBytesWithInput for Buffer {
 }
 
 impl ToBytes for Buffer {
-    fn to_bytes(&self, buffer: &mut std::io::Cursor<Vec<u8>>) -> Result<(), crate::err::SerError> {
+    fn to_bytes(
+        &self,
+        buffer: &mut (impl std::io::Write + std::io::Seek),
+    ) -> Result<(), crate::err::SerError> {
         buffer.write_all(self.0.as_slice())?;
         Ok(())
     }
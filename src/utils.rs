@@ -7,10 +7,17 @@
 //! have to be aware of them.
 
 use std::mem::size_of;
+#[cfg(any(feature = "sync", feature = "async-core"))]
+use std::{
+    cell::Cell,
+    mem::take,
+    ops::{Deref, DerefMut},
+};
 
-#[cfg(any(feature = "sync", feature = "async"))]
-use crate::consts::MAX_NL_LENGTH;
-use crate::err::MsgError;
+use derive_builder::Builder;
+use getset::Getters;
+
+use crate::{consts::MAX_NL_LENGTH, err::MsgError};
 
 type BitArrayType = u32;
 
@@ -147,6 +154,7 @@ fn mask_to_vec(mask: u32) -> Vec<u32> {
 
 /// Struct implementing handling of groups both as numerical values and as
 /// bitmasks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Groups(Vec<u32>);
 
 impl Groups {
@@ -228,6 +236,148 @@ impl Groups {
     }
 }
 
+/// Policy for receiving datagrams that do not fit in a buffer of a
+/// [`BufferPool`][synchronous::BufferPool].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BufferGrowth {
+    /// Keep the configured buffer size and report datagrams that do not fit as
+    /// [`SocketError::Truncated`][crate::err::SocketError::Truncated].
+    #[default]
+    Fixed,
+    /// Peek at the size of each datagram and grow the buffer if the datagram
+    /// does not fit.
+    OnDemand,
+}
+
+/// Configuration of the buffers used for receive operations on a socket.
+///
+/// The defaults can be set at compile time with the `NELI_MAX_PARALLEL_READ_OPS`
+/// and `NELI_AUTO_BUFFER_LEN` environment variables.
+///
+/// ```
+/// use neli::utils::{BufferGrowth, BufferPoolConfigBuilder};
+///
+/// let config = BufferPoolConfigBuilder::default()
+///     .buffers(1)
+///     .buffer_size(4096)
+///     .growth(BufferGrowth::OnDemand)
+///     .build()
+///     .unwrap();
+/// assert_eq!(*config.buffer_size(), 4096);
+/// ```
+#[derive(Builder, Getters, Clone, Debug, PartialEq, Eq)]
+#[builder(pattern = "owned")]
+#[builder(build_fn(validate = "Self::validate"))]
+pub struct BufferPoolConfig {
+    /// Number of buffers, which limits the number of parallel receive operations.
+    #[getset(get = "pub")]
+    #[builder(default = "default_buffers()")]
+    buffers: usize,
+    /// Size in bytes of each buffer.
+    #[getset(get = "pub")]
+    #[builder(default = "default_buffer_size()")]
+    buffer_size: usize,
+    /// Policy for datagrams that do not fit in a buffer.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    growth: BufferGrowth,
+}
+
+impl BufferPoolConfigBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if self.buffers == Some(0) {
+            return Err("A buffer pool requires at least one buffer".to_string());
+        }
+        if self.buffer_size == Some(0) {
+            return Err("The buffers of a buffer pool cannot be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        BufferPoolConfig {
+            buffers: default_buffers(),
+            buffer_size: default_buffer_size(),
+            growth: BufferGrowth::default(),
+        }
+    }
+}
+
+fn default_buffers() -> usize {
+    option_env!("NELI_MAX_PARALLEL_READ_OPS")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(3)
+}
+
+fn default_buffer_size() -> usize {
+    option_env!("NELI_AUTO_BUFFER_LEN")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(MAX_NL_LENGTH)
+}
+
+/// Largest capacity of a send buffer that is kept for reuse. Buffers that grew
+/// to serialize a larger message are shrunk before being cached.
+#[cfg(any(feature = "sync", feature = "async-core"))]
+const MAX_RETAINED_SEND_BUFFER: usize = MAX_NL_LENGTH;
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+thread_local! {
+    static SEND_BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// Buffer for serializing an outgoing message.
+///
+/// The buffer is taken from a per-thread cache so that sockets do not share a
+/// lock while sending and is returned to the cache of the thread that drops it.
+#[cfg(any(feature = "sync", feature = "async-core"))]
+pub(crate) struct SendBuffer(Vec<u8>);
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+impl SendBuffer {
+    /// Take an empty buffer from the cache of the current thread.
+    pub(crate) fn take() -> Self {
+        let mut buffer = SEND_BUFFER.try_with(Cell::take).unwrap_or_default();
+        buffer.clear();
+        SendBuffer(buffer)
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+impl Deref for SendBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+impl DerefMut for SendBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "async-core"))]
+impl Drop for SendBuffer {
+    fn drop(&mut self) {
+        let mut buffer = take(&mut self.0);
+        buffer.clear();
+        buffer.shrink_to(MAX_RETAINED_SEND_BUFFER);
+        // Ignore errors from a thread that is shutting down.
+        let _ = SEND_BUFFER.try_with(|cache| {
+            let cached = cache.take();
+            cache.set(if cached.capacity() > buffer.capacity() {
+                cached
+            } else {
+                buffer
+            });
+        });
+    }
+}
+
 /// Synchronous (blocking) utils.
 #[cfg(feature = "sync")]
 pub mod synchronous {
@@ -286,12 +436,7 @@ pub mod synchronous {
 
         /// Reset the buffer to the original size.
         pub fn reset(&mut self) {
-            self.1.resize(
-                option_env!("NELI_AUTO_BUFFER_LEN")
-                    .and_then(|s| s.parse::<usize>().ok())
-                    .unwrap_or(MAX_NL_LENGTH),
-                0,
-            );
+            self.1.resize(self.0.buffer_size, 0);
         }
     }
 
@@ -303,12 +448,7 @@ pub mod synchronous {
                 let mut sem_info = self.0.sem_info.lock();
                 let mut pool = self.0.pool.lock();
                 sem_info.count -= 1;
-                vec.resize(
-                    option_env!("NELI_AUTO_BUFFER_LEN")
-                        .and_then(|s| s.parse::<usize>().ok())
-                        .unwrap_or(MAX_NL_LENGTH),
-                    0,
-                );
+                vec.resize(self.0.buffer_size, 0);
                 pool.push(vec);
                 trace!(
                     "Semaphore released; current count is {}, available is {}",
@@ -324,35 +464,35 @@ pub mod synchronous {
     /// truncation.
     pub struct BufferPool {
         pool: Mutex<Vec<Vec<u8>>>,
+        buffer_size: usize,
         sem_info: Mutex<SemInfo>,
         condvar: Condvar,
     }
 
     impl Default for BufferPool {
         fn default() -> Self {
-            let max_parallel = option_env!("NELI_MAX_PARALLEL_READ_OPS")
-                .and_then(|s| s.parse::<u64>().ok())
-                .unwrap_or(3);
-            let buffer_size = option_env!("NELI_AUTO_BUFFER_LEN")
-                .and_then(|s| s.parse::<usize>().ok())
-                .unwrap_or(MAX_NL_LENGTH);
+            BufferPool::new(&BufferPoolConfig::default())
+        }
+    }
 
+    impl BufferPool {
+        /// Create a pool with the buffers described by `config`.
+        pub fn new(config: &BufferPoolConfig) -> Self {
             BufferPool {
                 pool: Mutex::new(
-                    (0..max_parallel)
-                        .map(|_| vec![0; buffer_size])
+                    (0..config.buffers)
+                        .map(|_| vec![0; config.buffer_size])
                         .collect::<Vec<_>>(),
                 ),
+                buffer_size: config.buffer_size,
                 sem_info: Mutex::new(SemInfo {
                     count: 0,
-                    max: max_parallel,
+                    max: config.buffers as u64,
                 }),
                 condvar: Condvar::new(),
             }
         }
-    }
 
-    impl BufferPool {
         /// Acquire a buffer for use.
         ///
         /// This method is backed by a semaphore.
@@ -424,11 +564,29 @@ pub mod synchronous {
                 assert_eq!(Some(&1), buf.first());
             }
         }
+
+        #[test]
+        fn test_buffer_pool_size() {
+            setup();
+
+            let pool = BufferPool::new(
+                &BufferPoolConfigBuilder::default()
+                    .buffers(1)
+                    .buffer_size(16)
+                    .build()
+                    .unwrap(),
+            );
+            let mut guard = pool.acquire();
+            assert_eq!(guard.len(), 16);
+            guard.resize(64, 0);
+            drop(guard);
+            assert_eq!(pool.acquire().len(), 16);
+        }
     }
 }
 
 /// Asynchronous utils.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub mod asynchronous {
     use super::*;
 
@@ -437,14 +595,14 @@ pub mod asynchronous {
         ops::{Deref, DerefMut},
     };
 
+    use async_lock::{Semaphore, SemaphoreGuard};
     use log::trace;
     use parking_lot::Mutex;
-    use tokio::sync::{Semaphore, SemaphorePermit};
 
     /// Guard indicating that a buffer has been acquired and the semaphore has been
     /// incremented.
     #[allow(dead_code)]
-    pub struct BufferPoolGuard<'a>(&'a BufferPool, SemaphorePermit<'a>, Vec<u8>);
+    pub struct BufferPoolGuard<'a>(&'a BufferPool, SemaphoreGuard<'a>, Vec<u8>);
 
     impl Deref for BufferPoolGuard<'_> {
         type Target = Vec<u8>;
@@ -481,12 +639,7 @@ pub mod asynchronous {
 
         /// Reset the buffer to the original size.
         pub fn reset(&mut self) {
-            self.2.resize(
-                option_env!("NELI_AUTO_BUFFER_LEN")
-                    .and_then(|s| s.parse::<usize>().ok())
-                    .unwrap_or(MAX_NL_LENGTH),
-                0,
-            );
+            self.2.resize(self.0.buffer_size, 0);
         }
     }
 
@@ -496,17 +649,12 @@ pub mod asynchronous {
                 let mut vec = Vec::new();
                 swap(&mut self.2, &mut vec);
                 let mut pool = self.0.pool.lock();
-                vec.resize(
-                    option_env!("NELI_AUTO_BUFFER_LEN")
-                        .and_then(|s| s.parse::<usize>().ok())
-                        .unwrap_or(MAX_NL_LENGTH),
-                    0,
-                );
+                vec.resize(self.0.buffer_size, 0);
                 pool.push(vec);
                 trace!(
                     "Semaphore released; current count is {}, max is {}",
-                    self.0.max - self.0.semaphore.available_permits(),
-                    self.0.semaphore.available_permits()
+                    self.0.max - pool.len(),
+                    self.0.max
                 );
             }
         }
@@ -516,46 +664,42 @@ pub mod asynchronous {
     /// truncation.
     pub struct BufferPool {
         pool: Mutex<Vec<Vec<u8>>>,
+        buffer_size: usize,
         max: usize,
         semaphore: Semaphore,
     }
 
     impl Default for BufferPool {
         fn default() -> Self {
-            let max_parallel = option_env!("NELI_MAX_PARALLEL_READ_OPS")
-                .and_then(|s| s.parse::<usize>().ok())
-                .unwrap_or(3);
-            let buffer_size = option_env!("NELI_AUTO_BUFFER_LEN")
-                .and_then(|s| s.parse::<usize>().ok())
-                .unwrap_or(MAX_NL_LENGTH);
+            BufferPool::new(&BufferPoolConfig::default())
+        }
+    }
 
+    impl BufferPool {
+        /// Create a pool with the buffers described by `config`.
+        pub fn new(config: &BufferPoolConfig) -> Self {
             BufferPool {
                 pool: Mutex::new(
-                    (0..max_parallel)
-                        .map(|_| vec![0; buffer_size])
+                    (0..config.buffers)
+                        .map(|_| vec![0; config.buffer_size])
                         .collect::<Vec<_>>(),
                 ),
-                max: max_parallel,
-                semaphore: Semaphore::new(max_parallel),
+                buffer_size: config.buffer_size,
+                max: config.buffers,
+                semaphore: Semaphore::new(config.buffers),
             }
         }
-    }
 
-    impl BufferPool {
         /// Acquire a buffer for use.
         ///
         /// This method is backed by a semaphore.
         pub async fn acquire(&self) -> BufferPoolGuard<'_> {
-            let permit = self
-                .semaphore
-                .acquire()
-                .await
-                .expect("Semaphore is never closed");
+            let permit = self.semaphore.acquire().await;
             let mut pool = self.pool.lock();
             trace!(
                 "Semaphore acquired; current count is {}, available is {}",
-                self.max - self.semaphore.available_permits(),
-                self.semaphore.available_permits(),
+                self.max - pool.len() + 1,
+                pool.len() - 1,
             );
             BufferPoolGuard(
                 self,
@@ -616,6 +760,47 @@ mod test {
         assert_eq!(bit_array.to_vec(), vec![4, 36, 68]);
     }
 
+    #[test]
+    fn test_buffer_pool_config() {
+        setup();
+
+        let config = BufferPoolConfig::default();
+        assert_eq!(*config.buffers(), default_buffers());
+        assert_eq!(*config.buffer_size(), default_buffer_size());
+        assert_eq!(*config.growth(), BufferGrowth::Fixed);
+        assert_eq!(BufferPoolConfigBuilder::default().build().unwrap(), config);
+        assert!(BufferPoolConfigBuilder::default()
+            .buffers(0)
+            .build()
+            .is_err());
+        assert!(BufferPoolConfigBuilder::default()
+            .buffer_size(0)
+            .build()
+            .is_err());
+    }
+
+    #[cfg(any(feature = "sync", feature = "async-core"))]
+    #[test]
+    fn test_send_buffer() {
+        setup();
+
+        let mut buffer = SendBuffer::take();
+        buffer.extend_from_slice(&[1; 64]);
+        let capacity = buffer.capacity();
+        drop(buffer);
+        let buffer = SendBuffer::take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+
+        let mut large = SendBuffer::take();
+        large.resize(MAX_RETAINED_SEND_BUFFER * 4, 0);
+        drop(large);
+        drop(buffer);
+        let buffer = SendBuffer::take();
+        assert!(buffer.capacity() >= capacity);
+        assert!(buffer.capacity() <= MAX_RETAINED_SEND_BUFFER);
+    }
+
     #[test]
     fn test_groups() {
         setup();